@@ -0,0 +1,549 @@
+use std::ops::Range;
+
+use crate::css::{self, Styles};
+
+/// User-agent defaults, applied before any page stylesheet.
+pub const DEFAULT_STYLESHEET: &str = r#"
+a:link { color: #0055ff; text-decoration: underline; }
+a:visited { color: #551a8b; text-decoration: underline; }
+center { text-align: center; }
+pre { white-space: pre; font-family: monospace; }
+code { font-family: monospace; }
+mark { background-color: yellow; color: black; }
+sub { vertical-align: sub; font-size: smaller; }
+sup { vertical-align: super; font-size: smaller; }
+address { display: block; }
+article { display: block; }
+aside { display: block; }
+dd { display: block; }
+dl { display: block; }
+dt { display: block; }
+figcaption { display: block; }
+figure { display: block; }
+footer { display: block; }
+header { display: block; }
+hgroup { display: block; }
+main { display: block; }
+nav { display: block; }
+section { display: block; }
+"#;
+
+/// Defaults swapped in for dark mode, over `DEFAULT_STYLESHEET`.
+pub const DARK_STYLESHEET: &str = r#"
+body { color: #e8e8e8; background-color: #1e1e1e; }
+a:link { color: #8ab4f8; }
+a:visited { color: #c58af9; }
+"#;
+
+/// Whether pages are shown light on dark.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DarkMode {
+    #[default]
+    Off,
+    /// Dark defaults, which colors the page sets for itself still override.
+    On,
+    /// Dark colors over whatever the page asks for.
+    Forced,
+}
+
+/// An element's name and attributes, as in a `NodeTrace`.
+pub type Element = (String, Vec<(String, String)>);
+
+pub struct Cascade {
+    /// Stylesheets in ascending priority.
+    sheets: Vec<Styles>,
+    /// How many of `sheets`, from the lowest, are user-agent defaults.
+    user_agent_sheets: usize,
+    /// How many of `sheets`, after the user-agent ones, came from the page.
+    page_sheets: usize,
+    /// Viewport width `@media` queries are evaluated against.
+    width: f32,
+}
+
+impl Cascade {
+    /// Layers the page's `<style>` sheets over the default stylesheet, and the reader's own
+    /// stylesheet, if any, over both. In forced dark mode the dark stylesheet goes over them all.
+    /// The sheets the page links to come before its `<style>`s, as they usually do in `<head>`.
+    pub fn new(
+        page_styles: &[String],
+        linked_styles: &[Styles],
+        user_styles: Option<&Styles>,
+        dark_mode: DarkMode,
+        width: f32,
+    ) -> Self {
+        let dark = || css::parse_css(DARK_STYLESHEET.to_string()).unwrap();
+        let mut sheets = vec![css::parse_css(DEFAULT_STYLESHEET.to_string()).unwrap()];
+        if dark_mode == DarkMode::On {
+            sheets.push(dark());
+        }
+        let user_agent_sheets = sheets.len();
+
+        sheets.extend(linked_styles.iter().cloned());
+        for style in page_styles {
+            match css::parse_css(style.clone()) {
+                Ok(styles) => sheets.push(styles),
+                Err(err) => eprintln!("Ignoring stylesheet: {:#}", err),
+            }
+        }
+        let page_sheets = sheets.len() - user_agent_sheets;
+        sheets.extend(user_styles.cloned());
+        if dark_mode == DarkMode::Forced {
+            sheets.push(dark());
+        }
+
+        Cascade {
+            sheets,
+            user_agent_sheets,
+            page_sheets,
+            width,
+        }
+    }
+
+    /// Looks up `property` for the last element of `path`, which is in the given pseudo-classes
+    /// (e.g. `visited`). `path` is the element's name and attributes preceded by its ancestors',
+    /// outermost first, as in a `NodeTrace`. Later sheets win; among the page's sheets the more
+    /// specific selector wins, and then the later rule.
+    pub fn get(&self, path: &[Element], pseudo_classes: &[&str], property: &str) -> Option<&str> {
+        self.get_in(0..self.sheets.len(), path, pseudo_classes, property)
+    }
+
+    /// Like `get`, but only from the page's and the reader's stylesheets.
+    pub fn get_author(
+        &self,
+        path: &[Element],
+        pseudo_classes: &[&str],
+        property: &str,
+    ) -> Option<&str> {
+        self.get_in(
+            self.user_agent_sheets..self.sheets.len(),
+            path,
+            pseudo_classes,
+            property,
+        )
+    }
+
+    /// Like `get`, but only from the user-agent defaults.
+    pub fn get_user_agent(
+        &self,
+        path: &[Element],
+        pseudo_classes: &[&str],
+        property: &str,
+    ) -> Option<&str> {
+        self.get_in(0..self.user_agent_sheets, path, pseudo_classes, property)
+    }
+
+    /// Where the sheet at `index` stands in the cascade. The page's sheets share one layer, so
+    /// specificity decides between them; every other sheet overrides the ones below it outright.
+    fn layer(&self, index: usize) -> usize {
+        if index >= self.user_agent_sheets && index < self.user_agent_sheets + self.page_sheets {
+            self.user_agent_sheets
+        } else {
+            index
+        }
+    }
+
+    fn get_in(
+        &self,
+        sheets: Range<usize>,
+        path: &[Element],
+        pseudo_classes: &[&str],
+        property: &str,
+    ) -> Option<&str> {
+        sheets
+            .flat_map(|index| {
+                self.sheets[index]
+                    .applicable(self.width)
+                    .into_iter()
+                    .map(move |style| (index, style))
+            })
+            .filter_map(|(index, style)| {
+                let selector = style.selector.as_deref()?;
+                matches_path(selector, path, pseudo_classes)
+                    .then(|| ((self.layer(index), specificity(selector)), style))
+            })
+            .filter_map(|(rank, style)| style.get(property).map(|value| (rank, value)))
+            .enumerate()
+            .max_by_key(|&(order, (rank, _))| (rank, order))
+            .map(|(_, (_, value))| value)
+    }
+}
+
+/// How specific a selector is: its ids, then its classes and pseudo-classes, then its tag
+/// names, summed over every compound. `*` counts for nothing.
+pub fn specificity(selector: &str) -> (u32, u32, u32) {
+    selector
+        .split_whitespace()
+        .fold((0, 0, 0), |(ids, classes, tags), compound| {
+            let (compound, pseudo_class) = match compound.split_once(':') {
+                Some((compound, _)) => (compound, 1),
+                None => (compound, 0),
+            };
+            let count = |kind| compound.matches(kind).count() as u32;
+            let tag = compound.split(['.', '#']).next().unwrap_or("");
+            let tag = u32::from(!tag.is_empty() && tag != "*");
+
+            (
+                ids + count('#'),
+                classes + count('.') + pseudo_class,
+                tags + tag,
+            )
+        })
+}
+
+/// Whether a selector such as `p`, `div .note` or `ul li a:visited` matches the last element of
+/// `path`. Each space-separated part but the last has to match some ancestor, in order.
+fn matches_path(selector: &str, path: &[Element], pseudo_classes: &[&str]) -> bool {
+    let mut compounds = selector.split_whitespace().rev();
+    let (Some(subject), Some(((name, attributes), mut ancestors))) =
+        (compounds.next(), path.split_last())
+    else {
+        return false;
+    };
+    if !matches(subject, name, attributes, pseudo_classes) {
+        return false;
+    }
+
+    // the nearest ancestor that matches is as good as any further out
+    compounds.all(|compound| {
+        match ancestors
+            .iter()
+            .rposition(|(name, attributes)| matches(compound, name, attributes, &[]))
+        {
+            Some(index) => {
+                ancestors = &ancestors[..index];
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Whether a selector such as `p`, `.note`, `a.external:visited` or `#top` matches the element.
+/// Every part given must match: the tag name, each class among the space-separated `class`
+/// attribute, the `id` and the pseudo-class.
+fn matches(
+    selector: &str,
+    name: &str,
+    attributes: &[(String, String)],
+    pseudo_classes: &[&str],
+) -> bool {
+    let attribute = |key: &str| {
+        attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map_or("", |(_, value)| value.as_str())
+    };
+
+    let (compound, pseudo_class) = match selector.split_once(':') {
+        Some((compound, pseudo_class)) => (compound, Some(pseudo_class)),
+        None => (selector, None),
+    };
+    if pseudo_class.is_some_and(|pseudo_class| !pseudo_classes.contains(&pseudo_class)) {
+        return false;
+    }
+
+    // the tag comes first, then `.class` and `#id` parts in any order
+    let tag_end = compound.find(['.', '#']).unwrap_or(compound.len());
+    let (tag, mut rest) = compound.split_at(tag_end);
+    if !(tag.is_empty() || tag == "*" || tag == name) {
+        return false;
+    }
+
+    while let Some(kind) = rest.chars().next() {
+        let end = rest[1..].find(['.', '#']).map_or(rest.len(), |end| end + 1);
+        let value = &rest[1..end];
+        let matched = match kind {
+            '.' => attribute("class")
+                .split_whitespace()
+                .any(|class| class == value),
+            _ => attribute("id") == value,
+        };
+        if value.is_empty() || !matched {
+            return false;
+        }
+        rest = &rest[end..];
+    }
+
+    !compound.is_empty()
+}
+
+#[cfg(test)]
+fn element(name: &str) -> Vec<Element> {
+    vec![(name.to_string(), vec![])]
+}
+
+#[test]
+fn test_cascade_link_color() {
+    let cases = vec![
+        (vec![], &["link"][..], Some("#0055ff")),
+        (vec![], &[][..], None),
+        (vec![], &["visited"][..], Some("#551a8b")),
+        (vec!["a { color: #ff0000; }"], &[][..], Some("#ff0000")),
+        (
+            vec!["a { color: #ff0000; }"],
+            &["visited"][..],
+            Some("#ff0000"),
+        ),
+        (
+            vec!["p { color: #ff0000; }"],
+            &["link"][..],
+            Some("#0055ff"),
+        ),
+        (
+            vec!["@media (max-width: 500px) { a { color: #ff0000; } }"],
+            &["link"][..],
+            Some("#0055ff"),
+        ),
+        (
+            vec!["@media (min-width: 500px) { a { color: #ff0000; } }"],
+            &[][..],
+            Some("#ff0000"),
+        ),
+    ];
+
+    for (page_styles, pseudo_classes, want) in cases {
+        let page_styles = page_styles
+            .into_iter()
+            .map(|style| style.to_string())
+            .collect::<Vec<_>>();
+        let cascade = Cascade::new(&page_styles, &[], None, DarkMode::Off, 800.0);
+
+        assert_eq!(
+            cascade.get(&element("a"), pseudo_classes, "color"),
+            want,
+            "{:?} {:?}",
+            page_styles,
+            pseudo_classes
+        );
+    }
+}
+
+#[test]
+fn test_cascade_user_styles() {
+    let user_styles =
+        css::parse_css("a:link { color: #ffffff; } body { color: #eeeeee; }".to_string()).unwrap();
+    let page_styles = vec!["a { color: #ff0000; } body { color: #000000; }".to_string()];
+
+    let cascade = Cascade::new(&page_styles, &[], Some(&user_styles), DarkMode::Off, 800.0);
+    assert_eq!(
+        cascade.get(&element("a"), &["link"], "color"),
+        Some("#ffffff")
+    );
+    assert_eq!(cascade.get(&element("a"), &[], "color"), Some("#ff0000"));
+    assert_eq!(cascade.get(&element("body"), &[], "color"), Some("#eeeeee"));
+}
+
+#[test]
+fn test_cascade_linked_styles() {
+    let page_styles = vec!["a { color: #ff0000; }".to_string()];
+    // a linked sheet is the page's too, below its <style>s
+    let linked =
+        vec![css::parse_css("a { color: #00ff00; } p { color: #0000ff; }".to_string()).unwrap()];
+    let cascade = Cascade::new(&page_styles, &linked, None, DarkMode::Off, 800.0);
+    assert_eq!(cascade.get(&element("a"), &[], "color"), Some("#ff0000"));
+    assert_eq!(cascade.get(&element("p"), &[], "color"), Some("#0000ff"));
+}
+
+#[test]
+fn test_cascade_dark_mode() {
+    let page_styles = vec!["body { background-color: #ffffcc; }".to_string()];
+
+    let cascade = Cascade::new(&[], &[], None, DarkMode::On, 800.0);
+    assert_eq!(cascade.get(&element("body"), &[], "color"), Some("#e8e8e8"));
+    assert_eq!(
+        cascade.get(&element("a"), &["link"], "color"),
+        Some("#8ab4f8")
+    );
+    assert_eq!(cascade.get_author(&element("body"), &[], "color"), None);
+
+    let cascade = Cascade::new(&page_styles, &[], None, DarkMode::On, 800.0);
+    assert_eq!(
+        cascade.get(&element("body"), &[], "background-color"),
+        Some("#ffffcc")
+    );
+
+    let cascade = Cascade::new(&page_styles, &[], None, DarkMode::Forced, 800.0);
+    assert_eq!(
+        cascade.get_author(&element("body"), &[], "background-color"),
+        Some("#1e1e1e")
+    );
+}
+
+#[test]
+fn test_matches() {
+    let attributes = vec![
+        ("class".to_string(), "note  hl".to_string()),
+        ("id".to_string(), "top".to_string()),
+    ];
+
+    let cases = vec![
+        ("p", true),
+        ("div", false),
+        (".hl", true),
+        (".note", true),
+        (".no", false),
+        ("p.hl", true),
+        ("div.hl", false),
+        (".hl.note", true),
+        (".hl.missing", false),
+        ("#top", true),
+        ("#bottom", false),
+        ("p#top.hl", true),
+        ("*", true),
+        (".hl:visited", true),
+        (".hl:link", false),
+        (".", false),
+        ("", false),
+    ];
+
+    for (selector, want) in cases {
+        assert_eq!(
+            matches(selector, "p", &attributes, &["visited"]),
+            want,
+            "{}",
+            selector
+        );
+    }
+}
+
+#[test]
+fn test_matches_path() {
+    let trace = |elements: &[(&str, &str)]| {
+        elements
+            .iter()
+            .map(|(name, class)| {
+                let attributes = if class.is_empty() {
+                    vec![]
+                } else {
+                    vec![("class".to_string(), class.to_string())]
+                };
+                (name.to_string(), attributes)
+            })
+            .collect::<Vec<Element>>()
+    };
+    let in_div = trace(&[
+        ("html", ""),
+        ("body", ""),
+        ("div", "menu"),
+        ("p", ""),
+        ("a", ""),
+    ]);
+    let outside = trace(&[("html", ""), ("body", ""), ("a", "")]);
+
+    let cases = vec![
+        ("a", true, true),
+        ("div a", true, false),
+        ("body a", true, true),
+        ("div p a", true, false),
+        ("p div a", false, false),
+        (".menu a", true, false),
+        ("html .menu p a", true, false),
+        ("div span a", false, false),
+        ("div p", false, false),
+        ("div a:link", true, false),
+    ];
+
+    for (selector, want_in_div, want_outside) in cases {
+        assert_eq!(
+            matches_path(selector, &in_div, &["link"]),
+            want_in_div,
+            "{}",
+            selector
+        );
+        assert_eq!(
+            matches_path(selector, &outside, &["link"]),
+            want_outside,
+            "{}",
+            selector
+        );
+    }
+}
+
+#[test]
+fn test_specificity() {
+    let cases = vec![
+        ("p", (0, 0, 1)),
+        ("*", (0, 0, 0)),
+        (".note", (0, 1, 0)),
+        ("#top", (1, 0, 0)),
+        ("a:link", (0, 1, 1)),
+        ("p#top.hl.note", (1, 2, 1)),
+        ("div .menu a:visited", (0, 2, 2)),
+    ];
+    for (selector, want) in cases {
+        assert_eq!(specificity(selector), want, "{}", selector);
+    }
+
+    assert!(specificity("#top") > specificity(".note.hl.extra"));
+    assert!(specificity(".note") > specificity("html body div p"));
+}
+
+#[test]
+fn test_cascade_specificity() {
+    let mut path = element("p");
+    path[0].1 = vec![
+        ("class".to_string(), "note".to_string()),
+        ("id".to_string(), "top".to_string()),
+    ];
+    let get = |page_styles: &[&str]| {
+        let page_styles = page_styles
+            .iter()
+            .map(|style| style.to_string())
+            .collect::<Vec<_>>();
+        let cascade = Cascade::new(&page_styles, &[], None, DarkMode::Off, 800.0);
+        cascade.get(&path, &[], "color").map(str::to_string)
+    };
+
+    let cases = vec![
+        (
+            vec!["#top { color: red; } .note { color: blue; } p { color: green; }"],
+            "red",
+        ),
+        (vec!["p { color: green; } .note { color: blue; }"], "blue"),
+        (vec![".note { color: blue; } p { color: green; }"], "blue"),
+        (
+            vec![".note { color: blue; }", "p { color: green; }"],
+            "blue",
+        ),
+        (
+            vec![".note { color: blue; } p.note { color: green; }"],
+            "green",
+        ),
+        (
+            vec!["p.note { color: green; } .note { color: blue; }"],
+            "green",
+        ),
+        (
+            vec![".note { color: blue; } .note { color: green; }"],
+            "green",
+        ),
+    ];
+    for (page_styles, want) in cases {
+        assert_eq!(
+            get(&page_styles).as_deref(),
+            Some(want),
+            "{:?}",
+            page_styles
+        );
+    }
+
+    // an inline style, applied over the cascade, beats the page's rules, an id's included
+    let html = crate::html::parse_html(
+        r##"<html><head><style>#top { color: #ff0000; } .note { color: #0000ff; }</style></head><body><p id="top" class="note" style="color: #008000">text</p></body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+    let options = crate::render::RenderOptions::default();
+    let boxes = crate::render::layout(&html, 800, 600, &options).boxes;
+    let color = boxes
+        .iter()
+        .find(|layout_box| layout_box.text.as_deref() == Some("text"))
+        .and_then(|layout_box| layout_box.color);
+    assert_eq!(color, Some(0x008000));
+
+    // the reader's stylesheet still wins over the page however specific the page's rule
+    let user_styles = css::parse_css("p { color: white; }".to_string()).unwrap();
+    let page_styles = vec!["#top { color: red; }".to_string()];
+    let cascade = Cascade::new(&page_styles, &[], Some(&user_styles), DarkMode::Off, 800.0);
+    assert_eq!(cascade.get(&path, &[], "color"), Some("white"));
+}
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use anyhow::bail;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -51,17 +53,106 @@ fn tokenize_css(str: String) -> Vec<Token> {
     tokens
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Styles {
     pub styles: Vec<Style>,
+    /// `@media` blocks, whose styles only apply while their query matches.
+    pub media: Vec<MediaBlock>,
+}
+
+impl Styles {
+    /// Styles in effect at the given viewport width: the unconditional ones first, then those of
+    /// every matching `@media` block.
+    pub fn applicable(&self, width: f32) -> Vec<&Style> {
+        let mut styles = self.styles.iter().collect::<Vec<_>>();
+
+        for block in &self.media {
+            if block.query.matches(width) {
+                styles.extend(block.styles.applicable(width));
+            }
+        }
+
+        styles
+    }
+
+    /// The value of `property` in the first of the unconditional styles declaring it, whatever
+    /// its selector.
+    pub fn get_first(&self, property: &str) -> Option<&str> {
+        self.styles.iter().find_map(|style| style.get(property))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaBlock {
+    pub query: MediaQuery,
+    pub styles: Styles,
+}
+
+/// A query such as `screen and (max-width: 500px)`. Only `min-width` and `max-width` in px are
+/// understood; a query using any other feature never matches.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MediaQuery {
+    pub media_type: Option<String>,
+    pub features: Vec<(String, String)>,
+}
+
+impl MediaQuery {
+    fn parse(query: &str) -> MediaQuery {
+        let mut media_type = None;
+        let mut features = vec![];
+
+        for part in query.split(" and ").map(str::trim) {
+            match part
+                .strip_prefix('(')
+                .and_then(|part| part.strip_suffix(')'))
+            {
+                Some(feature) => {
+                    let (name, value) = feature.split_once(':').unwrap_or((feature, ""));
+                    features.push((name.trim().to_string(), value.trim().to_string()));
+                }
+                None => media_type = Some(part.to_string()),
+            }
+        }
+
+        MediaQuery {
+            media_type,
+            features,
+        }
+    }
+
+    pub fn matches(&self, width: f32) -> bool {
+        let media_type = self.media_type.as_deref().unwrap_or("all");
+        if media_type != "all" && media_type != "screen" {
+            return false;
+        }
+
+        self.features.iter().all(
+            |(name, value)| match (name.as_str(), parse_length(value, 0.0)) {
+                ("min-width", Some(min)) => width >= min,
+                ("max-width", Some(max)) => width <= max,
+                _ => false,
+            },
+        )
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Style {
     pub selector: Option<String>,
     pub rules: Vec<(String, String)>,
 }
 
+impl Style {
+    /// The value declared for `property`, the last one if it is declared more than once.
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(key, _)| key == property)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
 struct CssParser {
     tokens: Vec<Token>,
     position: usize,
@@ -110,19 +201,69 @@ impl CssParser {
         }
     }
 
+    /// Parses styles up to the end of input or the `}` closing an enclosing block.
     fn styles(&mut self) -> Result<Styles, anyhow::Error> {
         let mut styles = vec![];
+        let mut media = vec![];
 
-        while self.position < self.tokens.len() {
-            styles.push(self.style()?);
+        while let Some(token) = self.peek() {
+            match token {
+                Token::RBrace => break,
+                Token::Ident(ident) if ident == "@media" => media.push(self.media()?),
+                _ => styles.push(self.style()?),
+            }
         }
 
-        Ok(Styles { styles })
+        Ok(Styles { styles, media })
+    }
+
+    fn media(&mut self) -> Result<MediaBlock, anyhow::Error> {
+        self.expect(Token::Ident("@media".to_string()))?;
+
+        let mut query = String::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::LBrace => break,
+                Token::Ident(ident) => {
+                    if !query.is_empty() && !query.ends_with(':') {
+                        query.push(' ');
+                    }
+                    query.push_str(ident);
+                }
+                Token::Colon => query.push(':'),
+                _ => bail!("Unexpected token in media query: {:?}", token),
+            }
+            self.position += 1;
+        }
+
+        self.expect(Token::LBrace)?;
+        let styles = self.styles()?;
+        self.expect(Token::RBrace)?;
+
+        Ok(MediaBlock {
+            query: MediaQuery::parse(&query),
+            styles,
+        })
     }
 
     fn style(&mut self) -> Result<Style, anyhow::Error> {
         let prev_position = self.position;
-        let ident = self.expect_ident()?;
+        let mut ident = self.expect_ident()?;
+
+        // a descendant selector such as `div p {`
+        while let Some(Token::Ident(next)) = self.peek() {
+            ident = format!("{} {}", ident, next);
+            self.position += 1;
+        }
+
+        // a pseudo-class selector such as `a:visited {`, as opposed to a `key: value` rule
+        if let Some([Token::Colon, Token::Ident(pseudo_class), Token::LBrace]) =
+            self.tokens.get(self.position..self.position + 3)
+        {
+            ident = format!("{}:{}", ident, pseudo_class);
+            self.position += 2;
+        }
+
         match self.peek() {
             Some(Token::LBrace) => {
                 self.expect(Token::LBrace)?;
@@ -151,22 +292,402 @@ impl CssParser {
         while let Some(Token::Ident(ident)) = self.peek() {
             let ident = self.expect_ident()?;
             self.expect(Token::Colon)?;
-            let value = self.expect_ident()?;
+            let mut values = vec![self.expect_ident()?];
+            while let Some(Token::Ident(_)) = self.peek() {
+                values.push(self.expect_ident()?);
+            }
             self.expect(Token::SemiColon)?;
-            rules.push((ident, value));
+            rules.push((ident, values.join(" ")));
         }
 
-        Ok(rules)
+        Ok(expand_shorthand(rules))
     }
 }
 
 pub fn parse_css(str: String) -> Result<Styles, anyhow::Error> {
-    println!("Parsing CSS: {}", str);
+    eprintln!("Parsing CSS: {}", str);
     let tokens = tokenize_css(str);
-    println!("Tokens: {:?}", tokens);
+    eprintln!("Tokens: {:?}", tokens);
     let mut parser = CssParser::new(tokens);
     let element = parser.styles()?;
-    println!("Element: {:?}", element);
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected token: {:?} ({})", token, parser.position);
+    }
+    eprintln!("Element: {:?}", element);
 
     Ok(element)
 }
+
+#[test]
+fn test_parse_css() {
+    let cases = vec![
+        (
+            "display: flex; gap: 8px;",
+            vec![Style {
+                selector: None,
+                rules: vec![
+                    ("display".to_string(), "flex".to_string()),
+                    ("gap".to_string(), "8px".to_string()),
+                ],
+            }],
+        ),
+        (
+            "div  p a:visited { color: #551a8b; }",
+            vec![Style {
+                selector: Some("div p a:visited".to_string()),
+                rules: vec![("color".to_string(), "#551a8b".to_string())],
+            }],
+        ),
+        (
+            "a { color: #0055ff; } a:visited { color: #551a8b; }",
+            vec![
+                Style {
+                    selector: Some("a".to_string()),
+                    rules: vec![("color".to_string(), "#0055ff".to_string())],
+                },
+                Style {
+                    selector: Some("a:visited".to_string()),
+                    rules: vec![("color".to_string(), "#551a8b".to_string())],
+                },
+            ],
+        ),
+    ];
+
+    for (str, want) in cases {
+        assert_eq!(parse_css(str.to_string()).unwrap().styles, want, "{}", str);
+    }
+}
+
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// Expands `margin`, `padding`, `border` and `background` into their longhand properties so
+/// consumers only ever look up longhands. Other rules pass through unchanged.
+pub fn expand_shorthand(rules: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut expanded = vec![];
+
+    for (key, value) in rules {
+        let values = value.split_whitespace().collect::<Vec<_>>();
+
+        match key.as_str() {
+            "margin" | "padding" => {
+                // top, right, bottom, left per the 1/2/3/4-value rules
+                let sides = match values.as_slice() {
+                    [all] => [*all, *all, *all, *all],
+                    [vertical, horizontal] => [*vertical, *horizontal, *vertical, *horizontal],
+                    [top, horizontal, bottom] => [*top, *horizontal, *bottom, *horizontal],
+                    [top, right, bottom, left] => [*top, *right, *bottom, *left],
+                    _ => {
+                        expanded.push((key, value));
+                        continue;
+                    }
+                };
+
+                for (side, value) in ["top", "right", "bottom", "left"].iter().zip(sides) {
+                    expanded.push((format!("{}-{}", key, side), value.to_string()));
+                }
+            }
+            "border" => {
+                for value in values {
+                    let property = if BORDER_STYLES.contains(&value) {
+                        "border-style"
+                    } else if ["thin", "medium", "thick"].contains(&value)
+                        || parse_length(value, 0.0).is_some()
+                    {
+                        "border-width"
+                    } else {
+                        "border-color"
+                    };
+
+                    expanded.push((property.to_string(), value.to_string()));
+                }
+            }
+            // only the color of a background is drawn, so the rest of it is dropped
+            "background" => match values.iter().find(|value| parse_color(value).is_some()) {
+                Some(color) => expanded.push(("background-color".to_string(), color.to_string())),
+                None => expanded.push((key, value)),
+            },
+            _ => expanded.push((key, value)),
+        }
+    }
+
+    expanded
+}
+
+#[test]
+fn test_expand_shorthand() {
+    let sides = |key: &str, values: [&str; 4]| {
+        ["top", "right", "bottom", "left"]
+            .iter()
+            .zip(values)
+            .map(|(side, value)| (format!("{}-{}", key, side), value.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let cases = vec![
+        (
+            "margin: 1px;",
+            sides("margin", ["1px", "1px", "1px", "1px"]),
+        ),
+        (
+            "margin: 1px 2px;",
+            sides("margin", ["1px", "2px", "1px", "2px"]),
+        ),
+        (
+            "padding: 1px 2px 3px;",
+            sides("padding", ["1px", "2px", "3px", "2px"]),
+        ),
+        (
+            "padding: 1px 2px 3px 4px;",
+            sides("padding", ["1px", "2px", "3px", "4px"]),
+        ),
+        (
+            "border: 1px solid red;",
+            vec![
+                ("border-width".to_string(), "1px".to_string()),
+                ("border-style".to_string(), "solid".to_string()),
+                ("border-color".to_string(), "red".to_string()),
+            ],
+        ),
+        (
+            "border: dashed #ff0000;",
+            vec![
+                ("border-style".to_string(), "dashed".to_string()),
+                ("border-color".to_string(), "#ff0000".to_string()),
+            ],
+        ),
+        (
+            "background: #808080 no-repeat;",
+            vec![("background-color".to_string(), "#808080".to_string())],
+        ),
+        (
+            "color: red; margin: 1px 2px 3px 4px 5px;",
+            vec![
+                ("color".to_string(), "red".to_string()),
+                ("margin".to_string(), "1px 2px 3px 4px 5px".to_string()),
+            ],
+        ),
+    ];
+
+    for (str, want) in cases {
+        assert_eq!(
+            parse_css(str.to_string()).unwrap().styles[0].rules,
+            want,
+            "{}",
+            str
+        );
+    }
+}
+
+#[test]
+fn test_style_get() {
+    let styles = parse_css(
+        "p { color: red; display: block; color: blue; } div { display: flex; gap: 2px; } @media (min-width: 0px) { p { width: 10px; } }"
+            .to_string(),
+    )
+    .unwrap();
+
+    // the last declaration in a rule list wins
+    assert_eq!(styles.styles[0].get("color"), Some("blue"));
+    assert_eq!(styles.styles[0].get("display"), Some("block"));
+    assert_eq!(styles.styles[0].get("gap"), None);
+
+    // and the first style declaring it across the sheet, leaving out @media blocks
+    assert_eq!(styles.get_first("display"), Some("block"));
+    assert_eq!(styles.get_first("gap"), Some("2px"));
+    assert_eq!(styles.get_first("width"), None);
+}
+
+/// Resolves a `px` (or unitless) or `%` length. Percentages are relative to `percent_base`.
+pub fn parse_length(value: &str, percent_base: f32) -> Option<f32> {
+    if let Some(percent) = value.strip_suffix('%') {
+        percent
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .map(|p| p * percent_base / 100.0)
+    } else {
+        value.trim_end_matches("px").trim().parse::<f32>().ok()
+    }
+}
+
+#[test]
+fn test_media_query() {
+    let styles = parse_css(
+        "a { color: #0055ff; } @media (max-width:500px) { a { color: #ff0000; } } @media screen and (min-width: 300px) and (max-width: 400px) { a { color: #00ff00; } }"
+            .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        styles.media[1].query,
+        MediaQuery {
+            media_type: Some("screen".to_string()),
+            features: vec![
+                ("min-width".to_string(), "300px".to_string()),
+                ("max-width".to_string(), "400px".to_string()),
+            ],
+        }
+    );
+
+    let colors = |width: f32| {
+        styles
+            .applicable(width)
+            .iter()
+            .map(|style| style.get("color").unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(colors(800.0), vec!["#0055ff"]);
+    assert_eq!(colors(500.0), vec!["#0055ff", "#ff0000"]);
+    assert_eq!(colors(350.0), vec!["#0055ff", "#ff0000", "#00ff00"]);
+
+    let print = parse_css("@media print { a { color: #000000; } }".to_string()).unwrap();
+    assert!(print.applicable(800.0).is_empty());
+}
+
+#[test]
+fn test_parse_length() {
+    let cases = vec![
+        ("100px", Some(100.0)),
+        ("12", Some(12.0)),
+        ("50%", Some(200.0)),
+        ("auto", None),
+    ];
+
+    for (value, want) in cases {
+        assert_eq!(parse_length(value, 400.0), want, "{}", value);
+    }
+}
+
+/// The 16 basic color keywords, which is what legacy `color` attributes use.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("black", 0x000000),
+    ("silver", 0xc0c0c0),
+    ("gray", 0x808080),
+    ("white", 0xffffff),
+    ("maroon", 0x800000),
+    ("red", 0xff0000),
+    ("purple", 0x800080),
+    ("fuchsia", 0xff00ff),
+    ("green", 0x008000),
+    ("lime", 0x00ff00),
+    ("olive", 0x808000),
+    ("yellow", 0xffff00),
+    ("navy", 0x000080),
+    ("blue", 0x0000ff),
+    ("teal", 0x008080),
+    ("aqua", 0x00ffff),
+];
+
+/// Resolves `#rrggbb`, `#rgb` or a basic color keyword to `0xrrggbb`.
+pub fn parse_color(value: &str) -> Option<u32> {
+    let value = value.trim();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let color = u32::from_str_radix(hex, 16).ok()?;
+        return match hex.len() {
+            6 => Some(color),
+            // each digit is doubled: #f80 is #ff8800
+            3 => Some(
+                (0..3)
+                    .map(|i| (color >> (8 - i * 4)) & 0xf)
+                    .fold(0, |rgb, digit| (rgb << 8) | (digit * 0x11)),
+            ),
+            _ => None,
+        };
+    }
+
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+        .map(|(_, color)| *color)
+}
+
+#[test]
+fn test_parse_color() {
+    let cases = vec![
+        ("#0055ff", Some(0x0055ff)),
+        ("#f80", Some(0xff8800)),
+        (" Red ", Some(0xff0000)),
+        ("navy", Some(0x000080)),
+        ("#12345", None),
+        ("#gggggg", None),
+        ("bogus", None),
+    ];
+
+    for (value, want) in cases {
+        assert_eq!(parse_color(value), want, "{}", value);
+    }
+}
+
+/// How many stylesheets a `StylesheetCache` holds by default.
+pub const STYLESHEET_CACHE_CAPACITY: usize = 32;
+
+/// Parsed stylesheets by URL, so a sheet shared by the pages of a site is fetched and parsed
+/// once. Beyond its capacity, the least recently used sheet is dropped.
+pub struct StylesheetCache {
+    /// Least recently used first.
+    entries: VecDeque<(String, Styles)>,
+    capacity: usize,
+    /// Lookups of a URL that wasn't cached.
+    pub misses: usize,
+}
+
+impl Default for StylesheetCache {
+    fn default() -> Self {
+        StylesheetCache::new(STYLESHEET_CACHE_CAPACITY)
+    }
+}
+
+impl StylesheetCache {
+    pub fn new(capacity: usize) -> Self {
+        StylesheetCache {
+            entries: VecDeque::new(),
+            capacity,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<Styles> {
+        let Some(index) = self.entries.iter().position(|(key, _)| key == url) else {
+            self.misses += 1;
+            return None;
+        };
+
+        let entry = self.entries.remove(index)?;
+        let styles = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(styles)
+    }
+
+    pub fn insert(&mut self, url: String, styles: Styles) {
+        self.entries.retain(|(key, _)| *key != url);
+        self.entries.push_back((url, styles));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+#[test]
+fn test_stylesheet_cache() {
+    let sheet = |color: &str| parse_css(format!("p {{ color: {}; }}", color)).unwrap();
+    let mut cache = StylesheetCache::new(2);
+    assert_eq!(cache.get("a.css"), None);
+
+    cache.insert("a.css".to_string(), sheet("red"));
+    cache.insert("b.css".to_string(), sheet("green"));
+    assert_eq!(cache.get("a.css"), Some(sheet("red")));
+
+    // b.css is now the one used longest ago
+    cache.insert("c.css".to_string(), sheet("blue"));
+    assert_eq!(cache.get("b.css"), None);
+    assert_eq!(cache.get("a.css"), Some(sheet("red")));
+    assert_eq!(cache.get("c.css"), Some(sheet("blue")));
+    assert_eq!(cache.misses, 2);
+}
@@ -1,4 +1,5 @@
-use std::sync::OnceLock;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
 
 use skia_safe::{FontMgr, FontStyle, Typeface};
 
@@ -7,7 +8,7 @@ pub fn default_typeface() -> Typeface {
         .get_or_init(|| {
             let font_mgr = FontMgr::new();
             for family in font_mgr.family_names() {
-                println!("Family: {}", family);
+                eprintln!("Family: {}", family);
             }
 
             font_mgr
@@ -18,3 +19,104 @@ pub fn default_typeface() -> Typeface {
 }
 
 static DEFAULT_TYPEFACE: OnceLock<Typeface> = OnceLock::new();
+
+/// Picks a typeface suited to a BCP 47 language tag such as the one in `<html lang>`, falling
+/// back to the default typeface when the tag is unknown or the family isn't installed.
+pub fn typeface_for_lang(lang: Option<&str>) -> Typeface {
+    let Some(family) = lang.and_then(font_family_for_lang) else {
+        return default_typeface();
+    };
+
+    TYPEFACES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+        .entry(family)
+        .or_insert_with(|| {
+            FontMgr::new()
+                .match_family_style(family, FontStyle::default())
+                .unwrap_or_else(default_typeface)
+        })
+        .clone()
+}
+
+/// Picks the first installed family in a CSS `font-family` list such as `Arial, sans-serif`.
+/// Generic families map onto the bundled Noto fonts.
+pub fn typeface_for_families(families: &str) -> Option<Typeface> {
+    families.split(',').find_map(|family| {
+        let family = family.trim().trim_matches(['"', '\'']);
+        let family = match family.to_ascii_lowercase().as_str() {
+            "sans-serif" | "serif" | "system-ui" => "Noto Sans",
+            "monospace" => "Noto Sans Mono",
+            _ => family,
+        };
+
+        FAMILY_TYPEFACES
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap()
+            .entry(family.to_string())
+            .or_insert_with(|| FontMgr::new().match_family_style(family, FontStyle::default()))
+            .clone()
+    })
+}
+
+static FAMILY_TYPEFACES: OnceLock<Mutex<HashMap<String, Option<Typeface>>>> = OnceLock::new();
+
+/// The bold and/or italic face of `typeface`'s family, or `typeface` itself if the family has
+/// none installed.
+pub fn styled_typeface(typeface: &Typeface, bold: bool, italic: bool) -> Typeface {
+    let style = match (bold, italic) {
+        (false, false) => return typeface.clone(),
+        (true, false) => FontStyle::bold(),
+        (false, true) => FontStyle::italic(),
+        (true, true) => FontStyle::bold_italic(),
+    };
+
+    STYLED_TYPEFACES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+        .entry((typeface.family_name(), bold, italic))
+        .or_insert_with(|| {
+            FontMgr::new()
+                .match_family_style(typeface.family_name(), style)
+                .unwrap_or_else(|| typeface.clone())
+        })
+        .clone()
+}
+
+/// Family name, and whether bold and italic.
+type StyleKey = (String, bool, bool);
+
+static STYLED_TYPEFACES: OnceLock<Mutex<HashMap<StyleKey, Typeface>>> = OnceLock::new();
+
+fn font_family_for_lang(lang: &str) -> Option<&'static str> {
+    let primary = lang.split(['-', '_']).next()?.to_ascii_lowercase();
+
+    match primary.as_str() {
+        "ja" | "zh" | "ko" => Some("Noto Sans CJK JP"),
+        "en" | "de" | "es" | "fr" | "it" | "nl" | "pt" | "sv" | "da" | "no" | "fi" | "pl"
+        | "cs" | "tr" | "vi" | "id" => Some("Noto Sans"),
+        _ => None,
+    }
+}
+
+static TYPEFACES: OnceLock<Mutex<HashMap<&'static str, Typeface>>> = OnceLock::new();
+
+#[test]
+fn test_font_family_for_lang() {
+    let cases = vec![
+        ("en", Some("Noto Sans")),
+        ("en-US", Some("Noto Sans")),
+        ("ja", Some("Noto Sans CJK JP")),
+        ("zh_TW", Some("Noto Sans CJK JP")),
+        ("KO", Some("Noto Sans CJK JP")),
+        ("x-klingon", None),
+        ("", None),
+    ];
+
+    for (lang, want) in cases {
+        assert_eq!(font_family_for_lang(lang), want, "{}", lang);
+    }
+}
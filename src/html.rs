@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Context};
 
@@ -10,6 +11,8 @@ enum Token {
     Equal,
     Text(String),
     QuotedText(String),
+    /// A run of whitespace in content, as written.
+    Whitespace(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -21,6 +24,16 @@ impl NodeTrace {
     }
 }
 
+/// Returned from the enter callback of [`HtmlElement::walk`] to control the traversal.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WalkControl {
+    Continue,
+    /// Prune the subtree: neither the children nor the leave callback of this node are visited.
+    SkipChildren,
+    /// End the whole walk. No further enter or leave callbacks are made, including for ancestors.
+    Stop,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct HtmlElement {
     pub name: String,
@@ -29,7 +42,222 @@ pub struct HtmlElement {
     pub text_node: Option<String>,
 }
 
+/// Metadata declared in `<head>`, extracted before the content is painted.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct HeadMetadata {
+    pub title: Option<String>,
+    pub meta: Vec<Vec<(String, String)>>,
+    pub links: Vec<Vec<(String, String)>>,
+    pub styles: Vec<String>,
+    /// `href` of the first `<base>`, which relative links resolve against instead of the
+    /// document's own URL.
+    pub base: Option<String>,
+}
+
+impl HeadMetadata {
+    /// `href`s of the `<link rel="stylesheet">`s, in document order.
+    pub fn stylesheet_links(&self) -> Vec<&str> {
+        self.links_with_rel("stylesheet").collect()
+    }
+
+    /// `href` of the first `<link rel="icon">`, which the older `rel="shortcut icon"` is too.
+    pub fn icon_link(&self) -> Option<&str> {
+        self.links_with_rel("icon").next()
+    }
+
+    /// `href`s of the `<link>`s whose `rel` lists `rel`, in document order.
+    fn links_with_rel<'a>(&'a self, rel: &'a str) -> impl Iterator<Item = &'a str> {
+        self.links
+            .iter()
+            .filter(move |attributes| {
+                attributes.iter().any(|(key, value)| {
+                    key == "rel"
+                        && value
+                            .split_whitespace()
+                            .any(|token| token.eq_ignore_ascii_case(rel))
+                })
+            })
+            .filter_map(|attributes| {
+                attributes
+                    .iter()
+                    .find(|(key, _)| key == "href")
+                    .map(|(_, href)| href.as_str())
+            })
+    }
+
+    /// Parses `<meta http-equiv="refresh" content="5;url=next.html">` into the delay in seconds
+    /// and the target, which is `None` when the page just reloads itself.
+    pub fn refresh(&self) -> Option<(u64, Option<String>)> {
+        let content = self.meta.iter().find_map(|attributes| {
+            attributes
+                .iter()
+                .any(|(key, value)| {
+                    key.eq_ignore_ascii_case("http-equiv") && value.eq_ignore_ascii_case("refresh")
+                })
+                .then(|| attributes.iter().find(|(key, _)| key == "content"))
+                .flatten()
+                .map(|(_, content)| content.clone())
+        })?;
+
+        let (delay, target) = match content.split_once([';', ',']) {
+            Some((delay, target)) => (delay, Some(target)),
+            None => (content.as_str(), None),
+        };
+        let delay = delay.trim().split('.').next()?.parse::<u64>().ok()?;
+
+        let target = target.and_then(|target| {
+            let target = target.trim();
+            let target = match target.get(..4) {
+                Some(prefix) if prefix.eq_ignore_ascii_case("url=") => &target[4..],
+                _ => target,
+            };
+            let target = target.trim().trim_matches(['\'', '"']);
+
+            (!target.is_empty()).then(|| target.to_string())
+        });
+
+        Some((delay, target))
+    }
+}
+
 impl HtmlElement {
+    /// An element with no attributes or children, to add them to with [`HtmlElement::attr`] and
+    /// [`HtmlElement::child`].
+    pub fn element(name: &str) -> Self {
+        HtmlElement {
+            name: name.to_string(),
+            attributes: vec![],
+            children: vec![],
+            text_node: None,
+        }
+    }
+
+    /// A text node, as the parser makes for each word.
+    pub fn text(text: &str) -> Self {
+        HtmlElement {
+            text_node: Some(text.to_string()),
+            ..HtmlElement::element("textNode")
+        }
+    }
+
+    /// This element with `key="value"` after its other attributes.
+    pub fn attr(mut self, key: &str, value: &str) -> Self {
+        self.attributes.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// This element with `child` after its other children.
+    pub fn child(mut self, child: HtmlElement) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Depth-first search for the first element with the given name.
+    pub fn find(&self, name: &str) -> Option<&HtmlElement> {
+        if self.name == name {
+            return Some(self);
+        }
+
+        self.children.iter().find_map(|child| child.find(name))
+    }
+
+    /// Every element named `name`, this one included, in document order.
+    pub fn find_all(&self, name: &str) -> Vec<&HtmlElement> {
+        let mut found = if self.name == name {
+            vec![self]
+        } else {
+            vec![]
+        };
+        found.extend(self.children.iter().flat_map(|child| child.find_all(name)));
+        found
+    }
+
+    /// Replaces the children of the element `trace` leads to with the elements parsed from
+    /// `source`, leaving the rest of the tree as it is. The trace starts at this element, and
+    /// each step after it is the first child with that name and attributes.
+    pub fn replace_children(
+        &mut self,
+        trace: &NodeTrace,
+        source: String,
+    ) -> Result<(), anyhow::Error> {
+        let Some(((name, attributes), path)) = trace.0.split_first() else {
+            bail!("Empty trace");
+        };
+        if self.name != *name || self.attributes != *attributes {
+            bail!("Trace starts at <{}>, not <{}>", name, self.name);
+        }
+
+        let mut element = self;
+        for (name, attributes) in path {
+            let parent = element.name.clone();
+            element = element
+                .children
+                .iter_mut()
+                .find(|child| child.name == *name && child.attributes == *attributes)
+                .with_context(|| format!("No <{}> in <{}>", name, parent))?;
+        }
+        element.children = parse_fragment(source)?;
+
+        Ok(())
+    }
+
+    /// Text of all descendant text nodes, joined by single spaces.
+    pub fn text_content(&self) -> String {
+        if let Some(text) = &self.text_node {
+            return text.clone();
+        }
+
+        self.children
+            .iter()
+            .map(|child| child.text_content())
+            .filter(|text| !text.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn head_metadata(&self) -> HeadMetadata {
+        let mut metadata = HeadMetadata::default();
+
+        if let Some(head) = self.find("head") {
+            // scripts never run, so <noscript> fallbacks apply as if written in place
+            let children = head.children.iter().flat_map(|child| {
+                if child.name == "noscript" {
+                    child.children.iter().collect::<Vec<_>>()
+                } else {
+                    vec![child]
+                }
+            });
+
+            for child in children {
+                match child.name.as_str() {
+                    "title" => metadata.title = Some(title_text(&child.text_content())),
+                    "meta" => metadata.meta.push(child.attributes.clone()),
+                    "link" => metadata.links.push(child.attributes.clone()),
+                    "style" => metadata.styles.push(child.text_content()),
+                    "base" if metadata.base.is_none() => {
+                        metadata.base = child
+                            .attributes
+                            .iter()
+                            .find(|(key, _)| key == "href")
+                            .map(|(_, href)| href.clone());
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        // minimal or malformed markup leaves out <head> or puts these under <html> or <body>
+        for (name, attributes, text) in stray_metadata(self) {
+            match name.as_str() {
+                "title" if metadata.title.is_none() => metadata.title = Some(title_text(&text)),
+                "meta" => metadata.meta.push(attributes),
+                _ => (),
+            }
+        }
+
+        metadata
+    }
+
     pub fn walk<
         D,
         F: Fn(
@@ -40,7 +268,7 @@ impl HtmlElement {
             Vec<HtmlElement>,
             Option<String>,
             &mut D,
-        ),
+        ) -> WalkControl,
         G: Fn(NodeTrace, String, &mut D),
     >(
         &self,
@@ -51,6 +279,135 @@ impl HtmlElement {
         self.walk_trace(&mut NodeTrace(vec![]), 0, f, g, d);
     }
 
+    /// Like [`HtmlElement::walk`], but only elements are visited. Instead of a callback per
+    /// `textNode`, the enter callback gets the element's direct text children concatenated, and
+    /// the index counts element siblings only.
+    pub fn walk_elements<
+        D,
+        F: Fn(NodeTrace, String, usize, Vec<(String, String)>, String, &mut D) -> WalkControl,
+        G: Fn(NodeTrace, String, &mut D),
+    >(
+        &self,
+        f: Rc<F>,
+        g: Rc<G>,
+        d: &mut D,
+    ) {
+        if self.name != "textNode" {
+            self.walk_elements_trace(&mut NodeTrace(vec![]), 0, &f, &g, d);
+        }
+    }
+
+    fn walk_elements_trace<
+        D,
+        F: Fn(NodeTrace, String, usize, Vec<(String, String)>, String, &mut D) -> WalkControl,
+        G: Fn(NodeTrace, String, &mut D),
+    >(
+        &self,
+        trace: &mut NodeTrace,
+        index: usize,
+        f: &Rc<F>,
+        g: &Rc<G>,
+        d: &mut D,
+    ) -> WalkControl {
+        trace.0.push((self.name.clone(), self.attributes.clone()));
+
+        let text = self
+            .children
+            .iter()
+            .filter_map(|child| child.text_node.as_deref())
+            .collect::<String>();
+        let control = f(
+            trace.clone(),
+            self.name.clone(),
+            index,
+            self.attributes.clone(),
+            text,
+            d,
+        );
+
+        let control = match control {
+            WalkControl::Continue => {
+                let stopped = self
+                    .children
+                    .iter()
+                    .filter(|child| child.name != "textNode")
+                    .enumerate()
+                    .any(|(i, child)| {
+                        child.walk_elements_trace(trace, i, f, g, d) == WalkControl::Stop
+                    });
+
+                if stopped {
+                    WalkControl::Stop
+                } else {
+                    g(trace.clone(), self.name.clone(), d);
+                    WalkControl::Continue
+                }
+            }
+            WalkControl::SkipChildren => WalkControl::Continue,
+            WalkControl::Stop => WalkControl::Stop,
+        };
+
+        trace.0.pop();
+
+        control
+    }
+
+    /// Serializes the tree back to markup that parses to the same tree.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        self.write_html(&mut html, true);
+
+        html
+    }
+
+    fn write_html(&self, html: &mut String, escape_text: bool) {
+        if let Some(text) = &self.text_node {
+            if escape_text {
+                html.push_str(
+                    &text
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;"),
+                );
+            } else {
+                html.push_str(text);
+            }
+            return;
+        }
+
+        html.push('<');
+        html.push_str(&self.name);
+        for (key, value) in &self.attributes {
+            let value = value.replace('&', "&amp;").replace('"', "&quot;");
+            html.push_str(&format!(" {}=\"{}\"", key, value));
+        }
+        if VOID_ELEMENTS.contains(&self.name.to_ascii_lowercase().as_str())
+            && self.children.is_empty()
+        {
+            html.push_str(" />");
+            return;
+        }
+        html.push('>');
+
+        // text is decoded everywhere but in raw text elements, so it is escaped everywhere else
+        let escape_text = !RAW_TEXT_ELEMENTS.contains(&self.name.as_str());
+        let is_word = |element: &HtmlElement| {
+            element
+                .text_node
+                .as_deref()
+                .is_some_and(|text| !text.trim().is_empty())
+        };
+        for (index, child) in self.children.iter().enumerate() {
+            // separate words are only told apart by the whitespace between them
+            if index > 0 && is_word(&self.children[index - 1]) && is_word(child) {
+                html.push(' ');
+            }
+            child.write_html(html, escape_text);
+        }
+
+        html.push_str(&format!("</{}>", self.name));
+    }
+
     pub fn walk_trace<
         D,
         F: Fn(
@@ -61,7 +418,7 @@ impl HtmlElement {
             Vec<HtmlElement>,
             Option<String>,
             &mut D,
-        ),
+        ) -> WalkControl,
         G: Fn(NodeTrace, String, &mut D),
     >(
         &self,
@@ -70,13 +427,13 @@ impl HtmlElement {
         f: Rc<F>,
         g: Rc<G>,
         d: &mut D,
-    ) {
+    ) -> WalkControl {
         let prev = trace.clone();
         if self.name != "textNode" {
             trace.0.push((self.name.clone(), self.attributes.clone()));
         }
 
-        f(
+        let control = f(
             trace.clone(),
             self.name.clone(),
             index,
@@ -86,43 +443,570 @@ impl HtmlElement {
             d,
         );
 
-        for (i, child) in self.children.iter().enumerate() {
-            child.walk_trace(trace, i, f.clone(), g.clone(), d);
-        }
+        let control = match control {
+            WalkControl::Continue => {
+                let stopped = self.children.iter().enumerate().any(|(i, child)| {
+                    child.walk_trace(trace, i, f.clone(), g.clone(), d) == WalkControl::Stop
+                });
 
-        g(trace.clone(), self.name.clone(), d);
+                if stopped {
+                    WalkControl::Stop
+                } else {
+                    g(trace.clone(), self.name.clone(), d);
+                    WalkControl::Continue
+                }
+            }
+            WalkControl::SkipChildren => WalkControl::Continue,
+            WalkControl::Stop => WalkControl::Stop,
+        };
 
         *trace = prev;
+
+        control
+    }
+}
+
+#[test]
+fn test_walk_control() {
+    let html = parse_html(
+        r##"<html><head><title>t</title></head><body><p>a</p><div>b</div><p>c</p></body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+
+    let cases = vec![
+        (
+            None,
+            WalkControl::Continue,
+            vec![
+                "+html",
+                "+head",
+                "+title",
+                "+textNode",
+                "-textNode",
+                "-title",
+                "-head",
+                "+body",
+                "+p",
+                "+textNode",
+                "-textNode",
+                "-p",
+                "+div",
+                "+textNode",
+                "-textNode",
+                "-div",
+                "+p",
+                "+textNode",
+                "-textNode",
+                "-p",
+                "-body",
+                "-html",
+            ],
+        ),
+        (
+            Some("head"),
+            WalkControl::SkipChildren,
+            vec![
+                "+html",
+                "+head",
+                "+body",
+                "+p",
+                "+textNode",
+                "-textNode",
+                "-p",
+                "+div",
+                "+textNode",
+                "-textNode",
+                "-div",
+                "+p",
+                "+textNode",
+                "-textNode",
+                "-p",
+                "-body",
+                "-html",
+            ],
+        ),
+        (
+            Some("div"),
+            WalkControl::Stop,
+            vec![
+                "+html",
+                "+head",
+                "+title",
+                "+textNode",
+                "-textNode",
+                "-title",
+                "-head",
+                "+body",
+                "+p",
+                "+textNode",
+                "-textNode",
+                "-p",
+                "+div",
+            ],
+        ),
+    ];
+
+    for (target, control, want) in cases {
+        let mut visited: Vec<String> = vec![];
+        html.walk(
+            Rc::new(
+                move |_: NodeTrace,
+                      name: String,
+                      _: usize,
+                      _: Vec<(String, String)>,
+                      _: Vec<HtmlElement>,
+                      _: Option<String>,
+                      visited: &mut Vec<String>| {
+                    visited.push(format!("+{}", name));
+                    if Some(name.as_str()) == target {
+                        control
+                    } else {
+                        WalkControl::Continue
+                    }
+                },
+            ),
+            Rc::new(|_: NodeTrace, name: String, visited: &mut Vec<String>| {
+                visited.push(format!("-{}", name));
+            }),
+            &mut visited,
+        );
+
+        assert_eq!(visited, want, "{:?}", control);
+    }
+}
+
+#[test]
+fn test_walk_elements() {
+    let html = parse_html(
+        r##"<html><body><p>a <b>b</b> c</p><div><p>d</p></div></body></html>"##.to_string(),
+    )
+    .unwrap();
+
+    let mut visited: Vec<String> = vec![];
+    html.walk_elements(
+        Rc::new(
+            |trace: NodeTrace,
+             name: String,
+             index: usize,
+             _: Vec<(String, String)>,
+             text: String,
+             visited: &mut Vec<String>| {
+                assert_eq!(trace.names().last(), Some(&name));
+                visited.push(format!("+{}{} {:?}", name, index, text));
+                WalkControl::Continue
+            },
+        ),
+        Rc::new(|_: NodeTrace, name: String, visited: &mut Vec<String>| {
+            visited.push(format!("-{}", name));
+        }),
+        &mut visited,
+    );
+
+    assert_eq!(
+        visited,
+        vec![
+            "+html0 \"\"",
+            "+body0 \"\"",
+            "+p0 \"a  c\"",
+            "+b0 \"b\"",
+            "-b",
+            "-p",
+            "+div1 \"\"",
+            "+p0 \"d\"",
+            "-p",
+            "-div",
+            "-body",
+            "-html",
+        ]
+    );
+}
+
+/// A node of the tree [`accessibility_tree`] makes, to check a page's semantics headlessly.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AxNode {
+    pub role: &'static str,
+    /// `aria-label`, `alt` for images, or else the text content, except for lists.
+    pub name: String,
+    pub children: Vec<AxNode>,
+}
+
+impl AxNode {
+    /// One `role "name"` line per node, indented by depth.
+    pub fn format(&self) -> String {
+        let mut lines = String::new();
+        self.write_lines(&mut lines, 0);
+
+        lines
+    }
+
+    fn write_lines(&self, lines: &mut String, depth: usize) {
+        lines.push_str(&format!(
+            "{}{} {:?}\n",
+            "  ".repeat(depth),
+            self.role,
+            self.name
+        ));
+        for child in &self.children {
+            child.write_lines(lines, depth + 1);
+        }
+    }
+}
+
+/// Elements left out of the accessibility tree with everything in them, as they are never shown.
+const AX_HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "template"];
+
+/// The role of an element that gets a node of its own in the accessibility tree. Images without
+/// `alt` text, or with an empty one as decoration, get none.
+fn ax_role(name: &str, attributes: &[(String, String)]) -> Option<&'static str> {
+    let has = |key: &str| attributes.iter().any(|(k, v)| k == key && !v.is_empty());
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Some("heading"),
+        "a" if has("href") => Some("link"),
+        "ul" | "ol" => Some("list"),
+        "li" => Some("listitem"),
+        "p" => Some("paragraph"),
+        "img" if has("alt") => Some("image"),
+        _ => None,
+    }
+}
+
+/// A simplified accessibility tree of the document: a `document` node named by the title, with
+/// the headings, links, lists, paragraphs and images with `alt` text nested under it as in the
+/// markup. Other elements only pass their content through, and `aria-hidden="true"` leaves a
+/// subtree out.
+pub fn accessibility_tree(html: &HtmlElement) -> AxNode {
+    let mut stack = vec![AxNode {
+        role: "document",
+        name: html.head_metadata().title.unwrap_or_default(),
+        children: vec![],
+    }];
+    html.walk(
+        Rc::new(
+            |_: NodeTrace,
+             name: String,
+             _: usize,
+             attributes: Vec<(String, String)>,
+             children: Vec<HtmlElement>,
+             _: Option<String>,
+             stack: &mut Vec<AxNode>| {
+                let hidden = attributes
+                    .iter()
+                    .any(|(key, value)| key == "aria-hidden" && value == "true");
+                if hidden || AX_HIDDEN_ELEMENTS.contains(&name.as_str()) {
+                    return WalkControl::SkipChildren;
+                }
+
+                if let Some(role) = ax_role(&name, &attributes) {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|(k, v)| k == key && !v.trim().is_empty())
+                            .map(|(_, v)| v.trim().to_string())
+                    };
+                    let label = attribute("aria-label")
+                        .or_else(|| attribute("alt").filter(|_| role == "image"))
+                        .unwrap_or_else(|| match role {
+                            // a list is only named by a label, not by everything in its items
+                            "list" => String::new(),
+                            _ => HtmlElement {
+                                children,
+                                ..HtmlElement::element(&name)
+                            }
+                            .text_content(),
+                        });
+                    stack.push(AxNode {
+                        role,
+                        name: label,
+                        children: vec![],
+                    });
+                }
+
+                WalkControl::Continue
+            },
+        ),
+        Rc::new(|trace: NodeTrace, name: String, stack: &mut Vec<AxNode>| {
+            let Some((_, attributes)) = trace.0.last() else {
+                return;
+            };
+            if ax_role(&name, attributes).is_some() {
+                let node = stack.pop();
+                if let (Some(node), Some(parent)) = (node, stack.last_mut()) {
+                    parent.children.push(node);
+                }
+            }
+        }),
+        &mut stack,
+    );
+
+    stack.remove(0)
+}
+
+#[test]
+fn test_accessibility_tree() {
+    let html = parse_html(
+        r##"<html>
+  <head>
+    <title>sample web page</title>
+  </head>
+  <body>
+    <h1>Hello, world!</h1>
+    <p>This is a <a href="link1.html">link</a>.</p>
+    <ul>
+      <li><a href="link2.html" aria-label="second page">next</a></li>
+      <li><img src="cat.png" alt="A cat" /><img src="spacer.png" alt="" /></li>
+    </ul>
+    <div aria-hidden="true"><p>hidden</p></div>
+    <script>ignored()</script>
+  </body>
+</html>
+"##
+        .to_string(),
+    )
+    .unwrap();
+
+    let node = |role, name: &str, children| AxNode {
+        role,
+        name: name.to_string(),
+        children,
+    };
+    let tree = accessibility_tree(&html);
+    assert_eq!(
+        tree,
+        node(
+            "document",
+            "sample web page",
+            vec![
+                node("heading", "Hello, world!", vec![]),
+                node(
+                    "paragraph",
+                    "This is a link .",
+                    vec![node("link", "link", vec![])]
+                ),
+                node(
+                    "list",
+                    "",
+                    vec![
+                        node(
+                            "listitem",
+                            "next",
+                            vec![node("link", "second page", vec![])]
+                        ),
+                        node("listitem", "", vec![node("image", "A cat", vec![])]),
+                    ]
+                ),
+            ]
+        )
+    );
+    assert_eq!(
+        tree.format().lines().take(3).collect::<Vec<_>>(),
+        vec![
+            "document \"sample web page\"",
+            "  heading \"Hello, world!\"",
+            "  paragraph \"This is a link .\"",
+        ]
+    );
+}
+
+/// Elements whose content is taken verbatim up to the closing tag instead of being parsed as
+/// markup. An inline `<svg>` is handed to the SVG renderer as it was written.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "svg"];
+/// Like `RAW_TEXT_ELEMENTS`, but character references in the content are decoded.
+const ESCAPABLE_RAW_TEXT_ELEMENTS: &[&str] = &["title", "textarea"];
+
+/// Elements that never have content, written as `<br />`.
+const VOID_ELEMENTS: &[&str] = &["br", "hr", "img", "input", "meta", "link", "base", "wbr"];
+
+/// `&shy;`, where a word may be broken with a hyphen if it doesn't fit on the line.
+pub const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Decodes character references such as `&amp;`, `&#60;` and `&#x3C;`. Unknown references are
+/// left as written.
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let reference = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let char = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            "shy" => Some(SOFT_HYPHEN),
+            _ => reference
+                .strip_prefix("#x")
+                .or_else(|| reference.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| reference.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        });
+
+        match (reference, char) {
+            (Some(reference), Some(char)) => {
+                decoded.push(char);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+#[test]
+fn test_decode_entities() {
+    let cases = vec![
+        ("A &lt; B", "A < B"),
+        ("Fish &amp; Chips", "Fish & Chips"),
+        ("&#60;&#x3C;&#X3c;", "<<<"),
+        ("&quot;&apos;", "\"'"),
+        ("co&shy;operate", "co\u{ad}operate"),
+        ("AT&T &unknown; &", "AT&T &unknown; &"),
+        ("&#xZZ;", "&#xZZ;"),
+        ("日本&amp;語 &#x1F389;", "日本&語 🎉"),
+    ];
+
+    for (text, want) in cases {
+        assert_eq!(decode_entities(text), want, "{}", text);
     }
 }
 
+/// Index of the first occurrence of `pattern` at or after `from`, if any.
+fn find_chars(chars: &[char], from: usize, pattern: &str) -> Option<usize> {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+
+    (from..chars.len()).find(|&index| chars[index..].starts_with(&pattern))
+}
+
 fn tokenize_html(str: String) -> Vec<Token> {
     let mut tokens = vec![];
     let chars = str.chars().collect::<Vec<_>>();
+    let lowercase = chars
+        .iter()
+        .map(|c| c.to_ascii_lowercase())
+        .collect::<Vec<_>>();
     let mut position = 0;
+    let mut inside_tag = false;
 
     while position < chars.len() {
         if chars[position].is_whitespace() {
-            position += 1;
+            let start = position;
+            while position < chars.len() && chars[position].is_whitespace() {
+                position += 1;
+            }
+
+            if !inside_tag && !tokens.is_empty() {
+                tokens.push(Token::Whitespace(
+                    chars[start..position].iter().collect::<String>(),
+                ));
+            }
             continue;
-        } else if chars[position..].starts_with(&"<!DOCTYPE html>".chars().collect::<Vec<_>>()) {
-            position += "<!DOCTYPE html>".len();
+        } else if chars[position..].starts_with(&"<![CDATA[".chars().collect::<Vec<_>>()) {
+            // character data: the content is text, even where it looks like markup
+            let start = position + "<![CDATA[".len();
+            let end = find_chars(&chars, start, "]]>").unwrap_or(chars.len());
+            let text = chars[start..end].iter().collect::<String>();
+            tokens.extend(
+                text.split_whitespace()
+                    .map(|word| Token::Text(word.to_string())),
+            );
+            position = (end + "]]>".len()).min(chars.len());
+        } else if chars[position..].starts_with(&"<!--".chars().collect::<Vec<_>>()) {
+            // an IE conditional comment hides everything up to its `<![endif]-->`, comments
+            // inside included, unless it closes at once to show what follows to other browsers
+            // as in `<!--[if !IE]><!-->`
+            let start = position + "<!--".len();
+            let mut end = find_chars(&chars, start, "-->").unwrap_or(chars.len());
+            let mut terminator = "-->";
+            if lowercase[start..].starts_with(&['[', 'i', 'f'])
+                && !chars[start..end].ends_with(&['<', '!'])
+            {
+                if let Some(endif) = find_chars(&lowercase, start, "<![endif]-->") {
+                    end = endif;
+                    terminator = "<![endif]-->";
+                }
+            }
+            position = (end + terminator.len()).min(chars.len());
+        } else if chars[position..].starts_with(&['<', '!']) {
+            // declarations such as any form of doctype, skipped up to the `>` outside quotes
+            let mut quote = None;
+            position += 2;
+            while position < chars.len() && (quote.is_some() || chars[position] != '>') {
+                match (quote, chars[position]) {
+                    (None, c @ ('"' | '\'')) => quote = Some(c),
+                    (Some(q), c) if q == c => quote = None,
+                    _ => (),
+                }
+                position += 1;
+            }
+            position += 1;
+        } else if chars[position..].starts_with(&['<', '?']) {
+            // processing instructions such as `<?xml version="1.0"?>` carry nothing to render
+            let end = find_chars(&chars, position + 2, "?>").unwrap_or(chars.len());
+            position = (end + "?>".len()).min(chars.len());
         } else if chars[position] == '<' {
             tokens.push(Token::LAngle);
+            inside_tag = true;
             position += 1;
         } else if chars[position] == '>' {
             tokens.push(Token::RAngle);
+            inside_tag = false;
             position += 1;
+
+            // the content of a raw text element runs up to its closing tag, whatever it contains
+            let tag_start = tokens.iter().rposition(|token| *token == Token::LAngle);
+            let raw_text = match tag_start.map(|start| &tokens[start + 1..]) {
+                Some([Token::Text(_), .., Token::Slash, Token::RAngle]) => None,
+                Some([Token::Text(name), ..]) => {
+                    let name = name.to_ascii_lowercase();
+                    if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                        Some((name, false))
+                    } else if ESCAPABLE_RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                        Some((name, true))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some((name, escapable)) = raw_text {
+                let end =
+                    find_chars(&lowercase, position, &format!("</{}", name)).unwrap_or(chars.len());
+                let text = chars[position..end].iter().collect::<String>();
+                if !text.is_empty() {
+                    tokens.push(Token::Text(if escapable {
+                        decode_entities(&text)
+                    } else {
+                        text
+                    }));
+                }
+                position = end;
+            }
         } else if chars[position] == '/' {
             tokens.push(Token::Slash);
             position += 1;
         } else if chars[position] == '=' {
             tokens.push(Token::Equal);
             position += 1;
-        } else if chars[position] == '"' {
+        } else if chars[position] == '"' || (inside_tag && chars[position] == '\'') {
+            // a value runs to the next of the quote it opened with, so it can hold the other one
+            // as in `alt='She said "hi"'`. Outside tags an apostrophe is just part of a word.
+            let quote = chars[position];
             let mut text = String::new();
             position += 1;
-            while position < chars.len() && chars[position] != '"' {
+            while position < chars.len() && chars[position] != quote {
                 text.push(chars[position]);
                 position += 1;
             }
@@ -132,16 +1016,24 @@ fn tokenize_html(str: String) -> Vec<Token> {
             let mut text = String::new();
             text.push(chars[position]);
             position += 1;
+            // inside a tag a quote ends a name or bare value too, so a quoted value comes apart
+            // from whatever is packed against it, as in `<a href="x"class="y">`
             while position < chars.len()
                 && !chars[position].is_whitespace()
                 && chars[position] != '<'
                 && chars[position] != '>'
                 && chars[position] != '='
+                && !(inside_tag && matches!(chars[position], '"' | '\''))
             {
                 text.push(chars[position]);
                 position += 1;
             }
-            tokens.push(Token::Text(text));
+            // content is decoded once split into words, so a decoded `&nbsp;` doesn't split one
+            tokens.push(Token::Text(if inside_tag {
+                text
+            } else {
+                decode_entities(&text)
+            }));
         }
     }
 
@@ -164,7 +1056,7 @@ fn test_tokenize_html() {
             ],
         ),
         (
-            r##"<body bgcolor="#ffffff">This is a paragraph</body>"##,
+            "<body bgcolor=\"#ffffff\">This is a\n  paragraph</body>",
             vec![
                 Token::LAngle,
                 Token::Text("body".to_string()),
@@ -173,8 +1065,11 @@ fn test_tokenize_html() {
                 Token::QuotedText("#ffffff".to_string()),
                 Token::RAngle,
                 Token::Text("This".to_string()),
+                Token::Whitespace(" ".to_string()),
                 Token::Text("is".to_string()),
+                Token::Whitespace(" ".to_string()),
                 Token::Text("a".to_string()),
+                Token::Whitespace("\n  ".to_string()),
                 Token::Text("paragraph".to_string()),
                 Token::LAngle,
                 Token::Slash,
@@ -204,16 +1099,168 @@ fn test_tokenize_html() {
     }
 }
 
+#[test]
+fn test_tokenize_html_mixed_quotes() {
+    let img = |key: &str, value: &str| {
+        vec![
+            Token::LAngle,
+            Token::Text("img".to_string()),
+            Token::Text(key.to_string()),
+            Token::Equal,
+            Token::QuotedText(value.to_string()),
+            Token::RAngle,
+        ]
+    };
+
+    let cases = vec![
+        (
+            r#"<img alt='She said "hi"'>"#,
+            img("alt", r#"She said "hi""#),
+        ),
+        (r#"<img title="it's fine">"#, img("title", "it's fine")),
+        (r#"<img alt='single'>"#, img("alt", "single")),
+        (r#"<img alt="'">"#, img("alt", "'")),
+        (r#"<img alt='"'>"#, img("alt", "\"")),
+    ];
+    for (str, want) in cases {
+        assert_eq!(tokenize_html(str.to_string()), want, "{}", str);
+    }
+
+    // an apostrophe in content doesn't start a quoted value
+    assert_eq!(
+        tokenize_html("<p>it's 'quoted'</p>".to_string())[3..6],
+        [
+            Token::Text("it's".to_string()),
+            Token::Whitespace(" ".to_string()),
+            Token::Text("'quoted'".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_tokenize_html_packed_attributes() {
+    let text = |text: &str| Token::Text(text.to_string());
+    let quoted = |text: &str| Token::QuotedText(text.to_string());
+
+    assert_eq!(
+        tokenize_html(r#"<a href="x"class='y'title=z"w">"#.to_string()),
+        vec![
+            Token::LAngle,
+            text("a"),
+            text("href"),
+            Token::Equal,
+            quoted("x"),
+            text("class"),
+            Token::Equal,
+            quoted("y"),
+            text("title"),
+            Token::Equal,
+            text("z"),
+            quoted("w"),
+            Token::RAngle,
+        ]
+    );
+
+    let html = parse_html(
+        r#"<html><body><a href="/x"class="y"title='z'>link</a></body></html>"#.to_string(),
+    )
+    .unwrap();
+    assert_eq!(
+        html.find_all("a")[0].attributes,
+        vec![
+            ("href".to_string(), "/x".to_string()),
+            ("class".to_string(), "y".to_string()),
+            ("title".to_string(), "z".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_tokenize_html_declarations() {
+    let want = tokenize_html("<html></html>".to_string());
+
+    let cases = vec![
+        "<!DOCTYPE html><html></html>",
+        "<!doctype html>\n<html></html>",
+        "<!DocType HTML><html></html>",
+        r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd"><html></html>"#,
+        r#"<!DOCTYPE html SYSTEM "about:legacy-compat>"><html></html>"#,
+        "<!-- a > b --><html><!----></html>",
+        "<html></html><!DOCTYPE unterminated",
+        "<html></html><!-- unterminated",
+        "<!--[if IE]><p>old</p><![endif]--><html></html>",
+        "<html><!--[if lt IE 9]><!-- shim --><p>old</p><![ENDIF]--></html>",
+        "<!--[if !IE]><!--><html></html><!--<![endif]-->",
+        "<html></html><!--[if IE]> unterminated -->",
+    ];
+
+    for str in cases {
+        assert_eq!(tokenize_html(str.to_string()), want, "{}", str);
+    }
+}
+
+#[test]
+fn test_tokenize_html_cdata_and_processing_instructions() {
+    let p = |text: &str| {
+        let mut tokens = vec![Token::LAngle, Token::Text("p".to_string()), Token::RAngle];
+        tokens.extend(text.split_whitespace().map(|w| Token::Text(w.to_string())));
+        tokens.extend([
+            Token::LAngle,
+            Token::Slash,
+            Token::Text("p".to_string()),
+            Token::RAngle,
+        ]);
+        tokens
+    };
+
+    let cases = vec![
+        ("<p><![CDATA[a <b> c]]></p>", p("a <b> c")),
+        ("<p><![CDATA[]]></p>", p("")),
+        (r#"<?xml version="1.0"?><p>x</p>"#, p("x")),
+        ("<p><?php echo 1 ?>x</p>", p("x")),
+        ("<p>x</p><![CDATA[never <closed", {
+            let mut tokens = p("x");
+            tokens.extend([
+                Token::Text("never".to_string()),
+                Token::Text("<closed".to_string()),
+            ]);
+            tokens
+        }),
+        ("<p>x</p><?unterminated", p("x")),
+    ];
+
+    for (str, want) in cases {
+        assert_eq!(tokenize_html(str.to_string()), want, "{}", str);
+    }
+}
+
 struct HtmlParser {
     tokens: Vec<Token>,
     position: usize,
+    /// How many of the open elements keep their whitespace as written, such as `<pre>`.
+    preserving_whitespace: usize,
+    /// Recover from close tags that don't match the open element instead of failing.
+    lenient: bool,
+    /// Names of the elements whose children are being parsed, outermost first.
+    open_elements: Vec<String>,
 }
 
 impl HtmlParser {
-    fn new(tokens: Vec<Token>) -> Self {
+    fn new(tokens: Vec<Token>, lenient: bool) -> Self {
         HtmlParser {
             tokens,
             position: 0,
+            preserving_whitespace: 0,
+            lenient,
+            open_elements: vec![],
+        }
+    }
+
+    /// Name in the close tag at the current position, if there is one.
+    fn close_tag(&self) -> Option<&str> {
+        match self.tokens.get(self.position..self.position + 4) {
+            Some([Token::LAngle, Token::Slash, Token::Text(name), Token::RAngle]) => Some(name),
+            _ => None,
         }
     }
 
@@ -230,7 +1277,7 @@ impl HtmlParser {
     }
 
     fn expect(&mut self, token: Token) -> Result<(), anyhow::Error> {
-        if self.tokens[self.position] != token {
+        if self.peek() != Some(&token) {
             bail!(
                 "Want {:?}, but got {:?} ({})",
                 token,
@@ -244,9 +1291,10 @@ impl HtmlParser {
     }
 
     fn expect_text(&mut self) -> Result<String, anyhow::Error> {
-        if let Token::Text(text) = &self.tokens[self.position] {
+        if let Some(Token::Text(text)) = self.peek() {
+            let text = text.clone();
             self.position += 1;
-            Ok(text.clone())
+            Ok(text)
         } else {
             bail!(
                 "Want text, but got {:?} ({})",
@@ -257,9 +1305,10 @@ impl HtmlParser {
     }
 
     fn expect_quoted_text(&mut self) -> Result<String, anyhow::Error> {
-        if let Token::QuotedText(text) = &self.tokens[self.position] {
+        if let Some(Token::QuotedText(text)) = self.peek() {
+            let text = text.clone();
             self.position += 1;
-            Ok(text.clone())
+            Ok(text)
         } else {
             bail!(
                 "Want quoted text, but got {:?} ({})",
@@ -271,8 +1320,16 @@ impl HtmlParser {
 
     fn attribute(&mut self) -> Result<(String, String), anyhow::Error> {
         let key = self.expect_text()?;
+        if self.lenient && self.peek() != Some(&Token::Equal) {
+            // a bare name, as in `<details open>`
+            return Ok((key, String::new()));
+        }
         self.expect(Token::Equal)?;
-        let value = self.expect_quoted_text()?;
+        if self.lenient && matches!(self.peek(), Some(Token::Text(_))) {
+            // unquoted, as in `title=bare`
+            return Ok((key, decode_entities(&self.expect_text()?)));
+        }
+        let value = decode_entities(&self.expect_quoted_text()?);
         Ok((key, value))
     }
 
@@ -280,13 +1337,48 @@ impl HtmlParser {
         let mut attributes = vec![];
 
         while self.peek() != Some(&Token::RAngle) && self.peek() != Some(&Token::Slash) {
-            attributes.push(self.attribute()?);
+            if !self.lenient {
+                attributes.push(self.attribute()?);
+                continue;
+            }
+
+            // skip whatever can't start a `key="value"` pair: orphan quoted strings, and a `=`
+            // with no name along with the value after it
+            match self.peek() {
+                None => break,
+                Some(Token::Text(_)) => {
+                    let start = self.position;
+                    match self.attribute() {
+                        Ok(attribute) => attributes.push(attribute),
+                        // a name followed by `=` and no value
+                        Err(_) => self.position = start + 2,
+                    }
+                }
+                Some(Token::Equal) => {
+                    self.position += 1;
+                    if matches!(self.peek(), Some(Token::Text(_) | Token::QuotedText(_))) {
+                        self.position += 1;
+                    }
+                }
+                Some(_) => self.position += 1,
+            }
         }
 
         Ok(attributes)
     }
 
     fn element(&mut self) -> Result<HtmlElement, anyhow::Error> {
+        if let Some(Token::Whitespace(text)) = self.peek() {
+            let text = text.clone();
+            self.position += 1;
+            return Ok(HtmlElement {
+                name: "textNode".to_string(),
+                attributes: vec![],
+                children: vec![],
+                text_node: Some(text),
+            });
+        }
+
         if matches!(self.peek(), Some(Token::Text(_))) {
             return Ok(HtmlElement {
                 name: "textNode".to_string(),
@@ -297,21 +1389,20 @@ impl HtmlParser {
         }
 
         self.expect(Token::LAngle)?;
-        let name = self.expect_text()?;
+        let mut name = self.expect_text()?;
+        // the tokenizer keeps a slash packed against the name, as in `<br/>`, within the name
+        let mut self_closing = name.len() > 1 && name.ends_with('/');
+        if self_closing {
+            name.pop();
+        }
         let attributes = self.attributes()?;
         if self.peek() == Some(&Token::Slash) {
             self.expect(Token::Slash)?;
-            self.expect(Token::RAngle)?;
-            return Ok(HtmlElement {
-                name,
-                attributes,
-                children: vec![],
-                text_node: None,
-            });
+            self_closing = true;
         }
         self.expect(Token::RAngle)?;
 
-        if name == "meta" {
+        if self_closing || VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
             return Ok(HtmlElement {
                 name,
                 attributes,
@@ -325,6 +1416,17 @@ impl HtmlParser {
                 Token::Text("script".to_string()),
                 Token::RAngle,
             ]) {
+                if self.peek().is_none() {
+                    if self.lenient {
+                        return Ok(HtmlElement {
+                            name,
+                            attributes,
+                            children: vec![],
+                            text_node: None,
+                        });
+                    }
+                    bail!("unclosed <script> at end of input ({})", self.position);
+                }
                 self.position += 1;
             }
 
@@ -340,13 +1442,39 @@ impl HtmlParser {
                 text_node: None,
             });
         } else {
-            let children: Vec<HtmlElement> =
-                self.elements().context(format!("children of {}", name))?;
+            let preserves_whitespace = preserves_whitespace(&name, &attributes);
+            if preserves_whitespace {
+                self.preserving_whitespace += 1;
+            }
+            self.open_elements.push(name.clone());
+            let children = self
+                .children(&name)
+                .context(format!("children of {}", name));
+            self.open_elements.pop();
+            if preserves_whitespace {
+                self.preserving_whitespace -= 1;
+            }
 
-            self.expect(Token::LAngle)?;
-            self.expect(Token::Slash)?;
-            self.expect(Token::Text(name.clone()))?;
-            self.expect(Token::RAngle)?;
+            let mut children: Vec<HtmlElement> = children?;
+            if name == "pre" {
+                // a newline right after the start tag is there for readability of the source
+                if let Some(text) = children
+                    .first_mut()
+                    .and_then(|first| first.text_node.as_mut())
+                {
+                    if let Some(rest) = text.strip_prefix('\n') {
+                        *text = rest.to_string();
+                    }
+                }
+                children.retain(|child| child.text_node.as_deref() != Some(""));
+            }
+            if !RAW_TEXT_ELEMENTS.contains(&name.as_str())
+                && !ESCAPABLE_RAW_TEXT_ELEMENTS.contains(&name.as_str())
+                && !preserves_whitespace
+                && self.preserving_whitespace == 0
+            {
+                children = normalize_whitespace(children);
+            }
 
             Ok(HtmlElement {
                 name,
@@ -357,6 +1485,47 @@ impl HtmlParser {
         }
     }
 
+    /// Parses the children of an open `name` element along with its close tag.
+    ///
+    /// In lenient mode, a close tag for an element further out closes `name` implicitly and is
+    /// left for that element, and a close tag for no open element at all is dropped.
+    fn children(&mut self, name: &str) -> Result<Vec<HtmlElement>, anyhow::Error> {
+        let mut children = vec![];
+
+        loop {
+            children.extend(self.elements()?);
+
+            let Some(found) = self.close_tag() else {
+                if self.lenient {
+                    return Ok(children);
+                }
+                bail!("unclosed <{}> at end of input ({})", name, self.position);
+            };
+
+            if found == name || (self.lenient && found.eq_ignore_ascii_case(name)) {
+                self.position += 4;
+                return Ok(children);
+            }
+            if !self.lenient {
+                bail!(
+                    "mismatched close tag: expected </{}>, found </{}> ({})",
+                    name,
+                    found,
+                    self.position
+                );
+            }
+
+            if self
+                .open_elements
+                .iter()
+                .any(|open| open.eq_ignore_ascii_case(found))
+            {
+                return Ok(children);
+            }
+            self.position += 4;
+        }
+    }
+
     fn elements(&mut self) -> Result<Vec<HtmlElement>, anyhow::Error> {
         let mut elements = vec![];
 
@@ -372,17 +1541,384 @@ impl HtmlParser {
     }
 }
 
+/// Parses a document. A lone root element is returned as is; when there are several, the ones
+/// outside `<html>` are moved into its body, and an `html` root is made up if there is none.
 pub fn parse_html(str: String) -> Result<HtmlElement, anyhow::Error> {
-    println!("Parsing HTML: {}", str);
-    let tokens = tokenize_html(str);
-    println!("Tokens: {:?}", tokens);
-    let mut parser = HtmlParser::new(tokens);
-    let element = parser.element()?;
-    println!("Element: {:?}", element);
+    let element = document_from_roots(parse_fragment(str)?);
+    eprintln!("Element: {:?}", element);
+
+    Ok(element)
+}
+
+/// Like [`parse_html`], but recovers from crossed and stray close tags the way browsers do with
+/// tag soup: `<b><i>x</b>` closes the `<i>` along with the `<b>`, and a `</i>` that closes
+/// nothing is dropped.
+#[cfg(test)]
+fn parse_html_lenient(str: String) -> Result<HtmlElement, anyhow::Error> {
+    let element = document_from_roots(parse_roots(str, true)?);
+    eprintln!("Element: {:?}", element);
 
     Ok(element)
 }
 
+/// How long [`parse_html_timed`] spent splitting the source into tokens, and building the tree
+/// from them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParseTimings {
+    pub tokenize: Duration,
+    pub parse: Duration,
+}
+
+/// [`parse_html`], timing each stage. If `lenient`, recovers from crossed and stray close tags
+/// the way browsers do with tag soup instead of failing.
+pub fn parse_html_timed(
+    str: String,
+    lenient: bool,
+) -> (Result<HtmlElement, anyhow::Error>, ParseTimings) {
+    let started = Instant::now();
+    let tokens = tokenize_html(str);
+    let tokenized = Instant::now();
+    let element = parse_tokens(tokens, lenient).map(document_from_roots);
+
+    let timings = ParseTimings {
+        tokenize: tokenized - started,
+        parse: tokenized.elapsed(),
+    };
+    (element, timings)
+}
+
+#[test]
+fn test_parse_html_timed() {
+    let source = "<html><body><b><i>x</b></i></body></html>";
+    let (element, _) = parse_html_timed(source.to_string(), false);
+    assert!(element.is_err());
+
+    let (element, _) = parse_html_timed(source.to_string(), true);
+    assert_eq!(
+        element.unwrap(),
+        parse_html_lenient(source.to_string()).unwrap()
+    );
+}
+
+/// Parses each of `sources` like `parse_html`, spread over up to `threads` threads, and returns
+/// the results in the same order.
+pub fn parse_many(sources: Vec<String>, threads: usize) -> Vec<Result<HtmlElement, anyhow::Error>> {
+    // each thread takes the next run of documents, in order
+    let threads = threads.clamp(1, sources.len().max(1));
+    let per_thread = sources.len().div_ceil(threads);
+    let mut sources = sources.into_iter();
+    let batches = (0..threads)
+        .map(|_| sources.by_ref().take(per_thread).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    std::thread::scope(|scope| {
+        let workers = batches
+            .into_iter()
+            .map(|batch| scope.spawn(|| batch.into_iter().map(parse_html).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+#[test]
+fn test_parse_many() {
+    let sources = (0..10)
+        .map(|i| match i % 3 {
+            0 => format!("<html><body><p>page {}</p></body></html>", i),
+            1 => format!("<p>fragment</p><p>{}</p>", i),
+            _ => "<html><body><b><i>crossed</b></i></body></html>".to_string(),
+        })
+        .collect::<Vec<_>>();
+    let sequential = sources
+        .iter()
+        .map(|source| parse_html(source.clone()).map_err(|err| err.to_string()))
+        .collect::<Vec<_>>();
+
+    for threads in [0, 1, 3, 16] {
+        let parsed = parse_many(sources.clone(), threads)
+            .into_iter()
+            .map(|result| result.map_err(|err| err.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, sequential, "{} threads", threads);
+    }
+    assert!(parse_many(vec![], 4).is_empty());
+}
+
+#[test]
+fn test_replace_children() {
+    let source = |list: &str| {
+        format!(
+            r#"<html><head><title>t</title></head><body><div id="nav"><a href="/">home</a></div><ul class="items">{}</ul><p>footer</p></body></html>"#,
+            list
+        )
+    };
+    let mut html = parse_html(source("<li>one</li>")).unwrap();
+    let original = html.clone();
+    let trace = |steps: &[(&str, &[(&str, &str)])]| {
+        NodeTrace(
+            steps
+                .iter()
+                .map(|(name, attributes)| {
+                    let attributes = attributes
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect();
+                    (name.to_string(), attributes)
+                })
+                .collect(),
+        )
+    };
+    let list = trace(&[("html", &[]), ("body", &[]), ("ul", &[("class", "items")])]);
+
+    html.replace_children(&list, "<li>one</li><li><b>two</b></li>".to_string())
+        .unwrap();
+    assert_eq!(
+        html,
+        parse_html(source("<li>one</li><li><b>two</b></li>")).unwrap()
+    );
+    // everything outside the list is as it was
+    let body = |html: &HtmlElement| html.find("body").unwrap().children.clone();
+    assert_eq!(body(&html)[0], body(&original)[0]);
+    assert_eq!(body(&html)[2], body(&original)[2]);
+    assert_eq!(html.children[0], original.children[0]);
+
+    // a trace that leads nowhere, or source that doesn't parse, leaves the tree alone
+    let edited = html.clone();
+    let missing = trace(&[("html", &[]), ("body", &[]), ("ul", &[("class", "other")])]);
+    assert!(html
+        .replace_children(&missing, "<li>x</li>".to_string())
+        .is_err());
+    assert!(html
+        .replace_children(&list, "<li><b>x</li></b>".to_string())
+        .is_err());
+    assert!(html
+        .replace_children(&trace(&[("body", &[])]), "x".to_string())
+        .is_err());
+    assert_eq!(html, edited);
+}
+
+/// Parses a sequence of sibling elements, such as `<p>a</p><p>b</p>`.
+pub fn parse_fragment(str: String) -> Result<Vec<HtmlElement>, anyhow::Error> {
+    parse_roots(str, false)
+}
+
+fn parse_roots(str: String, lenient: bool) -> Result<Vec<HtmlElement>, anyhow::Error> {
+    eprintln!("Parsing HTML: {}", str);
+    parse_tokens(tokenize_html(str), lenient)
+}
+
+fn parse_tokens(tokens: Vec<Token>, lenient: bool) -> Result<Vec<HtmlElement>, anyhow::Error> {
+    eprintln!("Tokens: {:?}", tokens);
+    let mut parser = HtmlParser::new(tokens, lenient);
+
+    let mut roots = parser.elements()?;
+    // close tags outside every element close nothing
+    while lenient && parser.close_tag().is_some() {
+        parser.position += 4;
+        roots.extend(parser.elements()?);
+    }
+
+    Ok(normalize_whitespace(roots))
+}
+
+fn title_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A `<title>` or `<meta>` by name, attributes and text.
+type StrayMetadata = (String, Vec<(String, String)>, String);
+
+/// Each `<title>` and `<meta>` element outside `<head>`, in document order. The `<title>` of an
+/// `<svg>` labels the drawing, not the document, so drawings are left out too.
+fn stray_metadata(html: &HtmlElement) -> Vec<StrayMetadata> {
+    let mut found = vec![];
+    html.walk_elements(
+        Rc::new(
+            |_: NodeTrace,
+             name: String,
+             _: usize,
+             attributes: Vec<(String, String)>,
+             text: String,
+             found: &mut Vec<StrayMetadata>| {
+                match name.as_str() {
+                    "head" | "svg" | "template" => WalkControl::SkipChildren,
+                    "title" | "meta" => {
+                        found.push((name, attributes, text));
+                        WalkControl::SkipChildren
+                    }
+                    _ => WalkControl::Continue,
+                }
+            },
+        ),
+        Rc::new(|_: NodeTrace, _: String, _: &mut Vec<_>| {}),
+        &mut found,
+    );
+
+    found
+}
+
+/// Elements that whitespace next to is insignificant: it's either not rendered at all or falls
+/// at the start or end of a line.
+pub const BLOCK_LEVEL_ELEMENTS: &[&str] = &[
+    "html",
+    "head",
+    "body",
+    "title",
+    "meta",
+    "link",
+    "base",
+    "style",
+    "script",
+    "template",
+    "div",
+    "blockquote",
+    "p",
+    "pre",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "table",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+    "td",
+    "th",
+    "form",
+    "hr",
+    "br",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "nav",
+    "main",
+    "aside",
+    "figure",
+    "figcaption",
+    "hgroup",
+    "address",
+];
+
+/// Whether whitespace inside the element is kept as written: `<pre>`, or a `white-space` in its
+/// inline style that preserves it. Stylesheets aren't known when parsing, so elements they style
+/// that way get collapsed whitespace.
+fn preserves_whitespace(name: &str, attributes: &[(String, String)]) -> bool {
+    name == "pre"
+        || attributes
+            .iter()
+            .filter(|(key, _)| key == "style")
+            .filter_map(|(_, style)| crate::css::parse_css(style.clone()).ok())
+            .flat_map(|styles| styles.styles)
+            .any(|style| {
+                style
+                    .get("white-space")
+                    .is_some_and(|value| value.starts_with("pre"))
+            })
+}
+
+/// Drops whitespace-only text nodes unless they separate two pieces of inline content, and turns
+/// the ones that remain into a single space. Between two words, where the space is implied by
+/// their being separate text nodes, they are dropped as well.
+fn normalize_whitespace(children: Vec<HtmlElement>) -> Vec<HtmlElement> {
+    let is_whitespace = |element: &HtmlElement| {
+        element
+            .text_node
+            .as_deref()
+            .is_some_and(|text| text.trim().is_empty())
+    };
+    let is_word = |element: &HtmlElement| element.text_node.is_some() && !is_whitespace(element);
+    let is_inline = |element: &HtmlElement| {
+        !is_whitespace(element)
+            && (element.name == "textNode"
+                || !BLOCK_LEVEL_ELEMENTS.contains(&element.name.as_str()))
+    };
+
+    let mut normalized: Vec<HtmlElement> = vec![];
+    for (index, child) in children.iter().enumerate() {
+        if !is_whitespace(child) {
+            normalized.push(child.clone());
+            continue;
+        }
+
+        let next = children[index + 1..]
+            .iter()
+            .find(|next| !is_whitespace(next));
+        let after_inline = normalized.last().is_some_and(is_inline);
+        let before_inline = next.is_some_and(is_inline);
+        let between_words = normalized.last().is_some_and(is_word) && next.is_some_and(is_word);
+        if after_inline && before_inline && !between_words {
+            normalized.push(HtmlElement {
+                text_node: Some(" ".to_string()),
+                ..child.clone()
+            });
+        }
+    }
+
+    normalized
+}
+
+fn document_from_roots(mut roots: Vec<HtmlElement>) -> HtmlElement {
+    if roots.len() == 1 {
+        return roots.remove(0);
+    }
+
+    let mut html = match roots.iter().position(|root| root.name == "html") {
+        Some(index) => roots.remove(index),
+        None => HtmlElement::element("html"),
+    };
+    if !html.children.iter().any(|child| child.name == "body") {
+        html = html.child(HtmlElement::element("body"));
+    }
+    let body = html
+        .children
+        .iter_mut()
+        .find(|child| child.name == "body")
+        .unwrap();
+    body.children.extend(roots);
+
+    html
+}
+
+#[test]
+fn test_builder() {
+    let built = HtmlElement::element("html")
+        .child(HtmlElement::element("head"))
+        .child(
+            HtmlElement::element("body")
+                .attr("bgcolor", "#cccccc")
+                .child(HtmlElement::text("Hello,"))
+                .child(HtmlElement::text("world!"))
+                .child(
+                    HtmlElement::element("a")
+                        .attr("href", "next.html")
+                        .attr("target", "_blank")
+                        .child(HtmlElement::text("next")),
+                ),
+        );
+    let parsed = parse_html(
+        r##"<html><head></head><body bgcolor="#cccccc">Hello, world!<a href="next.html" target="_blank">next</a></body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(built, parsed);
+    assert_eq!(parse_html(built.to_html()).unwrap(), built);
+}
+
 #[test]
 fn test_parse_html() {
     use pretty_assertions::assert_eq;
@@ -408,26 +1944,12 @@ fn test_parse_html() {
                         children: vec![HtmlElement {
                             name: "title".to_string(),
                             attributes: vec![],
-                            children: vec![
-                                HtmlElement {
-                                    name: "textNode".to_string(),
-                                    attributes: vec![],
-                                    children: vec![],
-                                    text_node: Some("sample".to_string()),
-                                },
-                                HtmlElement {
-                                    name: "textNode".to_string(),
-                                    attributes: vec![],
-                                    children: vec![],
-                                    text_node: Some("web".to_string()),
-                                },
-                                HtmlElement {
-                                    name: "textNode".to_string(),
-                                    attributes: vec![],
-                                    children: vec![],
-                                    text_node: Some("page".to_string()),
-                                },
-                            ],
+                            children: vec![HtmlElement {
+                                name: "textNode".to_string(),
+                                attributes: vec![],
+                                children: vec![],
+                                text_node: Some("sample web page".to_string()),
+                            }],
                             text_node: None,
                         }],
                         text_node: None,
@@ -469,6 +1991,12 @@ fn test_parse_html() {
                                 children: vec![],
                                 text_node: Some("a".to_string()),
                             },
+                            HtmlElement {
+                                name: "textNode".to_string(),
+                                attributes: vec![],
+                                children: vec![],
+                                text_node: Some(" ".to_string()),
+                            },
                             HtmlElement {
                                 name: "a".to_string(),
                                 attributes: vec![("href".to_string(), "link1.html".to_string())],
@@ -540,6 +2068,12 @@ fn test_parse_html() {
                                 children: vec![],
                                 text_node: Some("new".to_string()),
                             },
+                            HtmlElement {
+                                name: "textNode".to_string(),
+                                attributes: vec![],
+                                children: vec![],
+                                text_node: Some(" ".to_string()),
+                            },
                             HtmlElement {
                                 name: "a".to_string(),
                                 attributes: vec![("href".to_string(), "link2.html".to_string())],
@@ -647,6 +2181,78 @@ fn test_parse_html() {
     }
 }
 
+#[test]
+fn test_to_html() {
+    let cases = vec![
+        (
+            "<p class=\"note\">a  b <b>c</b></p>",
+            "<p class=\"note\">a b <b>c</b></p>",
+        ),
+        (
+            "<div>\n  <p>a</p>\n  <p>b</p>\n</div>",
+            "<div><p>a</p><p>b</p></div>",
+        ),
+        ("<p>a<br />b</p>", "<p>a<br />b</p>"),
+        (
+            "<head><title>A &lt; B</title><meta charset=\"utf-8\"></head>",
+            "<head><title>A &lt; B</title><meta charset=\"utf-8\" /></head>",
+        ),
+        (
+            "<p>a</p><p>b</p>",
+            "<html><body><p>a</p><p>b</p></body></html>",
+        ),
+        ("<p>&lt;b&gt; &amp;amp;</p>", "<p>&lt;b&gt; &amp;amp;</p>"),
+        (
+            "<a title='say \"hi\"' href=\"?x=1&amp;y=2\">a</a>",
+            "<a title=\"say &quot;hi&quot;\" href=\"?x=1&amp;y=2\">a</a>",
+        ),
+        (
+            "<style>p > b::after { content: \"&amp;\" }</style>",
+            "<style>p > b::after { content: \"&amp;\" }</style>",
+        ),
+    ];
+
+    for (source, want) in cases {
+        let html = parse_html(source.to_string()).unwrap();
+        assert_eq!(html.to_html(), want, "{}", source);
+        assert_eq!(parse_html(html.to_html()).unwrap(), html, "{}", source);
+    }
+}
+
+#[test]
+fn test_parse_void_elements() {
+    let cases = vec![
+        ("<p>a<wbr>b</p>", "<p>a<wbr />b</p>"),
+        ("<p>a<wbr/>b</p>", "<p>a<wbr />b</p>"),
+        ("<p>a<br>b<BR>c</p>", "<p>a<br />b<BR />c</p>"),
+        ("<p><hr></p>", "<p><hr /></p>"),
+        (
+            "<p><img src=\"x\"><input type=\"submit\"></p>",
+            "<p><img src=\"x\" /><input type=\"submit\" /></p>",
+        ),
+        (
+            "<head><link rel=\"stylesheet\" href=\"a.css\"><title>a</title></head>",
+            "<head><link rel=\"stylesheet\" href=\"a.css\" /><title>a</title></head>",
+        ),
+    ];
+
+    for (source, want) in cases {
+        assert_eq!(
+            parse_html(source.to_string()).unwrap().to_html(),
+            want,
+            "{}",
+            source
+        );
+    }
+
+    // unquoted values end at the `>` rather than taking the element's content along
+    let html = parse_html_lenient("<p><img src=x><input type=submit>a</p>".to_string()).unwrap();
+    assert_eq!(
+        html.to_html(),
+        "<p><img src=\"x\" /><input type=\"submit\" />a</p>"
+    );
+}
+
 #[test]
 fn test_smoke_parse_html() {
     let cases = vec![
@@ -666,3 +2272,368 @@ fn test_smoke_parse_html() {
         parse_html(case.to_string()).unwrap();
     }
 }
+
+#[test]
+fn test_parse_entities() {
+    let html = parse_html("<p>Fish&nbsp;&amp; Chips co&shy;op</p>".to_string()).unwrap();
+    assert_eq!(html.text_content(), "Fish\u{a0}& Chips co\u{ad}op");
+}
+
+#[test]
+fn test_parse_inline_svg() {
+    let source =
+        r##"<svg width="20" height="10"><rect width="20" height="10" fill="#ff0000"/></svg>"##;
+    let html = parse_html(format!("<p>{}<svg><circle r=\"5\"/></svg></p>", source)).unwrap();
+
+    let svgs = html.find_all("svg");
+    assert_eq!(svgs.len(), 2);
+    assert_eq!(svgs[0].to_html(), source);
+    assert!(html.find("rect").is_none());
+}
+
+#[test]
+fn test_parse_raw_text() {
+    let html = parse_html(
+        r#"<html><head><title>A < B &amp;
+    C</title><style>div > p { color: red; }</style></head><body><textarea name="t">  <b>bold</b> &lt;tag&gt;
+</textarea></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+
+    let metadata = html.head_metadata();
+    assert_eq!(metadata.title, Some("A < B & C".to_string()));
+    assert_eq!(metadata.styles, vec!["div > p { color: red; }".to_string()]);
+
+    let textarea = html.find("textarea").unwrap();
+    assert_eq!(
+        textarea.children,
+        vec![HtmlElement {
+            name: "textNode".to_string(),
+            attributes: vec![],
+            children: vec![],
+            text_node: Some("  <b>bold</b> <tag>\n".to_string()),
+        }]
+    );
+    assert!(html.find("b").is_none());
+
+    let empty = parse_html("<html><head><title></title></head></html>".to_string()).unwrap();
+    assert_eq!(empty.head_metadata().title, Some("".to_string()));
+}
+
+#[test]
+fn test_parse_template() {
+    // parsed like any other content, for the renderer to leave out
+    let html = parse_html(
+        "<html><body><template><p>later <b>on</b></p></template></body></html>".to_string(),
+    )
+    .unwrap();
+    let template = html.find("template").unwrap();
+    assert_eq!(template.children[0].name, "p");
+    assert_eq!(template.text_content(), "later on");
+}
+
+#[test]
+fn test_parse_multibyte() {
+    let source = r#"<p title="日本語">こんにちは、世界 🎉👍🏽 <b>太字</b></p>"#;
+    let html = parse_html(source.to_string()).unwrap();
+
+    assert_eq!(
+        html.attributes,
+        vec![("title".to_string(), "日本語".to_string())]
+    );
+    assert_eq!(
+        html.children
+            .iter()
+            .filter_map(|child| child.text_node.clone())
+            .filter(|text| !text.trim().is_empty())
+            .collect::<Vec<_>>(),
+        vec!["こんにちは、世界", "🎉👍🏽"]
+    );
+    assert_eq!(html.find("b").unwrap().text_content(), "太字");
+    assert_eq!(parse_html(html.to_html()).unwrap(), html);
+
+    // raw text is cut at its closing tag however wide the characters before it
+    let html = parse_html("<html><head><style>p::after { content: \"→\"; }</style><title>題名 &lt;〜&gt;</title></head></html>".to_string())
+        .unwrap();
+    let metadata = html.head_metadata();
+    assert_eq!(metadata.styles, vec!["p::after { content: \"→\"; }"]);
+    assert_eq!(metadata.title.as_deref(), Some("題名 <〜>"));
+}
+
+#[test]
+fn test_parse_whitespace() {
+    let children = |source: &str| {
+        parse_html(source.to_string())
+            .unwrap()
+            .children
+            .iter()
+            .map(|child| child.text_node.clone().unwrap_or(child.name.clone()))
+            .collect::<Vec<_>>()
+    };
+
+    let cases = vec![
+        ("<p>a <b>x</b>y</p>", vec!["a", " ", "b", "y"]),
+        ("<p>a<b>x</b> y</p>", vec!["a", "b", " ", "y"]),
+        ("<p><b>x</b>   <i>y</i></p>", vec!["b", " ", "i"]),
+        ("<p> a b </p>", vec!["a", "b"]),
+        ("<div>\n  <p>a</p>\n  <p>b</p>\n</div>", vec!["p", "p"]),
+        ("<p>a  <br />  b</p>", vec!["a", "br", "b"]),
+        ("<p>  </p>", vec![]),
+        ("<pre>\na  b\n</pre>", vec!["a", "  ", "b", "\n"]),
+        ("<pre>\n  a</pre>", vec!["  ", "a"]),
+        (
+            "<div style=\"white-space: pre;\"><b>a\tb</b> c</div>",
+            vec!["b", " ", "c"],
+        ),
+    ];
+
+    for (source, want) in cases {
+        assert_eq!(children(source), want, "{}", source);
+    }
+}
+
+#[test]
+fn test_parse_fragment() {
+    let p = |text: &str| HtmlElement {
+        name: "p".to_string(),
+        attributes: vec![],
+        children: vec![HtmlElement {
+            name: "textNode".to_string(),
+            attributes: vec![],
+            children: vec![],
+            text_node: Some(text.to_string()),
+        }],
+        text_node: None,
+    };
+
+    let roots = parse_fragment("<p>a</p><p>b</p>".to_string()).unwrap();
+    assert_eq!(roots, vec![p("a"), p("b")]);
+
+    let html = parse_html("<p>a</p><p>b</p>".to_string()).unwrap();
+    assert_eq!(html.name, "html");
+    assert_eq!(html.find("body").unwrap().children, vec![p("a"), p("b")]);
+
+    let html =
+        parse_html("<html><head></head><body><p>a</p></body></html><p>b</p>".to_string()).unwrap();
+    assert_eq!(html.children.len(), 2);
+    assert_eq!(html.find("body").unwrap().children, vec![p("a"), p("b")]);
+}
+
+#[test]
+fn test_parse_mismatched_close_tags() {
+    let err = parse_html("<p><b>bold</i></p>".to_string()).unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("mismatched close tag: expected </b>, found </i> (7)"),
+        "{:#}",
+        err
+    );
+
+    let err = parse_html("<p><b>bold".to_string()).unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("unclosed <b> at end of input"),
+        "{:#}",
+        err
+    );
+
+    let lenient = |source: &str| parse_html_lenient(source.to_string()).unwrap().to_html();
+    let cases = vec![
+        // crossed tags: </b> closes the <i> inside it
+        ("<p><b><i>x</b> y</i></p>", "<p><b><i>x</i></b> y</p>"),
+        (
+            "<div><p>a</div><p>b</p>",
+            "<html><body><div><p>a</p></div><p>b</p></body></html>",
+        ),
+        // stray close tags are dropped
+        ("<p>a</i> b</p>", "<p>a b</p>"),
+        ("</b><p>a</p>", "<p>a</p>"),
+        // unclosed elements end with the input
+        ("<p><b>a", "<p><b>a</b></p>"),
+        ("<P>a</p>", "<P>a</P>"),
+        ("<p><b>fine</b></p>", "<p><b>fine</b></p>"),
+    ];
+
+    for (source, want) in cases {
+        assert_eq!(lenient(source), want, "{}", source);
+    }
+}
+
+#[test]
+fn test_parse_unterminated_script() {
+    let source = "<html><body><script>x";
+
+    let err = parse_html(source.to_string()).unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("unclosed <script> at end of input"),
+        "{:#}",
+        err
+    );
+
+    assert_eq!(
+        parse_html_lenient(source.to_string()).unwrap().to_html(),
+        "<html><body><script></script></body></html>"
+    );
+}
+
+#[test]
+fn test_parse_malformed_attributes() {
+    let source = r#"<div class="a" "orphan" =broken <!-- note --> id="b" = "c" hidden title=bare lang="en"></div>"#;
+
+    assert!(parse_html(source.to_string()).is_err());
+    assert!(parse_html(r#"<div class="a""#.to_string()).is_err());
+
+    let html = parse_html_lenient(source.to_string()).unwrap();
+    assert_eq!(
+        html.attributes,
+        vec![
+            ("class".to_string(), "a".to_string()),
+            ("id".to_string(), "b".to_string()),
+            ("hidden".to_string(), "".to_string()),
+            ("title".to_string(), "bare".to_string()),
+            ("lang".to_string(), "en".to_string()),
+        ]
+    );
+
+    // comments inside a tag are skipped in either mode
+    let html = parse_html(r#"<div class="a" <!-- note --> id="b"></div>"#.to_string()).unwrap();
+    assert_eq!(html.attributes.len(), 2);
+}
+
+#[test]
+fn test_stylesheet_links() {
+    let html = parse_html(
+        r#"<html><head><link rel="stylesheet" href="a.css"><link rel="preload" href="font.woff2"><link rel="Alternate StyleSheet" href="b.css"><link rel="stylesheet"></head><body></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        html.head_metadata().stylesheet_links(),
+        vec!["a.css", "b.css"]
+    );
+}
+
+#[test]
+fn test_icon_link() {
+    let icon = |links: &str| {
+        parse_html(format!("<html><head>{}</head><body></body></html>", links))
+            .unwrap()
+            .head_metadata()
+            .icon_link()
+            .map(str::to_string)
+    };
+
+    assert_eq!(
+        icon(
+            r#"<link rel="apple-touch-icon" href="touch.svg" /><link rel="icon" href="a.svg" /><link rel="icon" href="b.svg" />"#
+        ),
+        Some("a.svg".to_string())
+    );
+    assert_eq!(
+        icon(r#"<link rel="Shortcut Icon" href="old.svg" />"#),
+        Some("old.svg".to_string())
+    );
+    assert_eq!(icon(r#"<link rel="stylesheet" href="a.css" />"#), None);
+}
+
+#[test]
+fn test_head_metadata() {
+    let html = parse_html(
+        r##"<html>
+  <head>
+    <meta charset="UTF-8">
+    <title>sample web page</title>
+    <link rel="stylesheet" href="style.css" />
+    <base href="/sub/">
+    <base href="/ignored/">
+    <style>p { color: red; }</style>
+  </head>
+  <body>content</body>
+</html>"##
+            .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        html.head_metadata(),
+        HeadMetadata {
+            title: Some("sample web page".to_string()),
+            meta: vec![vec![("charset".to_string(), "UTF-8".to_string())]],
+            links: vec![vec![
+                ("rel".to_string(), "stylesheet".to_string()),
+                ("href".to_string(), "style.css".to_string()),
+            ]],
+            styles: vec!["p { color: red; }".to_string()],
+            base: Some("/sub/".to_string()),
+        }
+    );
+}
+
+#[test]
+fn test_head_metadata_without_head() {
+    let html = parse_html(
+        r#"<html><title>stray  title</title><body><meta name="a" content="b" /><svg><title>drawing</title></svg></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let metadata = html.head_metadata();
+    assert_eq!(metadata.title, Some("stray title".to_string()));
+    assert_eq!(
+        metadata.meta,
+        vec![vec![
+            ("name".to_string(), "a".to_string()),
+            ("content".to_string(), "b".to_string()),
+        ]]
+    );
+
+    // a <title> in <head> wins over one elsewhere
+    let html = parse_html(
+        "<html><head><title>head</title></head><body><title>body</title></body></html>".to_string(),
+    )
+    .unwrap();
+    assert_eq!(html.head_metadata().title, Some("head".to_string()));
+}
+
+#[test]
+fn test_head_metadata_noscript() {
+    let html = parse_html(
+        r#"<html><head><noscript><style>p { color: red; }</style></noscript></head><body></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        html.head_metadata().styles,
+        vec!["p { color: red; }".to_string()]
+    );
+}
+
+#[test]
+fn test_meta_refresh() {
+    let cases = vec![
+        (
+            r#"<meta http-equiv="refresh" content="5;url=next.html">"#,
+            Some((5, Some("next.html"))),
+        ),
+        (
+            r#"<meta http-equiv="Refresh" content="0; URL='next.html'">"#,
+            Some((0, Some("next.html"))),
+        ),
+        (
+            r#"<meta http-equiv="refresh" content="3">"#,
+            Some((3, None)),
+        ),
+        (r#"<meta http-equiv="refresh" content="soon">"#, None),
+        (r#"<meta charset="UTF-8">"#, None),
+    ];
+
+    for (meta, want) in cases {
+        let html = parse_html(format!("<html><head>{}</head><body></body></html>", meta)).unwrap();
+        assert_eq!(
+            html.head_metadata().refresh(),
+            want.map(|(delay, target)| (delay, target.map(|t| t.to_string()))),
+            "{}",
+            meta
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! Fetching aside, everything that turns markup into pixels: parsing, the cascade and painting.
+//! The browser binary drives it from its event loop, and other tools can render pages headlessly.
+
+pub mod cascade;
+pub mod css;
+pub mod helper;
+pub mod html;
+pub mod process;
+pub mod render;
+pub mod url;
@@ -1,58 +1,588 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroU32;
 use std::process::Command;
-use std::rc::Rc;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
+use byo_browser::{cascade, css, helper, html, process, render, url};
+use cascade::DarkMode;
+use css::{Styles, StylesheetCache};
 use helper::default_typeface;
-use html::{HtmlElement, NodeTrace};
+use html::HtmlElement;
 use process::DroppableProcess;
-use skia_safe::{Font, Paint, Rect, TextBlob};
+use render::{
+    Disclosure, FontSettings, Frame, Image, Link, PaintExt, RenderOptions, RenderStats, TextCache,
+    TextField, Viewport,
+};
+use skia_safe::{svg, FontMgr, Rect, TextBlob};
+use tokio::task::AbortHandle;
+use url::{base_path, is_absolute_url, non_page_scheme, page_url, resolve_path};
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, StartCause, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
-use winit::window::{Window, WindowId};
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+use winit::window::{CursorIcon, Window, WindowId};
 
-mod css;
-mod helper;
-mod html;
-mod process;
+#[cfg(test)]
+mod snapshot;
 
-struct RendererState {
-    hyper_links: Vec<(Rect, String)>,
-    current_color: String,
-    cursor_position: (f32, f32),
-    layout: HashMap<String, String>,
-}
+/// Default distance scrolled by an arrow key or one wheel notch.
+const SCROLL_LINE: f32 = 36.0;
 
-#[derive(Default)]
-pub struct PaintExt(pub Paint);
+/// How often a smooth scroll steps, and how much of the way left it covers each step.
+const SMOOTH_SCROLL_FRAME: std::time::Duration = std::time::Duration::from_millis(16);
+const SMOOTH_SCROLL_STEP: f32 = 0.3;
 
-impl PaintExt {
-    pub fn set_color_hex(&mut self, hex: &str) {
-        let color = hex.trim_start_matches("#");
-        let color = u32::from_str_radix(color, 16).unwrap();
-        self.set_color_u32(color);
-    }
+/// How long the mouse has to rest on a titled element before its tooltip shows.
+const TOOLTIP_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
 
-    pub fn set_color_u32(&mut self, color: u32) {
-        self.0.set_argb(
-            0xFF,
-            (color >> 16) as u8 & 0xFF,
-            (color >> 8) as u8 & 0xFF,
-            color as u8 & 0xFF,
-        );
-    }
+/// Default limit on the size of a page, in bytes.
+const MAX_PAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Width and height of the favicon in the title bar.
+const FAVICON_SIZE: f32 = 24.0;
+
+/// How long the focused field's caret shows, and then hides, as it blinks.
+const CARET_BLINK: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How long each step of the loading spinner is shown.
+const SPINNER_FRAME: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// What a navigation hook makes of a page about to be fetched.
+#[derive(Debug, Clone, PartialEq)]
+enum NavigationDecision {
+    Allow,
+    /// Show an error page instead.
+    Block,
+    /// Fetch this URL, or path on the host, in its place.
+    Redirect(String),
 }
 
+/// Called with the URL of each page before it is fetched.
+type NavigationHook = Box<dyn Fn(&str) -> NavigationDecision>;
+
 #[derive(Default)]
 struct App {
     host: String,
     path: String,
     html: Arc<Mutex<Option<HtmlElement>>>,
+    /// The current page's response body as it arrived, in place by the time `html` is, or `None`
+    /// if it failed to load.
+    source: Arc<Mutex<Option<String>>>,
+    /// The sheets the current page links to, in place by the time `html` is.
+    linked_styles: Arc<Mutex<Vec<Styles>>>,
+    /// SVG sources of the current page's `<img>`s by `src`, also in place by the time `html` is.
+    images: Arc<Mutex<Vec<Image>>>,
+    /// The documents of the current page's `<iframe>`s by `src`, also in place by the time `html`
+    /// is.
+    frames: Arc<Mutex<Vec<Frame>>>,
+    /// SVG source of the current page's `<link rel="icon">`, also in place by the time `html` is,
+    /// or `None` if it has none that loaded.
+    favicon: Arc<Mutex<Option<Vec<u8>>>>,
+    /// Linked sheets by URL, kept across pages.
+    stylesheets: Arc<Mutex<StylesheetCache>>,
+    /// When the fetch of the current page started, while it is in flight.
+    loading: Option<std::time::Instant>,
+    /// The fetch of the current page, aborted on navigating away so it can't arm a refresh or
+    /// record a load for a page no longer shown.
+    load_task: Option<AbortHandle>,
+    /// Source read from stdin, shown as the first page in place of fetching it.
+    piped_source: Option<String>,
+    /// Largest page body accepted, in bytes, or `None` for `MAX_PAGE_SIZE`.
+    max_page_size: Option<usize>,
     window: Arc<Mutex<Option<Window>>>,
     mouse_cursor_position: Mutex<(f32, f32)>,
-    hyper_links: Arc<Mutex<Vec<(Rect, String)>>>,
+    hyper_links: Arc<Mutex<Vec<(Rect, Link)>>>,
+    disclosures: Vec<(Rect, Disclosure)>,
+    /// Open state of each `<details>` on the page the reader toggled, by its key.
+    expanded: HashMap<String, bool>,
+    text_fields: Vec<(Rect, TextField)>,
+    /// What the reader made of each `<textarea>` they typed into, by its key.
+    field_values: HashMap<String, String>,
+    /// The `<textarea>` last clicked, which typing goes into.
+    focused_field: Option<String>,
+    /// When the caret last came on, which focusing or typing into a field restarts its blink
+    /// from.
+    caret_shown: Option<std::time::Instant>,
+    viewport: Viewport,
+    /// Paths navigated to during this session, for `a:visited`.
+    visited: HashSet<String>,
+    text_runs: Vec<(Rect, String)>,
+    /// Text runs inside inline elements with a `title`, and the title shown over them.
+    titles: Vec<(Rect, String)>,
+    /// The title under the mouse, since when, and where in the window the mouse came onto it.
+    hover: Option<(String, std::time::Instant, (f32, f32))>,
+    text_cache: Mutex<TextCache>,
+    /// Drag anchor and current end of the text selection.
+    selection: Option<((f32, f32), (f32, f32))>,
+    mouse_pressed: bool,
+    modifiers: ModifiersState,
+    /// Set by a `<meta http-equiv="refresh">` timer, applied on the next redraw.
+    pending_navigation: Arc<Mutex<Option<String>>>,
+    refresh_task: Arc<Mutex<Option<AbortHandle>>>,
+    scroll_offset: f32,
+    /// Where a smooth scroll under way is heading.
+    scroll_destination: Option<f32>,
+    /// Distance scrolled by an arrow key or one wheel notch.
+    scroll_line: f32,
+    /// Animate scrolling over a few frames rather than jumping.
+    smooth_scroll: bool,
+    /// Largest useful `scroll_offset` for the current page, updated on each redraw.
+    max_scroll: f32,
+    blank_targets: BlankTargets,
+    /// Hand `mailto:` and `tel:` links to the platform's URL handler instead of ignoring them.
+    open_external_schemes: bool,
+    zoom: f32,
+    fonts: FontSettings,
+    /// The reader's stylesheet, parsed once at startup.
+    user_styles: Option<Styles>,
+    /// Toggled with Ctrl+D.
+    dark_mode: bool,
+    /// In dark mode, override the colors pages set for themselves too.
+    force_dark: bool,
+    /// Paint without antialiasing.
+    crisp_edges: bool,
+    /// Don't fetch images, drawing their alt text in their place. Toggled with Ctrl+I.
+    block_images: bool,
+    nav_events: Arc<Mutex<Vec<NavEvent>>>,
+    /// Print the navigation history when the window closes.
+    print_nav_history: bool,
+    /// Time each render, with F12 showing the timings over the page.
+    profile: bool,
+    show_profile: bool,
+    render_stats: Option<RenderStats>,
+    /// Consulted before each page fetch. Every page is allowed if unset.
+    navigation_hook: Option<NavigationHook>,
+}
+
+/// One page load, as recorded in the navigation history.
+#[derive(Debug, Clone, PartialEq)]
+struct NavEvent {
+    url: String,
+    /// HTTP status, or `None` if the request itself failed.
+    status: Option<u16>,
+    bytes: usize,
+    tokenize_duration: std::time::Duration,
+    parse_duration: std::time::Duration,
+}
+
+/// What clicking a `target="_blank"` link does. There is only one window, so by default the
+/// request is just reported instead of replacing the current page.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum BlankTargets {
+    /// Navigate in place, like any other link.
+    SameWindow,
+    #[default]
+    Report,
+    /// Hand the URL to the system's default browser.
+    External,
+}
+
+impl std::str::FromStr for BlankTargets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "same-window" => Ok(BlankTargets::SameWindow),
+            "report" => Ok(BlankTargets::Report),
+            "external" => Ok(BlankTargets::External),
+            _ => Err(format!("Unknown --blank-targets value: {}", s)),
+        }
+    }
+}
+
+impl App {
+    fn navigate(&mut self, path: String) {
+        if let Some(task) = self.load_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.refresh_task.lock().unwrap().take() {
+            task.abort();
+        }
+
+        self.visited.insert(path.clone());
+        self.path = path;
+        self.html = Arc::new(Mutex::new(None));
+        self.source = Arc::new(Mutex::new(None));
+        self.linked_styles = Arc::new(Mutex::new(vec![]));
+        self.images = Arc::new(Mutex::new(vec![]));
+        self.frames = Arc::new(Mutex::new(vec![]));
+        self.favicon = Arc::new(Mutex::new(None));
+        self.loading = None;
+        self.hyper_links.lock().unwrap().clear();
+        self.disclosures.clear();
+        self.expanded.clear();
+        self.text_fields.clear();
+        self.field_values.clear();
+        self.focused_field = None;
+        self.caret_shown = None;
+        self.text_runs.clear();
+        self.titles.clear();
+        self.hover = None;
+        self.text_cache.lock().unwrap().clear();
+        self.selection = None;
+        self.scroll_offset = 0.0;
+        self.scroll_destination = None;
+        self.max_scroll = 0.0;
+
+        if let Some(window) = self.window.lock().unwrap().as_ref() {
+            window.request_redraw();
+        }
+    }
+
+    /// Goes where a clicked link leads. A `javascript:` link does nothing, as scripts never run,
+    /// and a `mailto:` or `tel:` one is only handed to the platform with `open_external_schemes`.
+    fn follow_link(&mut self, link: &Link) {
+        if let Some(scheme) = non_page_scheme(&link.href) {
+            if scheme != "javascript" && self.open_external_schemes {
+                let url = link.href.trim();
+                if let Err(err) = open_external(url) {
+                    eprintln!("Failed to open {}: {}", url, err);
+                }
+            }
+            return;
+        }
+
+        let path = resolve_path(&self.host, &self.base(), &link.href);
+        match (link.target.as_deref(), self.blank_targets) {
+            (Some("_blank"), BlankTargets::Report) => {
+                println!("Link asks for a new window: {}", path);
+            }
+            (Some("_blank"), BlankTargets::External) => {
+                let url = page_url(&self.host, &path);
+                if let Err(err) = open_external(&url) {
+                    eprintln!("Failed to open {}: {}", url, err);
+                }
+            }
+            _ => self.navigate(path),
+        }
+    }
+
+    /// Fetches and parses the current page in the background, showing the loading indicator
+    /// until it arrives.
+    fn start_loading(&mut self) {
+        self.loading = Some(std::time::Instant::now());
+
+        // a redirect is taken as it is, not run past the hook again
+        let decision = self
+            .navigation_hook
+            .as_ref()
+            .map_or(NavigationDecision::Allow, |hook| {
+                hook(&page_url(&self.host, &self.path))
+            });
+        if let NavigationDecision::Redirect(target) = &decision {
+            self.path = resolve_path(&self.host, &self.path, target);
+        }
+        let blocked = decision == NavigationDecision::Block;
+        let piped_source = self.piped_source.take();
+
+        let html = self.html.clone();
+        let source = self.source.clone();
+        let linked_styles = self.linked_styles.clone();
+        let stylesheets = self.stylesheets.clone();
+        let images = self.images.clone();
+        let frames = self.frames.clone();
+        let favicon = self.favicon.clone();
+        let block_images = self.block_images;
+        let window = self.window.clone();
+        let url = page_url(&self.host, &self.path);
+        let max_size = self.max_page_size.unwrap_or(MAX_PAGE_SIZE);
+        let host = self.host.clone();
+        let path = self.path.clone();
+        let pending_navigation = self.pending_navigation.clone();
+        let refresh_task = self.refresh_task.clone();
+        let nav_events = self.nav_events.clone();
+        let task = tokio::spawn(async move {
+            let mut event = NavEvent {
+                url: url.clone(),
+                status: None,
+                bytes: 0,
+                tokenize_duration: std::time::Duration::ZERO,
+                parse_duration: std::time::Duration::ZERO,
+            };
+            let mut body = None;
+            // a piped page is taken as served, with its links resolving against its URL
+            let fetched = if let Some(source) = piped_source {
+                Ok((200, source))
+            } else if blocked {
+                Err("The page was blocked".to_string())
+            } else {
+                fetch(url.clone(), max_size).await.map_err(|err| {
+                    if let FetchError::Status(status) = err {
+                        event.status = Some(status);
+                    }
+                    err.to_string()
+                })
+            };
+            let element = match fetched {
+                Ok((status, resp)) => {
+                    event.status = Some(status);
+                    event.bytes = resp.len();
+
+                    let mut parse = |lenient| {
+                        let (element, timings) = html::parse_html_timed(resp.clone(), lenient);
+                        event.tokenize_duration += timings.tokenize;
+                        event.parse_duration += timings.parse;
+                        element
+                    };
+                    let element = parse(false).or_else(|err| {
+                        eprintln!("Recovering from parse error: {:#}", err);
+                        parse(true)
+                    });
+
+                    body = Some(resp);
+                    element.map_err(|err| format!("{:#}", err))
+                }
+                Err(message) => Err(message),
+            }
+            .unwrap_or_else(|message| error_page(&url, &message));
+            nav_events.lock().unwrap().push(event);
+            let metadata = element.head_metadata();
+            let refresh = metadata.refresh();
+            let base = base_path(&host, &path, metadata.base.as_deref());
+            *linked_styles.lock().unwrap() = load_stylesheets(
+                &stylesheets,
+                &host,
+                &base,
+                &metadata.stylesheet_links(),
+                max_size,
+            )
+            .await;
+            // the placeholders of blocked images need nothing fetched
+            if !block_images {
+                *images.lock().unwrap() = load_images(&host, &base, &element, max_size).await;
+                *favicon.lock().unwrap() =
+                    load_favicon(&host, &base, metadata.icon_link(), max_size).await;
+            }
+            *frames.lock().unwrap() = load_frames(&host, &base, &element, max_size).await;
+            *source.lock().unwrap() = body;
+            *html.lock().unwrap() = Some(element);
+
+            if let Some(window) = window.lock().unwrap().as_ref() {
+                window.request_redraw();
+            }
+
+            if let Some((delay, target)) = refresh {
+                let target = target
+                    .map(|target| resolve_path(&host, &base, &target))
+                    .unwrap_or(path);
+
+                let task = tokio::spawn(async move {
+                    tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
+
+                    *pending_navigation.lock().unwrap() = Some(target);
+                    if let Some(window) = window.lock().unwrap().as_ref() {
+                        window.request_redraw();
+                    }
+                });
+                *refresh_task.lock().unwrap() = Some(task.abort_handle());
+            }
+        });
+        self.load_task = Some(task.abort_handle());
+    }
+
+    /// How long the current page has been loading, or `None` once it has arrived.
+    fn loading(&mut self) -> Option<std::time::Duration> {
+        if self.html.lock().unwrap().is_some() {
+            self.loading = None;
+        }
+
+        self.loading.map(|started| started.elapsed())
+    }
+
+    /// When the window next needs redrawing for something that moves on its own: the loading
+    /// spinner's next step, a smooth scroll's next step, the caret's next blink or a tooltip
+    /// coming up. `None` if nothing is waiting to, so the event loop can sleep until the next
+    /// event.
+    fn next_frame(&self, now: std::time::Instant) -> Option<std::time::Instant> {
+        let spinner = self.loading.map(|started| {
+            let steps = now.duration_since(started).as_millis() / SPINNER_FRAME.as_millis() + 1;
+            started + SPINNER_FRAME * steps as u32
+        });
+        let tooltip = self
+            .hover
+            .as_ref()
+            .map(|(_, since, _)| *since + TOOLTIP_DELAY)
+            .filter(|at| *at > now);
+        let scroll = self.scroll_destination.map(|_| now + SMOOTH_SCROLL_FRAME);
+        let caret = self
+            .focused_field
+            .as_ref()
+            .and(self.caret_shown)
+            .map(|since| {
+                let steps = now.duration_since(since).as_millis() / CARET_BLINK.as_millis() + 1;
+                since + CARET_BLINK * steps as u32
+            });
+
+        spinner
+            .into_iter()
+            .chain(tooltip)
+            .chain(scroll)
+            .chain(caret)
+            .min()
+    }
+
+    /// Every page load of this session, oldest first.
+    fn nav_history(&self) -> Vec<NavEvent> {
+        self.nav_events.lock().unwrap().clone()
+    }
+
+    /// How long the current page took to tokenize and parse, and the last redraw to lay out
+    /// and paint, one stage per line.
+    fn profile_lines(&self) -> Vec<String> {
+        let event = self.nav_events.lock().unwrap().last().cloned();
+        let stages = [
+            (
+                "tokenize",
+                event.as_ref().map(|event| event.tokenize_duration),
+            ),
+            ("parse", event.as_ref().map(|event| event.parse_duration)),
+            ("layout", self.render_stats.map(|stats| stats.layout)),
+            ("paint", self.render_stats.map(|stats| stats.paint)),
+        ];
+
+        stages
+            .into_iter()
+            .map(|(stage, duration)| match duration {
+                Some(duration) => format!("{} {:.2}ms", stage, duration.as_secs_f64() * 1000.0),
+                None => format!("{} -", stage),
+            })
+            .collect()
+    }
+
+    /// Whether the link `href` on a document with the `base` URL leads to a page navigated to
+    /// this session.
+    fn is_visited(&self, base: &str, href: &str) -> bool {
+        self.visited.contains(&resolve_path(&self.host, base, href))
+    }
+
+    /// Path that links on the current page resolve against.
+    fn base(&self) -> String {
+        let base = self
+            .html
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|html| html.head_metadata().base);
+
+        base_path(&self.host, &self.path, base.as_deref())
+    }
+
+    fn scroll_to(&mut self, offset: f32) {
+        let offset = clamp_scroll(offset, self.max_scroll);
+        if offset == self.scroll_destination() {
+            return;
+        }
+        if self.smooth_scroll {
+            // `next_frame` keeps the redraws coming until `step_scroll` gets there
+            self.scroll_destination = Some(offset);
+        } else {
+            self.scroll_offset = offset;
+        }
+
+        let window = self.window.lock().unwrap();
+        window.as_ref().unwrap().request_redraw();
+    }
+
+    /// Where the page is scrolling to, so that scrolling again while a smooth scroll is under
+    /// way adds to it.
+    fn scroll_destination(&self) -> f32 {
+        self.scroll_destination.unwrap_or(self.scroll_offset)
+    }
+
+    /// Moves a smooth scroll under way on by a frame.
+    fn step_scroll(&mut self) {
+        if let Some(destination) = self.scroll_destination {
+            let destination = clamp_scroll(destination, self.max_scroll);
+            self.scroll_offset = smooth_scroll_step(self.scroll_offset, destination);
+            if self.scroll_offset == destination {
+                self.scroll_destination = None;
+            }
+        }
+    }
+
+    /// Focuses the field clicked at document position `pos`, unless it is disabled, taking the
+    /// focus off any other. Returns whether the focus moved.
+    fn focus_field(&mut self, pos: (f32, f32)) -> bool {
+        let field = hit_test(&self.text_fields, pos)
+            .filter(|field| !field.disabled)
+            .map(|field| field.key.clone());
+        if field == self.focused_field {
+            return false;
+        }
+        self.focused_field = field;
+        self.caret_shown = Some(std::time::Instant::now());
+
+        true
+    }
+
+    /// Applies `key` to the focused field, unless it is read-only. Returns whether that changed
+    /// the field.
+    fn type_into_field(&mut self, key: &Key) -> bool {
+        let Some((_, field)) = self
+            .text_fields
+            .iter()
+            .find(|(_, field)| Some(&field.key) == self.focused_field.as_ref())
+        else {
+            return false;
+        };
+        if field.readonly {
+            return false;
+        }
+
+        let value = self
+            .field_values
+            .entry(field.key.clone())
+            .or_insert(field.value.clone());
+        let changed = edit_field(value, key);
+        if changed {
+            self.caret_shown = Some(std::time::Instant::now());
+        }
+
+        changed
+    }
+
+    /// Whether the caret is in the on half of its blink at `now`.
+    fn caret_visible(&self, now: std::time::Instant) -> bool {
+        self.caret_shown.is_none_or(|since| {
+            (now.duration_since(since).as_millis() / CARET_BLINK.as_millis()).is_multiple_of(2)
+        })
+    }
+
+    /// Notes which title, if any, the mouse is on at window position `pos`. Returns whether that
+    /// changed.
+    fn hover(&mut self, pos: (f32, f32)) -> bool {
+        let title = self
+            .document_position(pos)
+            .and_then(|pos| hit_test(&self.titles, pos))
+            .cloned();
+
+        match (&self.hover, title) {
+            (Some((hovered, _, _)), Some(title)) if *hovered == title => false,
+            (None, None) => false,
+            (_, title) => {
+                self.hover = title.map(|title| (title, std::time::Instant::now(), pos));
+                true
+            }
+        }
+    }
+
+    /// The title to show and where the mouse came onto it, once it has rested there for
+    /// `TOOLTIP_DELAY`.
+    fn tooltip(&self) -> Option<(&str, (f32, f32))> {
+        self.hover
+            .as_ref()
+            .filter(|(_, since, _)| since.elapsed() >= TOOLTIP_DELAY)
+            .map(|(title, _, pos)| (title.as_str(), *pos))
+    }
+
+    /// Maps a window position to document coordinates, or `None` over the chrome.
+    fn document_position(&self, pos: (f32, f32)) -> Option<(f32, f32)> {
+        if pos.1 < self.viewport.top {
+            return None;
+        }
+
+        Some((pos.0, pos.1 + self.scroll_offset))
+    }
 }
 
 impl ApplicationHandler for App {
@@ -64,13 +594,50 @@ impl ApplicationHandler for App {
         )));
     }
 
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        if let StartCause::ResumeTimeReached { .. } = cause {
+            if let Some(window) = self.window.lock().unwrap().as_ref() {
+                window.request_redraw();
+            }
+        }
+    }
+
+    // sleep until whatever animates next is due, rather than polling for it
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        event_loop.set_control_flow(match self.next_frame(std::time::Instant::now()) {
+            Some(at) => ControlFlow::WaitUntil(at),
+            None => ControlFlow::Wait,
+        });
+    }
+
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
+                if self.print_nav_history {
+                    for event in self.nav_history() {
+                        println!(
+                            "{} {} {} bytes, tokenized in {:?}, parsed in {:?}",
+                            event
+                                .status
+                                .map_or("---".to_string(), |status| status.to_string()),
+                            event.url,
+                            event.bytes,
+                            event.tokenize_duration,
+                            event.parse_duration
+                        );
+                    }
+                }
                 event_loop.exit();
             }
             WindowEvent::RedrawRequested => {
+                let pending_navigation = self.pending_navigation.lock().unwrap().take();
+                if let Some(path) = pending_navigation {
+                    self.navigate(path);
+                }
+                let loading = self.loading();
+                self.step_scroll();
+
                 {
                     let window_lock = self.window.lock();
                     let window_guard = window_lock.as_ref().unwrap();
@@ -95,7 +662,8 @@ impl ApplicationHandler for App {
                     let canvas = raster_surface.canvas();
                     canvas.clear(0xFFFFFFFF);
 
-                    let mut paint = Paint::default();
+                    let anti_alias = !self.crisp_edges;
+                    let mut paint = PaintExt::new(anti_alias).0;
 
                     paint.set_argb(0xFF, 0x99, 0x99, 0x99);
                     canvas.draw_rect(Rect::new(0.0, 0.0, width as f32, 50.0), &paint);
@@ -107,264 +675,328 @@ impl ApplicationHandler for App {
                     canvas.draw_rect(Rect::new(20.0, 60.0, 1000.0, 110.0), &paint);
 
                     let text = TextBlob::from_str(
-                        format!("http://{}/{}", self.host, self.path),
-                        &Font::from_typeface(default_typeface(), 32.0),
+                        page_url(&self.host, &self.path),
+                        &render::font(default_typeface(), 32.0, anti_alias),
                     )
                     .unwrap();
 
                     paint.set_argb(0xFF, 0x00, 0x00, 0x00);
                     canvas.draw_text_blob(&text, (25, 60 + 36), &paint);
 
+                    if let Some(elapsed) = loading {
+                        draw_spinner(canvas, (width as f32 - 30.0, 25.0), elapsed, anti_alias);
+                    }
+
+                    let favicon = Rect::from_xywh(
+                        20.0,
+                        (50.0 - FAVICON_SIZE) / 2.0,
+                        FAVICON_SIZE,
+                        FAVICON_SIZE,
+                    );
+                    draw_favicon(
+                        canvas,
+                        self.favicon.lock().unwrap().as_deref(),
+                        favicon,
+                        anti_alias,
+                    );
+
                     if let Some(html) = self.html.lock().unwrap().as_ref() {
-                        let mut state = RendererState {
-                            hyper_links: Vec::new(),
-                            current_color: "#000000".to_string(),
-                            cursor_position: (25.0, 120.0 + 36.0),
-                            layout: HashMap::new(),
-                        };
-
-                        html.walk(
-                            Rc::new(
-                                move |trace: NodeTrace,
-                                      name: String,
-                                      index: usize,
-                                      attributes: Vec<(String, String)>,
-                                      children: Vec<HtmlElement>,
-                                      text_node: Option<String>,
-                                      state: &mut RendererState| {
-                                    if let Some((_, style)) =
-                                        attributes.iter().find(|(key, _)| key == "style")
-                                    {
-                                        let styles = css::parse_css(style.clone()).unwrap();
-
-                                        for style in styles.styles {
-                                            if let Some((_, display)) =
-                                                style.rules.iter().find(|(key, _)| key == "display")
-                                            {
-                                                if display == "flex" {
-                                                    let (_, gap_str) = style
-                                                        .rules
-                                                        .iter()
-                                                        .find(|(key, _)| key == "gap")
-                                                        .unwrap();
-
-                                                    for i in 0..children.len() {
-                                                        if i == 0 {
-                                                            continue;
-                                                        }
-
-                                                        state.layout.insert(
-                                                            trace.names().join(":")
-                                                                + ":"
-                                                                + children[i].name.as_str()
-                                                                + format!("[{}]", i).as_str()
-                                                                + "."
-                                                                + "gap-left",
-                                                            gap_str.clone(),
-                                                        );
-                                                    }
-                                                }
-                                            }
-                                        }
-
-                                        println!("{:?}", state.layout);
-                                    }
-
-                                    println!("{:?} ({:?}:{:?})", trace, name, text_node);
-                                    let mut paint = PaintExt::default();
-
-                                    if trace.names().ends_with(&["title".to_string()]) {
-                                        let mut title = String::new();
-                                        for child in children {
-                                            title.push_str(&child.text_node.unwrap());
-                                            title.push_str(" ");
-                                        }
-
-                                        println!("Title: {}", title);
-
-                                        let text = TextBlob::from_str(
-                                            title,
-                                            &Font::from_typeface(default_typeface(), 32.0),
-                                        );
-                                        if let Some(text) = text {
-                                            paint.set_color_hex("#000000");
-                                            canvas.draw_text_blob(&text, (25, 5 + 32), &paint.0);
-                                        }
-                                    } else if name == "body" {
-                                        for (key, value) in attributes {
-                                            if key == "bgcolor" {
-                                                paint.set_color_hex(&value);
-                                                canvas.draw_rect(
-                                                    Rect::new(
-                                                        0.0,
-                                                        120.0,
-                                                        width as f32,
-                                                        height as f32,
-                                                    ),
-                                                    &paint.0,
-                                                );
-                                            } else if key == "text" {
-                                                state.current_color = value.clone();
-                                            }
-                                        }
-                                    } else if trace.names().contains(&"body".to_string()) {
-                                        let is_anchor = trace.names().ends_with(&["a".to_string()]);
-                                        let is_text_node = text_node.is_some();
-                                        if let Some(text_node) = text_node {
-                                            let mut paint = PaintExt::default();
-                                            let font =
-                                                Font::from_typeface(default_typeface(), 32.0);
-
-                                            let text = TextBlob::from_str(&text_node, &font);
-                                            if let Some(text) = text {
-                                                if is_anchor {
-                                                    paint.0.set_argb(0xFF, 0x00, 0x55, 0xFF);
-                                                } else {
-                                                    paint.set_color_hex(&state.current_color);
-                                                }
-                                                let pos = state.cursor_position;
-                                                canvas.draw_text_blob(
-                                                    &text,
-                                                    (pos.0, pos.1),
-                                                    &paint.0,
-                                                );
-
-                                                if is_anchor {
-                                                    let (_, rect) = font
-                                                        .measure_str(&text_node, Some(&paint.0));
-
-                                                    println!("Hyperlink: {:?}", attributes);
-
-                                                    let (_, attributes) = trace.0.last().unwrap();
-
-                                                    state.hyper_links.push((
-                                                        Rect::new(
-                                                            pos.0,
-                                                            pos.1 - 32.0,
-                                                            pos.0 + rect.width(),
-                                                            pos.1 + rect.height() - 32.0,
-                                                        ),
-                                                        attributes
-                                                            .iter()
-                                                            .find(|(key, _)| key == "href")
-                                                            .unwrap()
-                                                            .1
-                                                            .clone(),
-                                                    ));
-                                                }
-
-                                                let (_, rect) =
-                                                    font.measure_str(text_node, Some(&paint.0));
-                                                state.cursor_position =
-                                                    (pos.0 + rect.width(), pos.1);
-                                            }
-                                        }
-
-                                        if name == "br" {
-                                            state.cursor_position =
-                                                (25.0, state.cursor_position.1 + 36.0);
-                                        } else {
-                                            let gap = if let Some(gap_left) = state.layout.get(
-                                                &(trace.names().join(":")
-                                                    + format!("[{}]", index).as_str()
-                                                    + "."
-                                                    + "gap-left"),
-                                            ) {
-                                                if !is_text_node {
-                                                    gap_left
-                                                        .trim_end_matches("px")
-                                                        .parse::<f32>()
-                                                        .unwrap()
-                                                } else {
-                                                    8.0
-                                                }
-                                            } else {
-                                                if is_text_node {
-                                                    8.0
-                                                } else {
-                                                    0.0
-                                                }
-                                            };
-
-                                            state.cursor_position = (
-                                                state.cursor_position.0 + gap,
-                                                state.cursor_position.1,
-                                            );
-                                        }
-                                    }
-                                },
-                            ),
-                            Rc::new(
-                                move |trace: NodeTrace, name: String, state: &mut RendererState| {
-                                    if name == "div" {
-                                        state.cursor_position =
-                                            (25.0, state.cursor_position.1 + 36.0);
-                                    }
-
-                                    let mut key_to_remove = vec![];
-                                    for key in state.layout.keys() {
-                                        if key.starts_with(&(trace.names().join(":") + ":")) {
-                                            key_to_remove.push(key.clone());
-                                        }
-                                    }
-
-                                    for key in key_to_remove {
-                                        state.layout.remove(&key);
-                                    }
+                        let head = html.head_metadata();
+                        if let Some(title) = head.title {
+                            eprintln!("Title: {}", title);
+
+                            let font = render::font(default_typeface(), 32.0, anti_alias);
+                            if let Some(text) = TextBlob::from_str(title, &font) {
+                                canvas.draw_text_blob(&text, (favicon.right + 8.0, 37.0), &paint);
+                            }
+                        }
+
+                        let base = base_path(&self.host, &self.path, head.base.as_deref());
+                        let is_visited = |href: &str| self.is_visited(&base, href);
+                        let details_open = |key: &str| self.expanded.get(key).copied();
+                        let field_value = |key: &str| self.field_values.get(key).cloned();
+                        let state = render::render(
+                            canvas,
+                            html,
+                            width,
+                            height,
+                            &RenderOptions {
+                                viewport: self.viewport,
+                                scroll_offset: self.scroll_offset,
+                                zoom: self.zoom,
+                                fonts: self.fonts,
+                                is_visited: &is_visited,
+                                details_open: &details_open,
+                                field_value: &field_value,
+                                focused_field: self.focused_field.as_deref(),
+                                caret_visible: self.caret_visible(std::time::Instant::now()),
+                                linked_styles: &self.linked_styles.lock().unwrap(),
+                                images: &self.images.lock().unwrap(),
+                                frames: &self.frames.lock().unwrap(),
+                                block_images: self.block_images,
+                                user_styles: self.user_styles.as_ref(),
+                                dark_mode: match (self.dark_mode, self.force_dark) {
+                                    (false, _) => DarkMode::Off,
+                                    (true, false) => DarkMode::On,
+                                    (true, true) => DarkMode::Forced,
                                 },
-                            ),
-                            &mut state,
+                                anti_alias,
+                                profile: self.profile,
+                            },
+                            &mut self.text_cache.lock().unwrap(),
                         );
 
+                        if let Some((from, to)) = self.selection {
+                            let mut paint = PaintExt::new(anti_alias).0;
+                            paint.set_argb(0x55, 0x33, 0x88, 0xFF);
+
+                            canvas.save();
+                            canvas.translate((0.0, -self.scroll_offset));
+                            for (rect, _) in selected_runs(&state.text_runs, from, to) {
+                                canvas.draw_rect(rect, &paint);
+                            }
+                            canvas.restore();
+                        }
+                        self.max_scroll = (state.document_height
+                            - (height as f32 - self.viewport.top - self.viewport.bottom))
+                            .max(0.0);
+                        self.text_runs = state.text_runs;
+                        self.titles = state.titles;
+                        self.disclosures = state.disclosures;
+                        self.text_fields = state.text_fields;
+                        self.render_stats = state.stats;
+
                         *self.hyper_links.lock().unwrap() = state.hyper_links;
                     }
 
-                    let pixdata = canvas.peek_pixels().unwrap();
-                    let pixdata = pixdata.bytes().unwrap();
+                    if let Some((title, pos)) = self.tooltip() {
+                        draw_tooltip(canvas, title, pos, width as f32, anti_alias);
+                    }
+
+                    if self.show_profile {
+                        let lines = self.profile_lines();
+                        draw_profile(canvas, &lines, (width as f32, height as f32), anti_alias);
+                    }
+
+                    let pixmap = canvas.peek_pixels().unwrap();
 
                     let mut buffer = surface.buffer_mut().unwrap();
-                    for index in 0..(width * height) as usize {
-                        buffer[index] = pixdata[index * 4 + 2] as u32
-                            | (pixdata[index * 4 + 1] as u32) << 8
-                            | (pixdata[index * 4 + 0] as u32) << 16;
-                    }
+                    render::copy_pixels(
+                        pixmap.bytes().unwrap(),
+                        pixmap.row_bytes(),
+                        width as usize,
+                        &mut buffer,
+                    );
                     buffer.present().unwrap();
                 }
 
-                let html = self.html.clone();
-                let window = self.window.clone();
+                if self.html.lock().unwrap().is_none() && self.loading.is_none() {
+                    self.start_loading();
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let pos = (position.x as f32, position.y as f32);
+                *self.mouse_cursor_position.lock().unwrap() = pos;
+
+                // a new title shows once the mouse has rested on it, when `next_frame` wakes the
+                // event loop for it, and an old one goes at once
+                if self.hover(pos) && self.hover.is_none() {
+                    let window = self.window.lock().unwrap();
+                    window.as_ref().unwrap().request_redraw();
+                }
+
+                let Some(pos) = self.document_position(pos) else {
+                    let window = self.window.lock().unwrap();
+                    window.as_ref().unwrap().set_cursor(CursorIcon::Default);
+                    return;
+                };
 
-                if self.html.clone().lock().unwrap().is_none() {
-                    let url = format!("http://{}/{}", self.host, self.path);
-                    tokio::spawn(async move {
-                        let resp = fetch(url).await.unwrap();
-                        *html.lock().unwrap() = Some(html::parse_html(resp).unwrap());
+                if self.mouse_pressed {
+                    if let Some((from, _)) = self.selection {
+                        self.selection = Some((from, pos));
 
-                        let window = window.lock().unwrap();
+                        let window = self.window.lock().unwrap();
                         window.as_ref().unwrap().request_redraw();
-                    });
+                    }
                 }
+
+                let links = self.hyper_links.lock().unwrap();
+                let icon = if hit_test(&links, pos).is_some()
+                    || hit_test(&self.disclosures, pos).is_some()
+                {
+                    CursorIcon::Pointer
+                } else {
+                    CursorIcon::Default
+                };
+
+                let window = self.window.lock().unwrap();
+                window.as_ref().unwrap().set_cursor(icon);
             }
-            WindowEvent::CursorMoved { position, .. } => {
-                *self.mouse_cursor_position.lock().unwrap() =
-                    (position.x as f32, position.y as f32);
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let pos = *self.mouse_cursor_position.lock().unwrap();
+                let Some(pos) = self.document_position(pos) else {
+                    return;
+                };
+                self.mouse_pressed = true;
+                self.selection = Some((pos, pos));
+
+                let window = self.window.lock().unwrap();
+                window.as_ref().unwrap().request_redraw();
             }
-            WindowEvent::MouseInput { .. } => {
-                let pos = self.mouse_cursor_position.lock().unwrap();
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let pos = *self.mouse_cursor_position.lock().unwrap();
+                self.mouse_pressed = false;
+                let Some(pos) = self.document_position(pos) else {
+                    return;
+                };
+
+                // a press and release without dragging is a click rather than a selection
+                let is_click = self.selection.is_none_or(|(from, _)| {
+                    (from.0 - pos.0).abs() < 3.0 && (from.1 - pos.1).abs() < 3.0
+                });
+                if !is_click {
+                    return;
+                }
+                self.selection = None;
+
+                if self.focus_field(pos) {
+                    let window = self.window.lock().unwrap();
+                    window.as_ref().unwrap().request_redraw();
+                }
+
                 let links = self.hyper_links.lock().unwrap().clone();
+                if let Some(link) = hit_test(&links, pos) {
+                    self.follow_link(link);
+                } else if let Some(Disclosure { key, open }) =
+                    hit_test(&self.disclosures, pos).cloned()
+                {
+                    self.expanded.insert(key, !open);
+
+                    let window = self.window.lock().unwrap();
+                    window.as_ref().unwrap().request_redraw();
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::Resized(_) => {
+                // reflow, which also re-evaluates @media queries against the new width
+                let window = self.window.lock().unwrap();
+                window.as_ref().unwrap().request_redraw();
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let dy = match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => lines * self.scroll_line,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                };
+                self.scroll_to(self.scroll_destination() - dy);
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                // a focused field takes the keys that type, ahead of scrolling and shortcuts
+                let shortcut = self.modifiers.control_key() || self.modifiers.super_key();
+                if event.state == ElementState::Pressed
+                    && !shortcut
+                    && self.type_into_field(&event.logical_key)
+                {
+                    let window = self.window.lock().unwrap();
+                    window.as_ref().unwrap().request_redraw();
+                    return;
+                }
 
-                for (link, path) in links.iter() {
-                    if link.x() <= pos.0
-                        && pos.0 <= link.right()
-                        && link.y() <= pos.1
-                        && pos.1 <= link.bottom()
+                if let (ElementState::Pressed, Key::Named(key)) = (event.state, &event.logical_key)
+                {
+                    // the session's cookies go, as if the browser had been restarted
+                    if *key == NamedKey::Delete
+                        && self.modifiers.control_key()
+                        && self.modifiers.shift_key()
                     {
-                        self.path = path.clone();
-                        self.html = Arc::new(Mutex::new(None));
-                        self.hyper_links.lock().unwrap().clear();
+                        cookie_jar().clear();
+                        println!("Cleared cookies");
+                        return;
+                    }
+
+                    if *key == NamedKey::F12 && self.profile {
+                        self.show_profile = !self.show_profile;
+
+                        let window = self.window.lock().unwrap();
+                        window.as_ref().unwrap().request_redraw();
+                        return;
+                    }
+
+                    let page_height = {
+                        let window = self.window.lock().unwrap();
+                        let height = window.as_ref().unwrap().inner_size().height as f32;
+                        height - self.viewport.top - self.viewport.bottom
+                    };
+
+                    if let Some(offset) = scroll_target(
+                        key,
+                        self.scroll_destination(),
+                        self.scroll_line,
+                        page_height,
+                        self.max_scroll,
+                    ) {
+                        self.scroll_to(offset);
+                        return;
+                    }
+                }
+
+                if let (ElementState::Pressed, true, Key::Character(key)) = (
+                    event.state,
+                    self.modifiers.control_key() || self.modifiers.super_key(),
+                    &event.logical_key,
+                ) {
+                    if let Some(zoom) = zoom_for_key(key, self.zoom) {
+                        // keep the same part of the page in view
+                        self.scroll_offset *= zoom / self.zoom;
+                        self.scroll_destination = None;
+                        self.zoom = zoom;
+                        self.text_cache.lock().unwrap().clear();
+
+                        let window = self.window.lock().unwrap();
+                        window.as_ref().unwrap().request_redraw();
+                        return;
+                    }
+
+                    if key.as_str() == "d" {
+                        self.dark_mode = !self.dark_mode;
 
                         let window = self.window.lock().unwrap();
                         window.as_ref().unwrap().request_redraw();
+                        return;
+                    }
+
+                    // reloaded so the images are fetched, or dropped
+                    if key.as_str() == "i" {
+                        self.block_images = !self.block_images;
+                        self.navigate(self.path.clone());
+                        return;
+                    }
+                }
+
+                let is_copy = event.state == ElementState::Pressed
+                    && (self.modifiers.control_key() || self.modifiers.super_key())
+                    && event.logical_key == Key::Character("c".into());
+
+                if let (true, Some((from, to))) = (is_copy, self.selection) {
+                    let text = selected_runs(&self.text_runs, from, to)
+                        .into_iter()
+                        .map(|(_, text)| text.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+
+                    if let Err(err) =
+                        arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text))
+                    {
+                        eprintln!("Failed to copy to clipboard: {}", err);
                     }
                 }
             }
@@ -373,49 +1005,2080 @@ impl ApplicationHandler for App {
     }
 }
 
-async fn fetch(url: String) -> Result<String, Box<dyn std::error::Error>> {
-    println!("Fetching: {}", url);
-    let resp = reqwest::get(url).await?.text().await?;
+/// Applies a key typed into a field to its `value`: a character or space is added at the end,
+/// Enter starts a new line and Backspace deletes the last character. Returns whether `key` was
+/// one of those.
+fn edit_field(value: &mut String, key: &Key) -> bool {
+    match key {
+        Key::Character(text) => value.push_str(text),
+        Key::Named(NamedKey::Space) => value.push(' '),
+        Key::Named(NamedKey::Enter) => value.push('\n'),
+        Key::Named(NamedKey::Backspace) => {
+            value.pop();
+        }
+        _ => return false,
+    }
 
-    Ok(resp)
+    true
 }
 
-async fn ensure_server_started(url: &str, timeout: std::time::Duration) -> Result<(), String> {
-    let start = std::time::Instant::now();
-    while start.elapsed() < timeout {
-        if let Ok(response) = reqwest::get(url).await {
-            if response.status().is_success() {
-                return Ok(());
-            }
+#[test]
+fn test_edit_field() {
+    let mut value = "a".to_string();
+    for key in [
+        Key::Character("b".into()),
+        Key::Named(NamedKey::Space),
+        Key::Named(NamedKey::Enter),
+        Key::Character("é".into()),
+        Key::Named(NamedKey::Backspace),
+        Key::Character("c".into()),
+    ] {
+        assert!(edit_field(&mut value, &key), "{:?}", key);
+    }
+    assert_eq!(value, "ab \nc");
+
+    // keys that don't type leave the field as it is
+    assert!(!edit_field(&mut value, &Key::Named(NamedKey::ArrowDown)));
+    assert_eq!(value, "ab \nc");
+}
+
+#[test]
+fn test_form_field_states() {
+    let field = |key: &str, disabled, readonly| {
+        (
+            Rect::from_xywh(0.0, 0.0, 100.0, 40.0),
+            TextField {
+                key: key.to_string(),
+                value: "text".to_string(),
+                disabled,
+                readonly,
+            },
+        )
+    };
+
+    // a disabled field ignores clicks
+    let mut app = App {
+        text_fields: vec![field("disabled", true, false)],
+        ..Default::default()
+    };
+    assert!(!app.focus_field((10.0, 10.0)));
+    assert_eq!(app.focused_field, None);
+
+    // a read-only one takes the focus but ignores keystrokes
+    app.text_fields = vec![field("readonly", false, true)];
+    assert!(app.focus_field((10.0, 10.0)));
+    assert_eq!(app.focused_field.as_deref(), Some("readonly"));
+    assert!(!app.type_into_field(&Key::Character("x".into())));
+    assert!(app.field_values.is_empty());
+
+    app.text_fields = vec![field("readonly", false, false)];
+    assert!(app.type_into_field(&Key::Character("x".into())));
+    assert_eq!(app.field_values["readonly"], "textx");
+
+    assert!(app.focus_field((200.0, 10.0)));
+    assert_eq!(app.focused_field, None);
+}
+
+#[test]
+fn test_caret_blink() {
+    use std::time::{Duration, Instant};
+
+    let field = TextField {
+        key: "field".to_string(),
+        value: String::new(),
+        disabled: false,
+        readonly: false,
+    };
+    let mut app = App {
+        text_fields: vec![(Rect::from_xywh(0.0, 0.0, 100.0, 40.0), field)],
+        ..Default::default()
+    };
+    assert!(app.focus_field((10.0, 10.0)));
+
+    // on and off by turns, waking the loop for each switch
+    let shown = Instant::now() - Duration::from_millis(100);
+    app.caret_shown = Some(shown);
+    assert!(app.caret_visible(shown + Duration::from_millis(100)));
+    assert!(!app.caret_visible(shown + CARET_BLINK + Duration::from_millis(100)));
+    assert!(app.caret_visible(shown + CARET_BLINK * 2));
+    assert_eq!(
+        app.next_frame(shown + Duration::from_millis(100)),
+        Some(shown + CARET_BLINK)
+    );
+
+    // typing brings it straight back on
+    assert!(app.type_into_field(&Key::Character("x".into())));
+    let restarted = app.caret_shown.unwrap();
+    assert!(restarted > shown);
+    assert!(app.caret_visible(restarted));
+
+    // and once nothing is focused the loop can sleep
+    assert!(app.focus_field((200.0, 10.0)));
+    assert_eq!(app.next_frame(Instant::now()), None);
+}
+
+fn clamp_scroll(offset: f32, max_scroll: f32) -> f32 {
+    offset.min(max_scroll).max(0.0)
+}
+
+/// Where a scrolling key moves the page to, or `None` if `key` doesn't scroll. The arrows move
+/// it by `line`.
+fn scroll_target(
+    key: &NamedKey,
+    offset: f32,
+    line: f32,
+    page_height: f32,
+    max_scroll: f32,
+) -> Option<f32> {
+    let target = match key {
+        NamedKey::ArrowUp => offset - line,
+        NamedKey::ArrowDown => offset + line,
+        NamedKey::PageUp => offset - page_height,
+        NamedKey::PageDown => offset + page_height,
+        NamedKey::Home => 0.0,
+        NamedKey::End => max_scroll,
+        _ => return None,
+    };
+
+    Some(clamp_scroll(target, max_scroll))
+}
+
+#[test]
+fn test_scroll_target() {
+    let cases = vec![
+        (NamedKey::ArrowDown, 0.0, Some(36.0)),
+        (NamedKey::ArrowUp, 0.0, Some(0.0)),
+        (NamedKey::PageDown, 100.0, Some(580.0)),
+        (NamedKey::PageDown, 900.0, Some(1000.0)),
+        (NamedKey::PageUp, 300.0, Some(0.0)),
+        (NamedKey::Home, 700.0, Some(0.0)),
+        (NamedKey::End, 0.0, Some(1000.0)),
+        (NamedKey::Enter, 50.0, None),
+    ];
+
+    for (key, offset, want) in cases {
+        assert_eq!(
+            scroll_target(&key, offset, SCROLL_LINE, 480.0, 1000.0),
+            want,
+            "{:?}",
+            key
+        );
+    }
+
+    // a page shorter than the window doesn't scroll at all
+    assert_eq!(
+        scroll_target(&NamedKey::End, 0.0, SCROLL_LINE, 480.0, 0.0),
+        Some(0.0)
+    );
+    assert_eq!(
+        scroll_target(&NamedKey::ArrowDown, 0.0, 100.0, 480.0, 1000.0),
+        Some(100.0)
+    );
+}
+
+/// The offset one frame of a smooth scroll from `offset` to `destination` reaches: part of the
+/// way there, and all of it once that's under a pixel.
+fn smooth_scroll_step(offset: f32, destination: f32) -> f32 {
+    let next = offset + (destination - offset) * SMOOTH_SCROLL_STEP;
+    if (destination - next).abs() < 1.0 {
+        destination
+    } else {
+        next
+    }
+}
+
+#[test]
+fn test_smooth_scroll() {
+    for (offset, destination) in [(0.0, 1000.0), (1000.0, 0.0), (250.0, 286.0)] {
+        let mut current = offset;
+        let mut frames = 0;
+        while current != destination {
+            let next = smooth_scroll_step(current, destination);
+            // each frame moves closer without passing the destination
+            assert!((destination - next).abs() < (destination - current).abs());
+            assert!((next - offset) * (destination - offset) >= 0.0);
+            assert!((destination - next) * (destination - offset) >= 0.0);
+            current = next;
+            frames += 1;
         }
+        assert!(frames < 30, "{} frames from {}", frames, offset);
+    }
 
-        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    // a destination past the end of a page that got shorter is held to it
+    let mut app = App {
+        smooth_scroll: true,
+        scroll_offset: 400.0,
+        scroll_destination: Some(900.0),
+        max_scroll: 500.0,
+        ..Default::default()
+    };
+    while app.scroll_destination.is_some() {
+        app.step_scroll();
+        assert!(app.scroll_offset <= 500.0);
     }
-    Err("Server did not start within the timeout".into())
+    assert_eq!(app.scroll_offset, 500.0);
+
+    let now = std::time::Instant::now();
+    assert_eq!(app.next_frame(now), None);
+    app.scroll_destination = Some(0.0);
+    assert_eq!(app.next_frame(now), Some(now + SMOOTH_SCROLL_FRAME));
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = std::env::args().collect();
-    let host = args.get(1).cloned().unwrap_or("localhost:8000".to_string());
+const ZOOM_RANGE: (f32, f32) = (0.5, 3.0);
 
-    // extend the lifetime of the process to the end of the program
-    let _process: DroppableProcess = DroppableProcess::new(
-        Command::new("python")
-            .arg("-m")
-            .arg("http.server")
-            .arg("8000")
-            .arg("-d")
-            .arg("public"),
-    )?;
-    ensure_server_started("http://localhost:8000", std::time::Duration::from_secs(5)).await?;
+/// The zoom level after Ctrl+`key`, or `None` if `key` isn't a zoom shortcut.
+fn zoom_for_key(key: &str, zoom: f32) -> Option<f32> {
+    let zoom = match key {
+        "=" | "+" => zoom + 0.1,
+        "-" => zoom - 0.1,
+        "0" => 1.0,
+        _ => return None,
+    };
+
+    // rounded so repeated steps land back on exactly 1.0
+    Some(((zoom * 10.0).round() / 10.0).clamp(ZOOM_RANGE.0, ZOOM_RANGE.1))
+}
+
+#[test]
+fn test_zoom_for_key() {
+    let cases = vec![
+        ("=", 1.0, Some(1.1)),
+        ("+", 1.0, Some(1.1)),
+        ("-", 1.0, Some(0.9)),
+        ("-", 0.5, Some(0.5)),
+        ("=", 3.0, Some(3.0)),
+        ("0", 2.3, Some(1.0)),
+        ("c", 1.0, None),
+    ];
+
+    for (key, zoom, want) in cases {
+        assert_eq!(zoom_for_key(key, zoom), want, "{} {}", key, zoom);
+    }
+
+    let stepped = (0..5).fold(1.0, |zoom, _| zoom_for_key("=", zoom).unwrap());
+    let back = (0..5).fold(stepped, |zoom, _| zoom_for_key("-", zoom).unwrap());
+    assert_eq!(back, 1.0);
+}
+
+#[test]
+fn test_https_link() {
+    let html = html::parse_html(
+        r#"<html><body><a href="https://example.com/docs/">docs</a> <a href="intro.html">intro</a></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let state = render::layout(&html, 800, 600, &RenderOptions::default());
+    let follow = |current: &str, index: usize| {
+        let base = base_path(
+            "localhost:8000",
+            current,
+            html.head_metadata().base.as_deref(),
+        );
+        let path = resolve_path("localhost:8000", &base, &state.hyper_links[index].1.href);
+        page_url("localhost:8000", &path)
+    };
+
+    // the absolute link leaves the local server, and relative links on the page it leads to
+    // stay on that page's host
+    assert_eq!(follow("index.html", 0), "https://example.com/docs/");
+    assert_eq!(follow("index.html", 1), "http://localhost:8000/intro.html");
+    assert_eq!(
+        follow("https://example.com/docs/", 1),
+        "https://example.com/docs/intro.html"
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_https() {
+    // nothing listens there, so the request gets as far as connecting, which it only tries for
+    // a scheme the client supports
+    let err = fetch("https://127.0.0.1:1/".to_string(), MAX_PAGE_SIZE)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, FetchError::Network(err) if err.is_connect()),
+        "{:?}",
+        err
+    );
+
+    let err = fetch("data:text/html,<p>hi</p>".to_string(), MAX_PAGE_SIZE)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Can't load data: URLs");
+}
+
+#[tokio::test]
+async fn test_fetch_error() {
+    use std::time::Duration;
+
+    let host = serve_once("404 Not Found", "\r\n".to_string(), Duration::ZERO).await;
+    let err = fetch(page_url(&host, "missing.html"), MAX_PAGE_SIZE)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FetchError::Status(404)), "{:?}", err);
+    assert_eq!(err.to_string(), "The server responded with 404 Not Found");
+
+    // reqwest's own errors, from a client that gives up sooner than the server answers
+    let host = serve_once("200 OK", "\r\n".to_string(), Duration::from_millis(500)).await;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let err = client.get(page_url(&host, "slow.html")).send().await;
+    assert!(matches!(
+        err.map_err(FetchError::from),
+        Err(FetchError::Timeout)
+    ));
+
+    let host = serve_once(
+        "500 Internal Server Error",
+        "\r\n".to_string(),
+        Duration::ZERO,
+    )
+    .await;
+    let err = reqwest::get(page_url(&host, "broken.html"))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap_err();
+    assert!(matches!(FetchError::from(err), FetchError::Status(500)));
+
+    assert!(matches!(
+        fetch("http://[::1".to_string(), MAX_PAGE_SIZE).await,
+        Err(FetchError::InvalidUrl(_))
+    ));
+}
+
+/// Returns the text runs covered by a selection dragged from `from` to `to`. Selections are
+/// single-line: only runs on the line containing `from` are picked.
+fn selected_runs(
+    runs: &[(Rect, String)],
+    from: (f32, f32),
+    to: (f32, f32),
+) -> Vec<&(Rect, String)> {
+    let (left, right) = (from.0.min(to.0), from.0.max(to.0));
+
+    runs.iter()
+        .filter(|(rect, _)| {
+            rect.y() <= from.1 && from.1 <= rect.bottom() && rect.x() < right && left < rect.right()
+        })
+        .collect()
+}
+
+#[test]
+fn test_selected_runs() {
+    let runs = vec![
+        (Rect::new(0.0, 0.0, 50.0, 40.0), "Hello,".to_string()),
+        (Rect::new(58.0, 0.0, 108.0, 40.0), "world!".to_string()),
+        (Rect::new(116.0, 0.0, 150.0, 40.0), "This".to_string()),
+        (Rect::new(0.0, 40.0, 50.0, 80.0), "next".to_string()),
+    ];
+
+    let texts = |from, to| {
+        selected_runs(&runs, from, to)
+            .into_iter()
+            .map(|(_, text)| text.clone())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(texts((10.0, 20.0), (70.0, 60.0)), vec!["Hello,", "world!"]);
+    assert_eq!(texts((120.0, 20.0), (60.0, 20.0)), vec!["world!", "This"]);
+    assert_eq!(texts((52.0, 20.0), (56.0, 20.0)), Vec::<String>::new());
+}
+
+/// Answers a single request on an ephemeral localhost port with `status` and then `response`,
+/// the headers and the body, once `delay` is up. Returns the `host:port`.
+#[cfg(test)]
+async fn serve_once(status: &str, response: String, delay: std::time::Duration) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+    let response = format!("HTTP/1.1 {}\r\nConnection: close\r\n{}", status, response);
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).await.unwrap();
+        tokio::time::sleep(delay).await;
+
+        // the client may hang up part way through a response it doesn't want
+        let _ = stream.write_all(response.as_bytes()).await;
+    });
+
+    host
+}
+
+#[tokio::test]
+async fn test_loading_indicator() {
+    use std::time::Duration;
+
+    // a server that takes its time answering
+    let body = "<html><body>slow</body></html>";
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+        Duration::from_millis(300),
+    )
+    .await;
+
+    let mut app = App {
+        host,
+        ..App::default()
+    };
+    assert_eq!(app.loading(), None);
+    app.start_loading();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(app.loading().is_some());
+
+    let html = app.html.clone();
+    for _ in 0..250 {
+        if html.lock().unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(app.loading(), None);
+    assert_eq!(app.nav_history()[0].status, Some(200));
+    assert_eq!(app.source.lock().unwrap().as_deref(), Some(body));
+}
+
+#[tokio::test]
+async fn test_navigate_away_from_loading_refresh() {
+    use std::time::Duration;
+
+    // a slow page that refreshes itself as soon as it arrives
+    let body = r#"<html><head><meta http-equiv="refresh" content="0" /></head></html>"#;
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+        Duration::from_millis(200),
+    )
+    .await;
+    let mut app = App {
+        host,
+        path: "slow.html".to_string(),
+        ..App::default()
+    };
+    app.start_loading();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    app.navigate("other.html".to_string());
+
+    // the abandoned load neither sends the reader back nor shows up in the history
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(*app.pending_navigation.lock().unwrap(), None);
+    assert!(app.refresh_task.lock().unwrap().is_none());
+    assert!(app.nav_history().is_empty());
+    assert_eq!(app.path, "other.html");
+}
+
+#[tokio::test]
+async fn test_page_source_cleared_on_error() {
+    let host = serve_once(
+        "404 Not Found",
+        "Content-Length: 0\r\n\r\n".to_string(),
+        std::time::Duration::ZERO,
+    )
+    .await;
+    let mut app = App {
+        host,
+        source: Arc::new(Mutex::new(Some("<html></html>".to_string()))),
+        ..App::default()
+    };
+    app.start_loading();
+
+    let html = app.html.clone();
+    for _ in 0..250 {
+        if html.lock().unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    // the error page shown in its place has no source of its own
+    assert!(html.lock().unwrap().is_some());
+    assert_eq!(*app.source.lock().unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_piped_source() {
+    let source = r#"<html><body><a href="next.html">piped</a></body></html>"#;
+    let mut app = App {
+        host: "nowhere.invalid".to_string(),
+        path: "https://example.com/docs/page.html".to_string(),
+        piped_source: Some(source.to_string()),
+        ..App::default()
+    };
+    app.start_loading();
+
+    let html = app.html.clone();
+    for _ in 0..250 {
+        if html.lock().unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    // shown as it came, without a fetch, for the window to render
+    assert_eq!(
+        *html.lock().unwrap(),
+        Some(html::parse_html(source.to_string()).unwrap())
+    );
+    assert_eq!(app.source.lock().unwrap().as_deref(), Some(source));
+    assert_eq!(app.nav_history()[0].status, Some(200));
+    assert_eq!(app.piped_source, None);
+
+    // and its links lead on from the URL it was given
+    let next = resolve_path(&app.host, &app.base(), "next.html");
+    assert_eq!(
+        page_url(&app.host, &next),
+        "https://example.com/docs/next.html"
+    );
+}
+
+#[tokio::test]
+async fn test_navigation_hook() {
+    let body = "<html><body>rewritten</body></html>";
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+        std::time::Duration::ZERO,
+    )
+    .await;
+    let target = format!("http://{}/new.html", host);
+    let redirect = target.clone();
+    let mut app = App {
+        host: "nowhere.invalid".to_string(),
+        path: "old.html".to_string(),
+        navigation_hook: Some(Box::new(move |url| {
+            if url == "http://nowhere.invalid/old.html" {
+                NavigationDecision::Redirect(redirect.clone())
+            } else {
+                NavigationDecision::Allow
+            }
+        })),
+        ..App::default()
+    };
+    app.start_loading();
+
+    let html = app.html.clone();
+    for _ in 0..250 {
+        if html.lock().unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    // the page came from where the hook pointed, which the browser is now on
+    assert_eq!(app.path, target);
+    assert_eq!(app.nav_history()[0].url, target);
+    assert_eq!(app.nav_history()[0].status, Some(200));
+    assert_eq!(app.source.lock().unwrap().as_deref(), Some(body));
+
+    // a blocked page isn't fetched at all
+    let mut app = App {
+        host: "nowhere.invalid".to_string(),
+        navigation_hook: Some(Box::new(|_| NavigationDecision::Block)),
+        ..App::default()
+    };
+    app.start_loading();
+    let html = app.html.clone();
+    for _ in 0..250 {
+        if html.lock().unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(html.lock().unwrap().is_some());
+    assert_eq!(app.nav_history()[0].status, None);
+    assert_eq!(*app.source.lock().unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_fetch_max_size() {
+    let body = "x".repeat(100);
+    let fetch_from = |response: String, max_size: usize| async move {
+        let host = serve_once("200 OK", response, std::time::Duration::ZERO).await;
+        fetch(page_url(&host, "page.html"), max_size)
+            .await
+            .map_err(|err| err.to_string())
+    };
+    let sized = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    // without a length up front, the body is only found to be too large while reading it
+    let streamed = format!("\r\n{}", body);
+
+    assert_eq!(
+        fetch_from(sized.clone(), 100).await,
+        Ok((200, body.clone()))
+    );
+    assert_eq!(
+        fetch_from(streamed.clone(), 100).await,
+        Ok((200, body.clone()))
+    );
+    let too_large = Err("Page is larger than the 99 byte limit".to_string());
+    assert_eq!(fetch_from(sized, 99).await, too_large);
+    assert_eq!(fetch_from(streamed, 99).await, too_large);
+}
+
+#[test]
+fn test_next_frame() {
+    use std::time::{Duration, Instant};
+
+    let now = Instant::now();
+    let mut app = App::default();
+    assert_eq!(app.next_frame(now), None);
+
+    // the spinner steps on whole frames from when loading started
+    let started = now - Duration::from_millis(250);
+    app.loading = Some(started);
+    assert_eq!(app.next_frame(now), Some(started + SPINNER_FRAME * 3));
+
+    // a tooltip due before the next step wakes the loop first, and one already up doesn't
+    app.hover = Some((
+        "title".to_string(),
+        now - TOOLTIP_DELAY + Duration::from_millis(10),
+        (0.0, 0.0),
+    ));
+    assert_eq!(app.next_frame(now), Some(now + Duration::from_millis(10)));
+    app.loading = None;
+    assert_eq!(app.next_frame(now + Duration::from_millis(10)), None);
+}
+
+/// Draws a ring of dots around `center`, the darkest of which goes round once every eight
+/// frames.
+fn draw_spinner(
+    canvas: &skia_safe::Canvas,
+    center: (f32, f32),
+    elapsed: std::time::Duration,
+    anti_alias: bool,
+) {
+    const DOTS: u128 = 8;
+    let head = elapsed.as_millis() / SPINNER_FRAME.as_millis() % DOTS;
+    let mut paint = PaintExt::new(anti_alias).0;
+
+    for dot in 0..DOTS {
+        // dots fade the further they trail behind the head
+        let behind = (head + DOTS - dot) % DOTS;
+        paint.set_argb(0xFF - behind as u8 * 0x1C, 0x33, 0x33, 0x33);
+
+        let angle = dot as f32 / DOTS as f32 * std::f32::consts::TAU;
+        canvas.draw_circle(
+            (center.0 + 12.0 * angle.cos(), center.1 + 12.0 * angle.sin()),
+            3.0,
+            &paint,
+        );
+    }
+}
+
+/// Draws a page's favicon from its SVG source into `rect`, or a blank page in its place if it has
+/// none or it doesn't decode.
+fn draw_favicon(canvas: &skia_safe::Canvas, icon: Option<&[u8]>, rect: Rect, anti_alias: bool) {
+    let dom = icon.and_then(|icon| {
+        svg::Dom::from_bytes(icon, FontMgr::new())
+            .map_err(|err| eprintln!("Ignoring favicon: {}", err))
+            .ok()
+    });
+    if let Some(mut dom) = dom {
+        dom.set_container_size((rect.width(), rect.height()));
+        canvas.save();
+        canvas.translate((rect.left, rect.top));
+        dom.render(canvas);
+        canvas.restore();
+        return;
+    }
+
+    let page = rect.with_inset((rect.width() / 8.0, 0.0));
+    let mut paint = PaintExt::new(anti_alias);
+    paint.set_color_hex("#767676");
+    canvas.draw_rect(page, &paint.0);
+    paint.set_color_hex("#ffffff");
+    canvas.draw_rect(page.with_inset((1.0, 1.0)), &paint.0);
+}
+
+#[test]
+fn test_draw_favicon() {
+    let rect = Rect::from_xywh(20.0, 13.0, FAVICON_SIZE, FAVICON_SIZE);
+    let draw = |icon: Option<&[u8]>| {
+        let mut surface = skia_safe::surfaces::raster_n32_premul((60, 50)).unwrap();
+        surface.canvas().clear(0xFF999999);
+        draw_favicon(surface.canvas(), icon, rect, true);
+
+        let pixmap = surface.peek_pixels().unwrap();
+        let mut pixels = vec![0; 60 * 50];
+        render::copy_pixels(pixmap.bytes().unwrap(), pixmap.row_bytes(), 60, &mut pixels);
+        pixels
+    };
+    let pixel = |pixels: &[u32], (x, y): (f32, f32)| pixels[y as usize * 60 + x as usize];
+    let center = (rect.center_x(), rect.center_y());
+
+    // the page's own icon, filling the box
+    let svg = br##"<svg xmlns="http://www.w3.org/2000/svg"><rect fill="#808080"/></svg>"##;
+    let pixels = draw(Some(svg));
+    assert_eq!(pixel(&pixels, center), 0x808080);
+    assert_eq!(pixel(&pixels, (rect.left + 1.0, rect.top + 1.0)), 0x808080);
+
+    // or a blank page when there's none or it doesn't decode
+    for icon in [None, Some(&b"not an image"[..])] {
+        let pixels = draw(icon);
+        assert_eq!(pixel(&pixels, center), 0xffffff);
+        assert_eq!(pixel(&pixels, (rect.left + 3.0, center.1)), 0x767676);
+        assert_eq!(pixel(&pixels, (rect.left + 1.0, center.1)), 0x999999);
+    }
+}
+
+/// Draws `title` in a box just below and right of the window position `pos`, kept within the
+/// window's `width`.
+fn draw_tooltip(
+    canvas: &skia_safe::Canvas,
+    title: &str,
+    pos: (f32, f32),
+    width: f32,
+    anti_alias: bool,
+) {
+    const PADDING: f32 = 6.0;
+    let font = render::font(default_typeface(), 20.0, anti_alias);
+    let Some(text) = TextBlob::from_str(title, &font) else {
+        return;
+    };
+    let (text_width, _) = font.measure_str(title, None);
+    let (_, metrics) = font.metrics();
+
+    let box_width = text_width + PADDING * 2.0;
+    let left = (pos.0 + 12.0).min(width - box_width).max(0.0);
+    let top = pos.1 + 20.0;
+    let outline = Rect::new(
+        left,
+        top,
+        left + box_width,
+        top + metrics.descent - metrics.ascent + PADDING * 2.0,
+    );
+
+    let mut paint = PaintExt::new(anti_alias);
+    paint.set_color_hex("#767676");
+    canvas.draw_rect(outline, &paint.0);
+    paint.set_color_hex("#ffffe1");
+    canvas.draw_rect(outline.with_inset((1.0, 1.0)), &paint.0);
+    paint.set_color_hex("#000000");
+    canvas.draw_text_blob(
+        &text,
+        (left + PADDING, top + PADDING - metrics.ascent),
+        &paint.0,
+    );
+}
+
+/// Draws `lines` in a translucent box in the bottom right corner of a window of `size`.
+fn draw_profile(canvas: &skia_safe::Canvas, lines: &[String], size: (f32, f32), anti_alias: bool) {
+    const PADDING: f32 = 6.0;
+    let font = render::font(default_typeface(), 16.0, anti_alias);
+    let (_, metrics) = font.metrics();
+    let line_height = metrics.descent - metrics.ascent;
+    let text_width = lines
+        .iter()
+        .map(|line| font.measure_str(line, None).0)
+        .fold(0.0, f32::max);
+
+    let outline = Rect::new(
+        size.0 - text_width - PADDING * 3.0,
+        size.1 - line_height * lines.len() as f32 - PADDING * 3.0,
+        size.0 - PADDING,
+        size.1 - PADDING,
+    );
+    let mut paint = PaintExt::new(anti_alias);
+    paint.0.set_argb(0xCC, 0x00, 0x00, 0x00);
+    canvas.draw_rect(outline, &paint.0);
+
+    paint.set_color_hex("#ffffff");
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(text) = TextBlob::from_str(line, &font) {
+            let top = outline.top + PADDING + line_height * i as f32;
+            canvas.draw_text_blob(
+                &text,
+                (outline.left + PADDING, top - metrics.ascent),
+                &paint.0,
+            );
+        }
+    }
+}
+
+#[test]
+fn test_profile_lines() {
+    let mut app = App::default();
+    assert_eq!(
+        app.profile_lines(),
+        vec!["tokenize -", "parse -", "layout -", "paint -"]
+    );
+
+    app.nav_events.lock().unwrap().push(NavEvent {
+        url: "http://localhost:8000/".to_string(),
+        status: Some(200),
+        bytes: 100,
+        tokenize_duration: std::time::Duration::from_micros(1500),
+        parse_duration: std::time::Duration::from_millis(2),
+    });
+    app.render_stats = Some(RenderStats {
+        layout: std::time::Duration::from_micros(12345),
+        paint: std::time::Duration::ZERO,
+    });
+    assert_eq!(
+        app.profile_lines(),
+        vec![
+            "tokenize 1.50ms",
+            "parse 2.00ms",
+            "layout 12.35ms",
+            "paint 0.00ms"
+        ]
+    );
+}
+
+#[test]
+fn test_hover_tooltip() {
+    let mut app = App {
+        viewport: Viewport::default(),
+        ..App::default()
+    };
+    let top = app.viewport.top;
+    app.titles = vec![
+        (Rect::new(0.0, top, 100.0, top + 40.0), "first".to_string()),
+        (
+            Rect::new(100.0, top, 200.0, top + 40.0),
+            "second".to_string(),
+        ),
+    ];
+
+    assert!(app.hover((50.0, top + 10.0)));
+    assert_eq!(app.tooltip(), None);
+    // moving within the same title keeps its timer going
+    assert!(!app.hover((60.0, top + 20.0)));
+
+    let (_, since, _) = app.hover.as_mut().unwrap();
+    *since -= TOOLTIP_DELAY;
+    assert_eq!(app.tooltip(), Some(("first", (50.0, top + 10.0))));
+
+    assert!(app.hover((150.0, top + 10.0)));
+    assert_eq!(app.tooltip(), None);
+    assert!(app.hover((250.0, top + 10.0)));
+    assert_eq!(app.hover, None);
+    assert!(!app.hover((300.0, top + 10.0)));
+    // nothing is titled over the chrome
+    assert!(!app.hover((50.0, 0.0)));
+}
+
+/// Returns the link, or other click target, under `pos`. Targets are recorded in document
+/// order, so the last matching rect is the topmost one.
+fn hit_test<T>(targets: &[(Rect, T)], pos: (f32, f32)) -> Option<&T> {
+    targets
+        .iter()
+        .rev()
+        .find(|(rect, _)| {
+            rect.x() <= pos.0
+                && pos.0 <= rect.right()
+                && rect.y() <= pos.1
+                && pos.1 <= rect.bottom()
+        })
+        .map(|(_, target)| target)
+}
+
+#[cfg(test)]
+fn link(href: &str) -> Link {
+    Link {
+        href: href.to_string(),
+        target: None,
+    }
+}
+
+#[test]
+fn test_hit_test() {
+    let links = vec![(Rect::new(10.0, 10.0, 110.0, 50.0), link("link.html"))];
+
+    let cases = vec![
+        ((60.0, 30.0), Some("link.html")),
+        ((5.0, 30.0), None),
+        ((60.0, 60.0), None),
+        ((10.0, 10.0), Some("link.html")),
+        ((110.0, 50.0), Some("link.html")),
+        ((110.5, 50.0), None),
+    ];
+
+    for (pos, want) in cases {
+        assert_eq!(
+            hit_test(&links, pos).map(|link| link.href.as_str()),
+            want,
+            "{:?}",
+            pos
+        );
+    }
+
+    assert_eq!(hit_test::<Link>(&[], (0.0, 0.0)), None);
+}
+
+#[test]
+fn test_visited_link_color() {
+    let page =
+        html::parse_html(r#"<html><body><a href="next.html">next</a></body></html>"#.to_string())
+            .unwrap();
+    let mut app = App {
+        host: "localhost:8000".to_string(),
+        path: "index.html".to_string(),
+        ..App::default()
+    };
+    let link_color = |app: &App| {
+        let base = app.base();
+        let is_visited = |href: &str| app.is_visited(&base, href);
+        let options = RenderOptions {
+            is_visited: &is_visited,
+            ..RenderOptions::default()
+        };
+        let boxes = render::layout(&page, 800, 600, &options).boxes;
+        boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some("next"))
+            .and_then(|layout_box| layout_box.color)
+    };
+    assert_eq!(link_color(&app), Some(0x0055ff));
+
+    // clicked through, then back on the page with the link
+    app.follow_link(&link("next.html"));
+    assert_eq!(app.path, "next.html");
+    app.navigate("index.html".to_string());
+    assert_eq!(link_color(&app), Some(0x551a8b));
+}
+
+#[test]
+fn test_follow_non_page_links() {
+    let mut app = App {
+        path: "page.html".to_string(),
+        ..App::default()
+    };
+
+    for href in [
+        "javascript:void(0)",
+        "JAVASCRIPT:go()",
+        "mailto:a@example.com",
+        "tel:555",
+    ] {
+        app.follow_link(&link(href));
+
+        // neither taken as the page to load nor fetched
+        assert_eq!(app.path, "page.html", "{}", href);
+        assert_eq!(app.loading, None, "{}", href);
+        assert!(app.nav_history().is_empty(), "{}", href);
+    }
+}
+
+#[test]
+fn test_hit_test_overlapping() {
+    let links = vec![
+        (Rect::new(0.0, 0.0, 100.0, 40.0), link("below.html")),
+        (Rect::new(50.0, 0.0, 150.0, 40.0), link("above.html")),
+    ];
+
+    assert_eq!(hit_test(&links, (75.0, 20.0)), Some(&link("above.html")));
+    assert_eq!(hit_test(&links, (25.0, 20.0)), Some(&link("below.html")));
+}
+
+/// Opens `url` with the platform's URL handler.
+fn open_external(url: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command.arg(url).spawn()?;
+
+    Ok(())
+}
+
+/// Why a page could not be fetched.
+#[derive(Debug)]
+pub enum FetchError {
+    /// The URL doesn't parse.
+    InvalidUrl(String),
+    /// The URL is for a scheme other than `http` or `https`.
+    UnsupportedScheme(String),
+    /// The server couldn't be reached, or the connection broke.
+    Network(reqwest::Error),
+    Timeout,
+    /// The server answered with a client or server error status.
+    Status(u16),
+    /// The response body couldn't be read.
+    Decode(reqwest::Error),
+    /// The body is larger than the byte limit it was fetched with.
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::InvalidUrl(url) => write!(f, "Invalid URL: {}", url),
+            FetchError::UnsupportedScheme(scheme) => write!(f, "Can't load {}: URLs", scheme),
+            FetchError::Network(err) => write!(f, "Could not reach the server: {}", err),
+            FetchError::Timeout => write!(f, "The server took too long to respond"),
+            FetchError::Status(status) => {
+                let reason = reqwest::StatusCode::from_u16(*status)
+                    .ok()
+                    .and_then(|status| status.canonical_reason());
+                match reason {
+                    Some(reason) => write!(f, "The server responded with {} {}", status, reason),
+                    None => write!(f, "The server responded with {}", status),
+                }
+            }
+            FetchError::Decode(err) => write!(f, "Could not read the response: {}", err),
+            FetchError::TooLarge(max_size) => {
+                write!(f, "Page is larger than the {} byte limit", max_size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Network(err) | FetchError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            FetchError::Timeout
+        } else if let Some(status) = err.status().filter(|_| err.is_status()) {
+            FetchError::Status(status.as_u16())
+        } else if err.is_decode() || err.is_body() {
+            FetchError::Decode(err)
+        } else {
+            FetchError::Network(err)
+        }
+    }
+}
+
+/// Fetches `url`, returning the HTTP status along with the body, decoded as UTF-8. Fails once
+/// the body has gone over `max_size` bytes, without reading the rest of it.
+async fn fetch(url: String, max_size: usize) -> Result<(u16, String), FetchError> {
+    eprintln!("Fetching: {}", url);
+    let url = url::parse_url(&url).ok_or(FetchError::InvalidUrl(url))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(FetchError::UnsupportedScheme(url.scheme().to_string()));
+    }
+    let mut resp = http_client().get(url).send().await?.error_for_status()?;
+    let status = resp.status().as_u16();
+
+    if resp
+        .content_length()
+        .is_some_and(|length| length > max_size as u64)
+    {
+        return Err(FetchError::TooLarge(max_size));
+    }
+    let mut body = vec![];
+    while let Some(chunk) = resp.chunk().await? {
+        if body.len() + chunk.len() > max_size {
+            return Err(FetchError::TooLarge(max_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok((status, String::from_utf8_lossy(&body).into_owned()))
+}
+
+/// The sheets `links` on the document at `base` point to, from `cache` or else fetched and
+/// parsed into it. A sheet that fails to load or parse is left out.
+async fn load_stylesheets(
+    cache: &Mutex<StylesheetCache>,
+    host: &str,
+    base: &str,
+    links: &[&str],
+    max_size: usize,
+) -> Vec<Styles> {
+    let mut sheets = vec![];
+    for href in links {
+        let url = page_url(host, &resolve_path(host, base, href));
+        let cached = cache.lock().unwrap().get(&url);
+        if let Some(styles) = cached {
+            sheets.push(styles);
+            continue;
+        }
+
+        let styles = fetch(url.clone(), max_size)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|(_, source)| css::parse_css(source));
+        match styles {
+            Ok(styles) => {
+                cache.lock().unwrap().insert(url, styles.clone());
+                sheets.push(styles);
+            }
+            Err(err) => eprintln!("Ignoring stylesheet {}: {:#}", url, err),
+        }
+    }
+
+    sheets
+}
+
+#[tokio::test]
+async fn test_load_stylesheets() {
+    use std::time::{Duration, Instant};
+
+    // the server answers once, and slowly: pages after the first that share the sheet must
+    // take it from the cache, and are that much quicker
+    let delay = Duration::from_millis(200);
+    let source = "p { color: red; }";
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", source.len(), source),
+        delay,
+    )
+    .await;
+    let cache = Mutex::new(StylesheetCache::default());
+
+    let started = Instant::now();
+    let first = load_stylesheets(&cache, &host, "index.html", &["style.css"], MAX_PAGE_SIZE).await;
+    let uncached = started.elapsed();
+    let started = Instant::now();
+    let second = load_stylesheets(
+        &cache,
+        &host,
+        "docs/intro.html",
+        &["../style.css"],
+        MAX_PAGE_SIZE,
+    )
+    .await;
+    let cached = started.elapsed();
+    eprintln!(
+        "Stylesheet loaded in {:?}, then {:?} from the cache",
+        uncached, cached
+    );
+
+    assert_eq!(first, vec![css::parse_css(source.to_string()).unwrap()]);
+    assert_eq!(second, first);
+    assert_eq!(cache.lock().unwrap().misses, 1);
+    assert!(uncached >= delay, "{:?}", uncached);
+    assert!(cached < delay, "{:?}", cached);
+
+    // nothing answers for a sheet that isn't cached
+    let missing = load_stylesheets(&cache, &host, "index.html", &["other.css"], MAX_PAGE_SIZE);
+    assert_eq!(missing.await, vec![]);
+}
+
+/// Fetches the SVG images the `<img>`s of `element`, the document at `base`, show, by their
+/// `src` as written. Other images aren't drawn, so aren't fetched. An image that fails to load
+/// is left out.
+async fn load_images(host: &str, base: &str, element: &HtmlElement, max_size: usize) -> Vec<Image> {
+    let mut srcs = element
+        .find_all("img")
+        .into_iter()
+        .filter_map(|img| img.attributes.iter().find(|(key, _)| key == "src"))
+        .map(|(_, src)| src.clone())
+        .filter(|src| is_svg(src))
+        .collect::<Vec<_>>();
+    srcs.dedup();
+
+    let mut images = vec![];
+    for src in srcs {
+        match fetch(page_url(host, &resolve_path(host, base, &src)), max_size).await {
+            Ok((_, body)) => images.push((src, body.into_bytes())),
+            Err(err) => eprintln!("Ignoring image {}: {}", src, err),
+        }
+    }
+
+    images
+}
+
+/// Fetches the favicon at `href`, as linked from the document at `base`. Like images only SVG
+/// ones are, and one that fails to load is left out.
+async fn load_favicon(
+    host: &str,
+    base: &str,
+    href: Option<&str>,
+    max_size: usize,
+) -> Option<Vec<u8>> {
+    let href = href.filter(|href| is_svg(href))?;
+
+    match fetch(page_url(host, &resolve_path(host, base, href)), max_size).await {
+        Ok((_, body)) => Some(body.into_bytes()),
+        Err(err) => {
+            eprintln!("Ignoring favicon {}: {}", href, err);
+            None
+        }
+    }
+}
+
+/// Whether `src` names an SVG file, going by its extension.
+fn is_svg(src: &str) -> bool {
+    let path = src.split(['?', '#']).next().unwrap_or_default();
+    path.to_ascii_lowercase().ends_with(".svg")
+}
+
+/// Fetches and parses the documents the `<iframe>`s of `element`, the document at `base`, embed,
+/// by their `src` as written. Only those on `host` are fetched, and their own frames aren't. A
+/// document that fails to load or parse is left out.
+async fn load_frames(host: &str, base: &str, element: &HtmlElement, max_size: usize) -> Vec<Frame> {
+    let mut srcs = element
+        .find_all("iframe")
+        .into_iter()
+        .filter_map(|iframe| iframe.attributes.iter().find(|(key, _)| key == "src"))
+        .map(|(_, src)| src.clone())
+        .collect::<Vec<_>>();
+    srcs.dedup();
+
+    let mut frames = vec![];
+    for src in srcs {
+        // paths on the host stay relative, other origins are whole URLs
+        let path = resolve_path(host, base, &src);
+        if is_absolute_url(&path) {
+            eprintln!("Ignoring frame from another origin: {}", src);
+            continue;
+        }
+
+        let document = fetch(page_url(host, &path), max_size)
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|(_, body)| html::parse_html(body).map_err(|err| format!("{:#}", err)));
+        match document {
+            Ok(document) => frames.push((src, document)),
+            Err(err) => eprintln!("Ignoring frame {}: {}", src, err),
+        }
+    }
+
+    frames
+}
+
+#[tokio::test]
+async fn test_load_frames() {
+    let source = "<html><body>framed</body></html>";
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", source.len(), source),
+        std::time::Duration::ZERO,
+    )
+    .await;
+    // the other origin would need a second answer from the server, so is found not to be fetched
+    let html = html::parse_html(
+        r#"<html><body><iframe src="https://example.com/ad.html" /><iframe src="../frame.html" /></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        load_frames(&host, "docs/index.html", &html, MAX_PAGE_SIZE).await,
+        vec![(
+            "../frame.html".to_string(),
+            html::parse_html(source.to_string()).unwrap()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_load_favicon() {
+    let source = r#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#;
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", source.len(), source),
+        std::time::Duration::ZERO,
+    )
+    .await;
+
+    // one in another format would need a second answer from the server, so is found not to be
+    // fetched
+    assert_eq!(
+        load_favicon(&host, "docs/index.html", Some("favicon.ico"), MAX_PAGE_SIZE).await,
+        None
+    );
+    assert_eq!(
+        load_favicon(&host, "docs/index.html", Some("../icon.svg"), MAX_PAGE_SIZE).await,
+        Some(source.as_bytes().to_vec())
+    );
+    assert_eq!(
+        load_favicon(&host, "docs/index.html", None, MAX_PAGE_SIZE).await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_load_images() {
+    let source = r#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#;
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", source.len(), source),
+        std::time::Duration::ZERO,
+    )
+    .await;
+    // the PNG would need a second answer from the server, so is found not to be fetched
+    let html = html::parse_html(
+        r#"<html><body><img src="photo.png" /><img src="../icons/logo.SVG?v=2" /></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        load_images(&host, "docs/index.html", &html, MAX_PAGE_SIZE).await,
+        vec![(
+            "../icons/logo.SVG?v=2".to_string(),
+            source.as_bytes().to_vec()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_broken_image_alt_text() {
+    // the server answers for the page only, so the image fails to load
+    let body = r#"<html><body><img src="missing.svg" alt="A missing logo"></body></html>"#;
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+        std::time::Duration::ZERO,
+    )
+    .await;
+    let mut app = App {
+        host,
+        ..App::default()
+    };
+    app.start_loading();
+    let html = app.html.clone();
+    for _ in 0..250 {
+        if html.lock().unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    let html = html.lock().unwrap();
+    let state = render::layout(
+        html.as_ref().unwrap(),
+        800,
+        600,
+        &RenderOptions {
+            images: &app.images.lock().unwrap(),
+            ..RenderOptions::default()
+        },
+    );
+    let texts = state
+        .text_runs
+        .iter()
+        .map(|(_, text)| text.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["A missing logo"]);
+}
+
+#[tokio::test]
+async fn test_block_images() {
+    let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#;
+    let image_host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", svg.len(), svg),
+        std::time::Duration::ZERO,
+    )
+    .await;
+    let body = format!(
+        r#"<html><body><img src="http://{}/logo.svg" alt="Logo"></body></html>"#,
+        image_host
+    );
+    let host = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+        std::time::Duration::ZERO,
+    )
+    .await;
+
+    let mut app = App {
+        host,
+        block_images: true,
+        ..App::default()
+    };
+    app.start_loading();
+    let html = app.html.clone();
+    for _ in 0..250 {
+        if html.lock().unwrap().is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(html.lock().unwrap().is_some());
+    assert!(app.images.lock().unwrap().is_empty());
+
+    // the image server is still waiting for its one request
+    let image = fetch(page_url(&image_host, "logo.svg"), MAX_PAGE_SIZE).await;
+    assert_eq!(image.unwrap(), (200, svg.to_string()));
+}
+
+/// The client all pages, stylesheets and images load through, as set up by [`init_http_client`].
+fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(|| build_http_client(None, cookie_jar().clone()).unwrap())
+}
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// The cookies of this session, which the shared client stores and sends.
+fn cookie_jar() -> &'static Arc<CookieJar> {
+    COOKIE_JAR.get_or_init(Arc::default)
+}
+
+static COOKIE_JAR: OnceLock<Arc<CookieJar>> = OnceLock::new();
+
+/// Cookies pages set with `Set-Cookie`, sent back on later requests until cleared. They live
+/// only as long as the process.
+#[derive(Default)]
+struct CookieJar(RwLock<reqwest::cookie::Jar>);
+
+impl CookieJar {
+    fn clear(&self) {
+        *self.0.write().unwrap() = reqwest::cookie::Jar::default();
+    }
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(
+        &self,
+        headers: &mut dyn Iterator<Item = &reqwest::header::HeaderValue>,
+        url: &reqwest::Url,
+    ) {
+        self.0.read().unwrap().set_cookies(headers, url);
+    }
+
+    fn cookies(&self, url: &reqwest::Url) -> Option<reqwest::header::HeaderValue> {
+        self.0.read().unwrap().cookies(url)
+    }
+}
+
+/// Sets up the shared client before the first fetch, sending every request through `proxy` if
+/// one is given.
+fn init_http_client(proxy: Option<&str>) -> reqwest::Result<()> {
+    let client = build_http_client(proxy, cookie_jar().clone())?;
+    let _ = HTTP_CLIENT.set(client);
+
+    Ok(())
+}
+
+/// A client with TLS so `https://` URLs work as well as local ones, keeping cookies in `cookies`.
+/// Without an explicit `proxy`, reqwest picks one up from `HTTP_PROXY` and `HTTPS_PROXY`, minus
+/// the hosts in `NO_PROXY`.
+fn build_http_client(
+    proxy: Option<&str>,
+    cookies: Arc<CookieJar>,
+) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .use_native_tls()
+        .cookie_provider(cookies);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+
+    builder.build()
+}
+
+#[tokio::test]
+async fn test_build_http_client() {
+    let body = "<html><body>from the proxy</body></html>";
+    let proxy = serve_once(
+        "200 OK",
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+        std::time::Duration::ZERO,
+    )
+    .await;
+
+    // the host doesn't exist, so only the proxy can have answered
+    let client = build_http_client(Some(&format!("http://{}", proxy)), Arc::default()).unwrap();
+    let resp = client
+        .get("http://nowhere.invalid/index.html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.text().await.unwrap(), body);
+
+    assert!(build_http_client(Some("not a url"), Arc::default()).is_err());
+}
+
+#[tokio::test]
+async fn test_cookie_jar() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // a server that sets a cookie on the first request, and answers each later one with the
+    // cookies it was sent
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        for set_cookie in ["Set-Cookie: session=abc; Path=/\r\n", "", ""] {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).await.unwrap();
+            let cookie = String::from_utf8_lossy(&request[..read])
+                .lines()
+                .find_map(|line| line.strip_prefix("cookie: ").map(str::to_string))
+                .unwrap_or_default();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+                set_cookie,
+                cookie.len(),
+                cookie
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    let jar = Arc::new(CookieJar::default());
+    let client = build_http_client(None, jar.clone()).unwrap();
+    let get = |path: &str| {
+        let request = client.get(format!("http://{}/{}", host, path)).send();
+        async { request.await.unwrap().text().await.unwrap() }
+    };
+    assert_eq!(get("login.html").await, "");
+    assert_eq!(get("account.html").await, "session=abc");
+
+    jar.clear();
+    assert_eq!(get("account.html").await, "");
+}
+
+/// Builds the page shown when a fetch or parse fails. The url and message are added as text
+/// nodes rather than spliced into markup, so they are never parsed as HTML.
+fn error_page(url: &str, message: &str) -> HtmlElement {
+    let words = |div: HtmlElement, text: &str| {
+        text.split_whitespace()
+            .fold(div, |div, word| div.child(HtmlElement::text(word)))
+    };
+
+    HtmlElement::element("html")
+        .child(
+            HtmlElement::element("head").child(
+                HtmlElement::element("title").child(HtmlElement::text("Failed to load page")),
+            ),
+        )
+        .child(
+            HtmlElement::element("body")
+                .attr("bgcolor", "#fff0f0")
+                .attr("text", "#880000")
+                .child(words(
+                    HtmlElement::element("div"),
+                    "Could not load the page.",
+                ))
+                .child(words(HtmlElement::element("div").attr("id", "url"), url))
+                .child(words(
+                    HtmlElement::element("div").attr("id", "message"),
+                    message,
+                )),
+        )
+}
+
+#[test]
+fn test_error_page() {
+    fn texts(element: &HtmlElement, out: &mut Vec<String>) {
+        if let Some(text) = &element.text_node {
+            out.push(text.clone());
+        }
+        for child in &element.children {
+            texts(child, out);
+        }
+    }
+
+    let page = error_page(
+        "http://localhost:8000/missing.html",
+        "error sending request: <connection refused>",
+    );
+
+    let mut words = vec![];
+    texts(&page, &mut words);
+
+    assert!(words.contains(&"http://localhost:8000/missing.html".to_string()));
+    assert!(words.ends_with(&[
+        "error".to_string(),
+        "sending".to_string(),
+        "request:".to_string(),
+        "<connection".to_string(),
+        "refused>".to_string(),
+    ]));
+
+    // the same tree as the markup with the text written in it
+    assert_eq!(
+        error_page("missing.html", "not found"),
+        html::parse_html(
+            r##"<html>
+  <head>
+    <title>Failed to load page</title>
+  </head>
+  <body bgcolor="#fff0f0" text="#880000">
+    <div>Could not load the page.</div>
+    <div id="url">missing.html</div>
+    <div id="message">not found</div>
+  </body>
+</html>
+"##
+            .to_string()
+        )
+        .unwrap()
+    );
+}
+
+/// Command-line options: `[--flag...] [host | url]`, the host defaulting to `localhost:8000`.
+#[derive(Debug, PartialEq)]
+struct Args {
+    host: String,
+    /// An absolute URL to open instead of the host's index page.
+    url: Option<String>,
+    blank_targets: BlankTargets,
+    /// Open `mailto:` and `tel:` links with the platform's URL handler.
+    open_external_schemes: bool,
+    print_nav_history: bool,
+    fonts: FontSettings,
+    force_dark: bool,
+    crisp_edges: bool,
+    block_images: bool,
+    /// File with the reader's own stylesheet, which wins over the page's.
+    user_stylesheet: Option<String>,
+    /// Largest page body to accept, in bytes.
+    max_page_size: usize,
+    profile: bool,
+    /// Print the layout of this page and exit instead of opening a window.
+    dump_layout: Option<String>,
+    /// Print the accessibility tree of this page and exit instead of opening a window.
+    dump_ax_tree: Option<String>,
+    /// Print the parsed tree of each of these pages and exit instead of opening a window.
+    print_dom: Vec<String>,
+    /// Proxy every request goes through, in place of any from the environment.
+    proxy: Option<String>,
+    /// Prefixes of URLs never to load.
+    blocked_urls: Vec<String>,
+    /// URL prefixes to load from another prefix instead, as `(from, to)`.
+    rewritten_urls: Vec<(String, String)>,
+    /// Show the page piped to stdin instead of fetching one.
+    stdin: bool,
+    /// URL the page piped to stdin is taken to be at.
+    base_url: Option<String>,
+    /// Distance scrolled by an arrow key or one wheel notch.
+    scroll_line: f32,
+    smooth_scroll: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        host: "localhost:8000".to_string(),
+        url: None,
+        blank_targets: BlankTargets::default(),
+        open_external_schemes: false,
+        print_nav_history: false,
+        fonts: FontSettings::default(),
+        force_dark: false,
+        crisp_edges: false,
+        block_images: false,
+        user_stylesheet: None,
+        max_page_size: MAX_PAGE_SIZE,
+        profile: false,
+        dump_layout: None,
+        dump_ax_tree: None,
+        print_dom: vec![],
+        proxy: None,
+        blocked_urls: vec![],
+        rewritten_urls: vec![],
+        stdin: false,
+        base_url: None,
+        scroll_line: SCROLL_LINE,
+        smooth_scroll: false,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(value) = arg.strip_prefix("--blank-targets=") {
+            parsed.blank_targets = value.parse()?;
+        } else if arg == "--open-external-schemes" {
+            parsed.open_external_schemes = true;
+        } else if arg == "--nav-history" {
+            parsed.print_nav_history = true;
+        } else if let Some(value) = arg.strip_prefix("--font-size=") {
+            parsed.fonts.default_size = parse_font_size(value)?;
+        } else if let Some(value) = arg.strip_prefix("--min-font-size=") {
+            parsed.fonts.minimum_size = parse_font_size(value)?;
+        } else if arg == "--force-dark" {
+            parsed.force_dark = true;
+        } else if arg == "--crisp-edges" {
+            parsed.crisp_edges = true;
+        } else if arg == "--no-images" {
+            parsed.block_images = true;
+        } else if let Some(value) = arg.strip_prefix("--user-stylesheet=") {
+            parsed.user_stylesheet = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--max-page-size=") {
+            parsed.max_page_size = parse_page_size(value)?;
+        } else if arg == "--profile" {
+            parsed.profile = true;
+        } else if let Some(value) = arg.strip_prefix("--proxy=") {
+            parsed.proxy = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--block-url=") {
+            parsed.blocked_urls.push(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--rewrite-url=") {
+            let (from, to) = value.split_once('=').ok_or(format!(
+                "Invalid --rewrite-url value, expected FROM=TO: {}",
+                value
+            ))?;
+            parsed
+                .rewritten_urls
+                .push((from.to_string(), to.to_string()));
+        } else if let Some(value) = arg.strip_prefix("--scroll-line=") {
+            parsed.scroll_line = value
+                .trim_end_matches("px")
+                .parse::<f32>()
+                .ok()
+                .filter(|line| line.is_finite() && *line > 0.0)
+                .ok_or(format!("Invalid scroll distance: {}", value))?;
+        } else if arg == "--stdin" {
+            parsed.stdin = true;
+        } else if let Some(value) = arg.strip_prefix("--base-url=") {
+            if !is_absolute_url(value) {
+                return Err(format!("--base-url needs an absolute URL: {}", value));
+            }
+            parsed.base_url = Some(value.to_string());
+        } else if arg == "--smooth-scroll" {
+            parsed.smooth_scroll = true;
+        } else if arg == "--dump-layout" {
+            parsed.dump_layout = Some(args.next().ok_or("--dump-layout needs a page")?);
+        } else if arg == "--dump-ax-tree" {
+            parsed.dump_ax_tree = Some(args.next().ok_or("--dump-ax-tree needs a page")?);
+        } else if arg == "--print-dom" {
+            parsed
+                .print_dom
+                .push(args.next().ok_or("--print-dom needs a page")?);
+        } else if arg.starts_with("--") {
+            return Err(format!("Unknown flag: {}", arg));
+        } else if is_absolute_url(&arg) {
+            parsed.url = Some(arg);
+        } else {
+            parsed.host = arg;
+        }
+    }
+    if parsed.base_url.is_some() && !parsed.stdin {
+        return Err("--base-url only applies to a page piped in with --stdin".to_string());
+    }
+
+    Ok(parsed)
+}
+
+/// A navigation hook blocking URLs that start with any of the `blocked` prefixes, and else
+/// redirecting those starting with the first of a rewrite's prefixes to the same URL under the
+/// second. `None` if there are no rules.
+fn url_rules(blocked: Vec<String>, rewrites: Vec<(String, String)>) -> Option<NavigationHook> {
+    if blocked.is_empty() && rewrites.is_empty() {
+        return None;
+    }
+
+    Some(Box::new(move |url| {
+        if blocked
+            .iter()
+            .any(|prefix| url.starts_with(prefix.as_str()))
+        {
+            return NavigationDecision::Block;
+        }
+
+        rewrites
+            .iter()
+            .find_map(|(from, to)| {
+                url.strip_prefix(from.as_str())
+                    .map(|rest| NavigationDecision::Redirect(format!("{}{}", to, rest)))
+            })
+            .unwrap_or(NavigationDecision::Allow)
+    }))
+}
+
+#[test]
+fn test_url_rules() {
+    assert!(url_rules(vec![], vec![]).is_none());
+
+    let hook = url_rules(
+        vec!["http://ads.example/".to_string()],
+        vec![(
+            "https://example.com/".to_string(),
+            "http://localhost:8000/".to_string(),
+        )],
+    )
+    .unwrap();
+    let cases = vec![
+        ("http://ads.example/banner.html", NavigationDecision::Block),
+        (
+            "https://example.com/docs/a.html",
+            NavigationDecision::Redirect("http://localhost:8000/docs/a.html".to_string()),
+        ),
+        (
+            "http://localhost:8000/index.html",
+            NavigationDecision::Allow,
+        ),
+    ];
+
+    for (url, want) in cases {
+        assert_eq!(hook(url), want, "{}", url);
+    }
+}
+
+/// A font size flag value in pixels, with or without the `px`.
+fn parse_font_size(value: &str) -> Result<f32, String> {
+    value
+        .trim_end_matches("px")
+        .parse::<f32>()
+        .ok()
+        .filter(|size| size.is_finite() && *size > 0.0)
+        .ok_or(format!("Invalid font size: {}", value))
+}
+
+/// A page size flag value in bytes, or in kibibytes or mebibytes with a `K` or `M` suffix.
+fn parse_page_size(value: &str) -> Result<usize, String> {
+    let (number, unit) = match value.strip_suffix(['K', 'k']) {
+        Some(number) => (number, 1024),
+        None => match value.strip_suffix(['M', 'm']) {
+            Some(number) => (number, 1024 * 1024),
+            None => (value, 1),
+        },
+    };
+
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .filter(|size| *size > 0)
+        .ok_or(format!("Invalid page size: {}", value))
+}
+
+#[test]
+fn test_parse_args() {
+    let parse = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+
+    let defaults = parse(&[]).unwrap();
+    assert_eq!(defaults.host, "localhost:8000");
+    assert_eq!(defaults.url, None);
+    assert_eq!(defaults.fonts, FontSettings::default());
+    assert_eq!(defaults.user_stylesheet, None);
+    assert_eq!(defaults.max_page_size, MAX_PAGE_SIZE);
+    assert_eq!(defaults.dump_layout, None);
+    assert_eq!(defaults.dump_ax_tree, None);
+    assert!(defaults.print_dom.is_empty());
+    assert_eq!(defaults.proxy, None);
+    assert_eq!(defaults.scroll_line, SCROLL_LINE);
+
+    let args = parse(&[
+        "--dump-layout",
+        "page.html",
+        "--dump-ax-tree",
+        "other.html",
+        "example.com:8080",
+        "--blank-targets=external",
+        "--open-external-schemes",
+        "--nav-history",
+        "--font-size=40",
+        "--min-font-size=12px",
+        "--user-stylesheet=dark.css",
+        "--max-page-size=2M",
+        "--force-dark",
+        "--crisp-edges",
+        "--no-images",
+        "--profile",
+        "--proxy=http://localhost:3128",
+        "--scroll-line=60px",
+        "--smooth-scroll",
+        "--stdin",
+        "--base-url=https://example.com/docs/",
+    ])
+    .unwrap();
+    assert_eq!(
+        args,
+        Args {
+            host: "example.com:8080".to_string(),
+            url: None,
+            blank_targets: BlankTargets::External,
+            open_external_schemes: true,
+            print_nav_history: true,
+            fonts: FontSettings {
+                default_size: 40.0,
+                minimum_size: 12.0,
+            },
+            force_dark: true,
+            crisp_edges: true,
+            block_images: true,
+            user_stylesheet: Some("dark.css".to_string()),
+            max_page_size: 2 * 1024 * 1024,
+            profile: true,
+            dump_layout: Some("page.html".to_string()),
+            dump_ax_tree: Some("other.html".to_string()),
+            print_dom: vec![],
+            proxy: Some("http://localhost:3128".to_string()),
+            blocked_urls: vec![],
+            rewritten_urls: vec![],
+            stdin: true,
+            base_url: Some("https://example.com/docs/".to_string()),
+            scroll_line: 60.0,
+            smooth_scroll: true,
+        }
+    );
+    let args = parse(&[
+        "--block-url=http://ads.example/",
+        "--rewrite-url=https://example.com/=http://localhost:8000/",
+    ])
+    .unwrap();
+    assert_eq!(args.blocked_urls, vec!["http://ads.example/".to_string()]);
+    assert_eq!(
+        args.rewritten_urls,
+        vec![(
+            "https://example.com/".to_string(),
+            "http://localhost:8000/".to_string()
+        )]
+    );
+    assert!(parse(&["--rewrite-url=nowhere"]).is_err());
+    assert_eq!(
+        parse(&["--print-dom", "-", "--print-dom", "b.html"])
+            .unwrap()
+            .print_dom,
+        vec!["-".to_string(), "b.html".to_string()]
+    );
+
+    let args = parse(&["https://example.com/page.html"]).unwrap();
+    assert_eq!(args.host, "localhost:8000");
+    assert_eq!(args.url, Some("https://example.com/page.html".to_string()));
+
+    assert!(parse(&["--dump-layout"]).is_err());
+    assert!(parse(&["--dump-ax-tree"]).is_err());
+    assert!(parse(&["--print-dom"]).is_err());
+    assert!(parse(&["--blank-targets=sometimes"]).is_err());
+    assert!(parse(&["--font-size=0"]).is_err());
+    assert!(parse(&["--min-font-size=big"]).is_err());
+    assert!(parse(&["--unknown"]).is_err());
+    assert!(parse(&["--scroll-line=-5"]).is_err());
+    assert!(parse(&["--stdin", "--base-url=docs/"]).is_err());
+    assert!(parse(&["--base-url=https://example.com/"]).is_err());
+    assert_eq!(parse(&["--max-page-size=512"]).unwrap().max_page_size, 512);
+    assert_eq!(
+        parse(&["--max-page-size=64k"]).unwrap().max_page_size,
+        64 * 1024
+    );
+    assert!(parse(&["--max-page-size=0"]).is_err());
+    assert!(parse(&["--max-page-size=lots"]).is_err());
+}
+
+/// Reads `path` from disk if such a file exists, or stdin for `-`, otherwise fetches it from
+/// `host`, or from wherever it points if an absolute URL.
+async fn load_source(
+    host: &str,
+    path: &str,
+    max_size: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if path == "-" {
+        let mut source = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut source)?;
+        return Ok(source);
+    }
+    if std::path::Path::new(path).is_file() {
+        return Ok(std::fs::read_to_string(path)?);
+    }
+
+    if is_absolute_url(path) {
+        let (_, body) = fetch(path.to_string(), max_size).await?;
+        return Ok(body);
+    }
+
+    let _process = start_server().await?;
+    let (_, body) = fetch(page_url(host, path.trim_start_matches('/')), max_size).await?;
+
+    Ok(body)
+}
+
+/// Serves `public` on port 8000 for as long as the returned process is alive.
+async fn start_server() -> Result<DroppableProcess, Box<dyn std::error::Error>> {
+    let process = DroppableProcess::new(
+        Command::new("python")
+            .arg("-m")
+            .arg("http.server")
+            .arg("8000")
+            .arg("-d")
+            .arg("public"),
+    )?;
+    ensure_server_started("http://localhost:8000", std::time::Duration::from_secs(5)).await?;
+
+    Ok(process)
+}
+
+async fn ensure_server_started(url: &str, timeout: std::time::Duration) -> Result<(), String> {
+    let start = std::time::Instant::now();
+    while start.elapsed() < timeout {
+        if let Ok(response) = reqwest::get(url).await {
+            if response.status().is_success() {
+                return Ok(());
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+    Err("Server did not start within the timeout".into())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args(std::env::args().skip(1))?;
+    init_http_client(args.proxy.as_deref())?;
+    let user_styles = match &args.user_stylesheet {
+        Some(path) => Some(css::parse_css(std::fs::read_to_string(path)?)?),
+        None => None,
+    };
+
+    if let Some(path) = &args.dump_layout {
+        let html = html::parse_html(load_source(&args.host, path, args.max_page_size).await?)?;
+        let options = RenderOptions {
+            fonts: args.fonts,
+            user_styles: user_styles.as_ref(),
+            anti_alias: !args.crisp_edges,
+            block_images: args.block_images,
+            ..RenderOptions::default()
+        };
+        let state = render::layout(&html, 800, 600, &options);
+        print!("{}", render::format_layout(&state.boxes));
+
+        return Ok(());
+    }
+
+    if let Some(path) = &args.dump_ax_tree {
+        let html = html::parse_html(load_source(&args.host, path, args.max_page_size).await?)?;
+        print!("{}", html::accessibility_tree(&html).format());
+
+        return Ok(());
+    }
+
+    if !args.print_dom.is_empty() {
+        let mut sources = vec![];
+        for path in &args.print_dom {
+            sources.push(load_source(&args.host, path, args.max_page_size).await?);
+        }
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        for html in html::parse_many(sources, threads) {
+            println!("{}", html?.to_html());
+        }
+
+        return Ok(());
+    }
+
+    // a page piped in needs no server, its links resolving against `--base-url` if given
+    let piped_source = match args.stdin {
+        true => Some(load_source(&args.host, "-", args.max_page_size).await?),
+        false => None,
+    };
+    // extend the lifetime of the process to the end of the program
+    let _process = match piped_source {
+        Some(_) => None,
+        None => Some(start_server().await?),
+    };
 
     let event_loop = EventLoop::new().unwrap();
 
     event_loop.set_control_flow(ControlFlow::Wait);
 
     let mut app = App::default();
-    app.host = host.clone();
+    app.host = args.host;
+    app.path = args.base_url.or(args.url).unwrap_or_default();
+    app.piped_source = piped_source;
+    app.blank_targets = args.blank_targets;
+    app.zoom = 1.0;
+    app.scroll_line = args.scroll_line;
+    app.smooth_scroll = args.smooth_scroll;
+    app.fonts = args.fonts;
+    app.user_styles = user_styles;
+    app.force_dark = args.force_dark;
+    app.crisp_edges = args.crisp_edges;
+    app.block_images = args.block_images;
+    app.open_external_schemes = args.open_external_schemes;
+    app.print_nav_history = args.print_nav_history;
+    app.max_page_size = Some(args.max_page_size);
+    app.profile = args.profile;
+    app.navigation_hook = url_rules(args.blocked_urls, args.rewritten_urls);
     event_loop.run_app(&mut app).unwrap();
 
     Ok(())
@@ -13,7 +13,7 @@ impl DroppableProcess {
 
 impl Drop for DroppableProcess {
     fn drop(&mut self) {
-        println!("Killing child process");
+        eprintln!("Killing child process");
 
         if let Err(err) = self.child.kill() {
             eprintln!("Failed to kill child process: {}", err);
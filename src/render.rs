@@ -0,0 +1,4966 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use skia_safe::font::Edging;
+use skia_safe::typeface::TypefaceId;
+use skia_safe::{svg, Canvas, Font, FontMgr, Paint, Path, Rect, TextBlob, Typeface};
+
+use crate::cascade::{Cascade, DarkMode, Element};
+use crate::css;
+use crate::helper::{styled_typeface, typeface_for_families, typeface_for_lang};
+use crate::html::{HtmlElement, NodeTrace, WalkControl, BLOCK_LEVEL_ELEMENTS, SOFT_HYPHEN};
+
+/// Elements that end the current line when they close.
+const BLOCK_ELEMENTS: &[&str] = &["div", "blockquote", "pre"];
+
+pub const BLOCKQUOTE_INDENT: f32 = 40.0;
+
+/// Size of an SVG image that doesn't give its own `width` and `height`, as for any replaced
+/// element.
+const SVG_SIZE: (f32, f32) = (300.0, 150.0);
+
+/// How far the items of `<ol>` and `<ul>` are indented, leaving room for their markers.
+pub const LIST_INDENT: f32 = 40.0;
+
+/// How far a `<dd>` is indented from the terms of its `<dl>`.
+pub const DEFINITION_INDENT: f32 = 40.0;
+
+/// Opening and closing marks of a `<q>`, then of one nested in it, and so on alternately.
+const QUOTE_MARKS: [(char, char); 2] = [('"', '"'), ('\'', '\'')];
+
+/// A word as the element path of its parent and its index among the parent's children.
+pub type WordKey = (String, usize);
+
+/// Space between the edges of a table cell and its content.
+const CELL_PADDING: f32 = 4.0;
+
+/// Space between the edges of the box standing in for an image that isn't drawn and its alt
+/// text.
+const ALT_TEXT_PADDING: f32 = 4.0;
+
+/// Space between the edges of a button and its label.
+const BUTTON_PADDING: f32 = 6.0;
+
+/// Size of the bar a `<progress>` or `<meter>` is drawn as.
+const BAR_SIZE: (f32, f32) = (160.0, 16.0);
+
+/// Space between the edges of an `<iframe>` and the content of the document in it.
+const FRAME_MARGIN: f32 = 8.0;
+
+/// Space between the edges of a `<textarea>` and its text.
+const TEXTAREA_PADDING: f32 = 2.0;
+
+/// Width of the space between two words.
+const SPACE_WIDTH: f32 = 8.0;
+
+/// Elements whose legacy `align` attribute is honored as `text-align`.
+const ALIGN_ELEMENTS: &[&str] = &["div", "p", "h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Insets of the page content area from the window edges. `top` leaves room for the chrome.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            left: 25.0,
+            top: 120.0,
+            right: 25.0,
+            bottom: 0.0,
+        }
+    }
+}
+
+type TextCacheKey = (String, u32, TypefaceId, bool);
+
+/// Measured bounds and shaped blobs of text runs, keyed by (text, font size, typeface, whether
+/// antialiased). The typeface tells styles apart. Kept across redraws of a page and cleared on
+/// navigation and zoom.
+#[derive(Default)]
+pub struct TextCache {
+    entries: HashMap<TextCacheKey, Option<(Rect, TextBlob)>>,
+    /// `Font::measure_str` results, which runs only measured for where to break them need too.
+    measures: HashMap<TextCacheKey, (f32, Rect)>,
+    pub misses: usize,
+    /// How many times text was actually measured.
+    pub measurements: usize,
+}
+
+impl TextCache {
+    fn key(font: &Font, text: &str) -> TextCacheKey {
+        (
+            text.to_string(),
+            font.size().to_bits(),
+            font.typeface().unique_id(),
+            font.edging() != Edging::Alias,
+        )
+    }
+
+    pub fn get(&mut self, font: &Font, text: &str) -> Option<(Rect, TextBlob)> {
+        let key = TextCache::key(font, text);
+        if let Some(entry) = self.entries.get(&key) {
+            return entry.clone();
+        }
+
+        self.misses += 1;
+        let entry = TextBlob::from_str(text, font).map(|blob| (self.measure(font, text).1, blob));
+        self.entries.insert(key, entry.clone());
+        entry
+    }
+
+    /// Advance width and bounds of `text` in `font`, measured once however often it's asked for.
+    pub fn measure(&mut self, font: &Font, text: &str) -> (f32, Rect) {
+        *self
+            .measures
+            .entry(TextCache::key(font, text))
+            .or_insert_with(|| {
+                self.measurements += 1;
+                font.measure_str(text, None)
+            })
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.measures.clear();
+    }
+}
+
+/// A clickable anchor: where it points and, if given, the browsing context it asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub href: String,
+    pub target: Option<String>,
+}
+
+/// The summary of a `<details>` element, which opens or closes it when clicked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disclosure {
+    /// The `<details>` element's path from the root, e.g. `html[0]:body[1]:details[0]`. Stays the
+    /// same across redraws of the page.
+    pub key: String,
+    pub open: bool,
+}
+
+/// A `<textarea>`, which takes the reader's typing once clicked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextField {
+    /// The element's path from the root, as for a `Disclosure`.
+    pub key: String,
+    /// The text in it: what the reader made of it, or else its initial content.
+    pub value: String,
+    /// Takes no focus, and so no typing either.
+    pub disabled: bool,
+    /// Takes the focus but not the reader's edits.
+    pub readonly: bool,
+}
+
+/// The extent of an element or text run after layout, in document order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutBox {
+    /// Nesting depth, 0 for the root element.
+    pub depth: usize,
+    pub name: String,
+    pub rect: Rect,
+    /// The painted text, for text runs.
+    pub text: Option<String>,
+    /// The text color as `0xrrggbb`, for text runs.
+    pub color: Option<u32>,
+    /// Weight, slant and decorations, for text runs.
+    pub style: Option<TextStyle>,
+    /// Family of the typeface the text is set in, for text runs.
+    pub family: Option<String>,
+}
+
+/// How text is set, from the presentational tags around it (`<b>`, `<i>`, `<u>`, and `<s>` or
+/// `<strike>`), `text-decoration`, and the `background-color` and `vertical-align` of inline
+/// elements.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub overline: bool,
+    pub line_through: bool,
+    /// Highlight painted behind the glyphs, as `0xrrggbb`.
+    pub background: Option<u32>,
+    /// How far above the line's baseline the text's own sits, in pixels. Negative lowers it.
+    pub baseline_shift: f32,
+    pub vertical_align: VerticalAlign,
+}
+
+/// Where an inline run sits on its line, from `vertical-align`. `sub` and `super` are the
+/// baseline, shifted.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VerticalAlign {
+    #[default]
+    Baseline,
+    /// Centered on half the x-height above the baseline.
+    Middle,
+    /// Against the top or bottom of the line, however tall it is.
+    Top,
+    Bottom,
+}
+
+impl TextStyle {
+    /// The style of text inside a `name` element that is itself set in this style. The element's
+    /// `text-decoration`, if it lists any lines or `none`, replaces the lines its tag implies;
+    /// lines from outer elements are drawn either way. Its `background`, if any, covers the one
+    /// from outside. Its `baseline_shift` adds to the one it's already shifted by, and its
+    /// `vertical_align`, if any, replaces the one from outside.
+    fn within(
+        self,
+        name: &str,
+        text_decoration: Option<&str>,
+        background: Option<u32>,
+        baseline_shift: f32,
+        vertical_align: Option<VerticalAlign>,
+    ) -> Self {
+        let mut own = match name {
+            "b" => TextStyle {
+                bold: true,
+                ..TextStyle::default()
+            },
+            "i" => TextStyle {
+                italic: true,
+                ..TextStyle::default()
+            },
+            "u" => TextStyle {
+                underline: true,
+                ..TextStyle::default()
+            },
+            "s" | "strike" => TextStyle {
+                line_through: true,
+                ..TextStyle::default()
+            },
+            _ => TextStyle::default(),
+        };
+
+        if let Some(value) = text_decoration {
+            let keywords = value.to_ascii_lowercase();
+            let has = |keyword| keywords.split_whitespace().any(|word| word == keyword);
+            if ["none", "underline", "overline", "line-through"]
+                .into_iter()
+                .any(has)
+            {
+                own.underline = has("underline");
+                own.overline = has("overline");
+                own.line_through = has("line-through");
+            }
+        }
+
+        TextStyle {
+            bold: self.bold || own.bold,
+            italic: self.italic || own.italic,
+            underline: self.underline || own.underline,
+            overline: self.overline || own.overline,
+            line_through: self.line_through || own.line_through,
+            background: background.or(self.background),
+            baseline_shift: self.baseline_shift + baseline_shift,
+            vertical_align: vertical_align.unwrap_or(self.vertical_align),
+        }
+    }
+}
+
+/// Horizontal alignment of the lines in a block, from `text-align`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TextAlign {
+    /// The side lines begin on: left, or right for right-to-left text.
+    #[default]
+    Start,
+    End,
+    Left,
+    Center,
+    Right,
+}
+
+/// Which way a line's runs advance, from `direction` or the `dir` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    /// Direction of the first character with a strong direction in `text`, if any.
+    fn of_text(text: &str) -> Option<Self> {
+        text.chars().find_map(|c| match c as u32 {
+            // Hebrew, Arabic, Syriac, Thaana, NKo and their presentation forms
+            0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF => Some(Direction::Rtl),
+            _ if c.is_alphabetic() => Some(Direction::Ltr),
+            _ => None,
+        })
+    }
+}
+
+impl TextAlign {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "start" => Some(TextAlign::Start),
+            "end" => Some(TextAlign::End),
+            "left" => Some(TextAlign::Left),
+            "center" => Some(TextAlign::Center),
+            "right" => Some(TextAlign::Right),
+            _ => None,
+        }
+    }
+}
+
+/// What a run on a line paints once the line is finished.
+pub enum RunContent {
+    Text(TextBlob),
+    /// An image, painted as it was laid out with the canvas moved to where it ends up.
+    Image(Box<dyn FnOnce(&Canvas)>),
+}
+
+/// A text run or image waiting for the rest of its line, to be shifted into place and painted.
+pub struct LineRun {
+    content: RunContent,
+    origin: (f32, f32),
+    paint: Paint,
+    /// Where the run's rect is in `boxes`, `text_runs` for text and an image's alt text, and, for
+    /// links and titled elements, `hyper_links` and `titles`.
+    layout_box: usize,
+    text_run: Option<usize>,
+    hyper_link: Option<usize>,
+    title: Option<usize>,
+    /// Extent of the run's line height above and below its baseline, with its top where the
+    /// line's is as laid out.
+    above_baseline: f32,
+    below_baseline: f32,
+    /// `Top` and `Bottom` runs leave the baseline to the others, and `Middle` ones set their
+    /// extents to center on it.
+    vertical_align: VerticalAlign,
+    /// Underline and strikethrough lines, painted in the run's color.
+    decorations: Vec<Rect>,
+    /// Highlight behind the glyphs, reaching back over the space before like the decorations.
+    background: Option<(Rect, u32)>,
+}
+
+/// A cell's slot in its table's grid: its first column and row, and how many of each it spans.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridCell {
+    pub column: usize,
+    pub row: usize,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+/// An open `<table>`, laid out as equal columns across the width it starts in.
+pub struct TableLayout {
+    left: f32,
+    column_width: f32,
+    /// Every cell of the table, in document order.
+    cells: Vec<GridCell>,
+    /// How many of `cells` have been entered.
+    next_cell: usize,
+    /// Top edge of each row entered so far, then of the one after the last row ended.
+    row_tops: Vec<f32>,
+    /// Lowest content of the cells ending on each row.
+    row_bottoms: Vec<f32>,
+    /// Index into `boxes` of each cell entered, with its slot. Cells take the height of the rows
+    /// they span once the last one ends.
+    cell_boxes: Vec<(usize, GridCell)>,
+    /// Margins outside the open cell, restored when it closes.
+    outer_margins: Option<(f32, f32)>,
+}
+
+pub struct RendererState {
+    pub hyper_links: Vec<(Rect, Link)>,
+    /// Every text run inside an inline element with a `title`, with the innermost such title.
+    pub titles: Vec<(Rect, String)>,
+    pub disclosures: Vec<(Rect, Disclosure)>,
+    pub text_fields: Vec<(Rect, TextField)>,
+    /// Index into `boxes` of the `<summary>` of each disclosure, whose rect it takes once laid
+    /// out.
+    pub summary_boxes: Vec<usize>,
+    /// Key, open state and summary child index of each open `<details>`.
+    pub details: Vec<(String, bool, Option<usize>)>,
+    /// `name[index]` of each element currently being laid out, from the root down.
+    pub element_path: Vec<String>,
+    /// Border width of each open block element, if it has a border.
+    pub borders: Vec<Option<f32>>,
+    /// Index into `boxes` of each bordered element, with its border's width and color. Borders
+    /// are painted around the element's box once all lines are aligned.
+    pub bordered_boxes: Vec<(usize, f32, u32)>,
+    /// Every painted text run with its source text, in document order.
+    pub text_runs: Vec<(Rect, String)>,
+    pub current_color: String,
+    /// Where the next run goes: its left edge and the top of its line.
+    pub cursor_position: (f32, f32),
+    /// Where lines start; moved right while inside indented blocks.
+    pub left_margin: f32,
+    /// Where lines wrap; moved left while inside a table cell.
+    pub right_margin: f32,
+    pub layout: HashMap<String, String>,
+    /// Bottom edge of each open `<div>`, if it has a fixed height.
+    pub blocks: Vec<Option<f32>>,
+    /// Top edge of each open `<blockquote>`, for drawing its bar on close.
+    pub quote_tops: Vec<f32>,
+    /// Each open `<ol>` as its `type` and the number of its next item, or `None` for `<ul>`.
+    pub lists: Vec<(Option<char>, i64)>,
+    /// Each open `<table>`, the innermost last.
+    pub tables: Vec<TableLayout>,
+    /// The first and last words of each open `<q>`, outermost first, which its opening and
+    /// closing marks are set before and after. `None` if it has no words.
+    pub quotes: Vec<Option<(WordKey, WordKey)>>,
+    /// Whether a whitespace node was passed since the last word.
+    pub pending_space: bool,
+    /// Parent trace and child index of the last word painted. The next sibling word gets a
+    /// space before it, as its source was split from the same run of text.
+    pub last_word: Option<(NodeTrace, usize)>,
+    pub boxes: Vec<LayoutBox>,
+    /// Indices into `boxes` of the elements currently being laid out.
+    pub open_boxes: Vec<usize>,
+    /// Index into `boxes` of each element laid out so far, with the end of its contents. Element
+    /// boxes are grown to cover their contents once all lines are aligned.
+    pub closed_boxes: Vec<(usize, usize)>,
+    /// The `text-align` each open element sets, if any. The innermost one applies.
+    pub text_aligns: Vec<Option<TextAlign>>,
+    /// Style of text in each open element. The innermost one applies.
+    pub text_styles: Vec<TextStyle>,
+    /// Runs laid out on the current line, which are painted when it ends.
+    pub line: Vec<LineRun>,
+    /// Left margin, alignment and direction in effect where the current line started.
+    pub line_start: (f32, TextAlign, Direction),
+    /// Height of a line without any text on it, such as the blank line after an empty `<div>`.
+    pub strut: f32,
+    pub typeface: Typeface,
+    pub cascade: Cascade,
+    pub text_cache: TextCache,
+    /// Lowest edge of anything painted, measured from the top of the content area.
+    pub document_height: f32,
+    /// Timings, if the render was profiled.
+    pub stats: Option<RenderStats>,
+}
+
+impl RendererState {
+    fn extend_document(&mut self, bottom: f32, viewport: Viewport) {
+        self.document_height = self.document_height.max(bottom - viewport.top);
+    }
+
+    fn text_align(&self) -> TextAlign {
+        self.text_aligns
+            .iter()
+            .rev()
+            .find_map(|text_align| *text_align)
+            .unwrap_or_default()
+    }
+
+    /// Space the current line takes above and below its baseline, so far. Runs on the baseline
+    /// share it, so the line is as tall as their tallest extents on either side combined, and then
+    /// as its tallest run against the top or bottom. One against the bottom taller than the others
+    /// pushes the baseline down, and one against the top the line's bottom.
+    fn line_extent(&self) -> Option<(f32, f32)> {
+        if self.line.is_empty() {
+            return None;
+        }
+
+        let (mut above, mut below, mut top, mut bottom) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+        for run in &self.line {
+            let height = run.above_baseline + run.below_baseline;
+            match run.vertical_align {
+                VerticalAlign::Top => top = top.max(height),
+                VerticalAlign::Bottom => bottom = bottom.max(height),
+                VerticalAlign::Baseline | VerticalAlign::Middle => {
+                    above = above.max(run.above_baseline);
+                    below = below.max(run.below_baseline);
+                }
+            }
+        }
+        above += (bottom - above - below).max(0.0);
+        below += (top - above - below).max(0.0);
+
+        Some((above, below))
+    }
+
+    /// Height of the current line, or of a blank one if nothing is on it yet.
+    fn line_height(&self) -> f32 {
+        self.line_extent()
+            .map_or(self.strut, |(above, below)| above + below)
+    }
+
+    /// Moves the runs on the current line onto a shared baseline and to its alignment against
+    /// `right`, and paints them.
+    ///
+    /// Runs are always laid out left to right. A right-to-left line is mirrored within its
+    /// margins here, so its first run ends up at the right margin and later ones advance leftward.
+    fn finish_line(&mut self, canvas: &Canvas, right: f32, viewport: Viewport) {
+        let (Some(last), Some((above, below))) = (self.line.last(), self.line_extent()) else {
+            return;
+        };
+
+        let started = self.stats.is_some().then(Instant::now);
+        let (left, text_align, direction) = self.line_start;
+        let rtl = direction == Direction::Rtl;
+        let free = (right - self.boxes[last.layout_box].rect.right).max(0.0);
+        // how far right of the start side the line moves
+        let offset = match (text_align, rtl) {
+            (TextAlign::Start, _) | (TextAlign::Left, false) | (TextAlign::Right, true) => 0.0,
+            (TextAlign::End, _) | (TextAlign::Right, false) | (TextAlign::Left, true) => free,
+            (TextAlign::Center, _) => free / 2.0,
+        };
+
+        for run in std::mem::take(&mut self.line) {
+            // runs were placed as if their own baseline were the line's, and their top its top
+            let dy = match run.vertical_align {
+                VerticalAlign::Baseline | VerticalAlign::Middle => above - run.above_baseline,
+                VerticalAlign::Top => 0.0,
+                VerticalAlign::Bottom => above + below - run.above_baseline - run.below_baseline,
+            };
+            let rect = self.boxes[run.layout_box].rect;
+            let dx = if rtl {
+                left + right - rect.right - rect.left - offset
+            } else {
+                offset
+            };
+
+            if let Some(index) = run.text_run {
+                self.text_runs[index].0.offset((dx, dy));
+            }
+            self.boxes[run.layout_box].rect.offset((dx, dy));
+            if let Some(index) = run.hyper_link {
+                self.hyper_links[index].0.offset((dx, dy));
+            }
+            if let Some(index) = run.title {
+                self.titles[index].0.offset((dx, dy));
+            }
+            self.extend_document(self.boxes[run.layout_box].rect.bottom, viewport);
+
+            // lines may reach past the run into the space before it, so are mirrored themselves
+            let mirror = |rect: Rect| {
+                let (left, right) = if rtl {
+                    (
+                        left + right - rect.right - offset,
+                        left + right - rect.left - offset,
+                    )
+                } else {
+                    (rect.left + offset, rect.right + offset)
+                };
+                Rect::new(left, rect.top + dy, right, rect.bottom + dy)
+            };
+            if let Some((rect, color)) = run.background {
+                let mut paint = PaintExt(run.paint.clone());
+                paint.set_color_u32(color);
+                canvas.draw_rect(mirror(rect), &paint.0);
+            }
+            match run.content {
+                RunContent::Text(blob) => {
+                    canvas.draw_text_blob(
+                        &blob,
+                        (run.origin.0 + dx, run.origin.1 + dy),
+                        &run.paint,
+                    );
+                }
+                RunContent::Image(draw) => {
+                    canvas.save();
+                    canvas.translate((dx, dy));
+                    draw(canvas);
+                    canvas.restore();
+                }
+            }
+            for decoration in run.decorations {
+                canvas.draw_rect(mirror(decoration), &run.paint);
+            }
+        }
+
+        if let (Some(stats), Some(started)) = (self.stats.as_mut(), started) {
+            stats.paint += started.elapsed();
+        }
+    }
+
+    /// Ends the current line and moves the cursor to the start of the next one.
+    fn break_line(&mut self, canvas: &Canvas, right: f32, viewport: Viewport) {
+        let height = self.line_height();
+        self.finish_line(canvas, right, viewport);
+        self.cursor_position = (self.left_margin, self.cursor_position.1 + height);
+    }
+}
+
+pub struct PaintExt(pub Paint);
+
+impl Default for PaintExt {
+    fn default() -> Self {
+        PaintExt::new(true)
+    }
+}
+
+impl PaintExt {
+    /// A paint with antialiasing explicitly on or, for pixel-crisp edges, off.
+    pub fn new(anti_alias: bool) -> Self {
+        let mut paint = Paint::default();
+        paint.set_anti_alias(anti_alias);
+
+        PaintExt(paint)
+    }
+
+    /// Sets a CSS color such as `#ff0000` or `red`, leaving the paint as is if it doesn't parse.
+    pub fn set_color_hex(&mut self, hex: &str) {
+        if let Some(color) = css::parse_color(hex) {
+            self.set_color_u32(color);
+        }
+    }
+
+    pub fn set_color_u32(&mut self, color: u32) {
+        self.0.set_argb(
+            0xFF,
+            (color >> 16) as u8 & 0xFF,
+            (color >> 8) as u8 & 0xFF,
+            color as u8 & 0xFF,
+        );
+    }
+}
+
+/// The reader's font size preferences, before zoom is applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontSettings {
+    /// Size of body text, which `medium` and relative sizes resolve against.
+    pub default_size: f32,
+    /// Computed sizes below this are raised to it.
+    pub minimum_size: f32,
+}
+
+impl Default for FontSettings {
+    fn default() -> Self {
+        FontSettings {
+            default_size: 32.0,
+            minimum_size: 0.0,
+        }
+    }
+}
+
+impl FontSettings {
+    /// Computed font size of text in `trace`, resolving each `font-size` against the one outside
+    /// it.
+    fn size(&self, trace: &NodeTrace, cascade: &Cascade) -> f32 {
+        (1..=trace.0.len())
+            .fold(self.default_size, |parent, end| {
+                specified_property(&trace.0[..end], cascade, "font-size")
+                    .and_then(|value| self.resolve(&value, parent))
+                    .unwrap_or(parent)
+            })
+            .max(self.minimum_size)
+    }
+
+    /// Resolves a CSS `font-size`: an absolute keyword scaled from the default size, a length, or
+    /// an `em`, percentage or `larger`/`smaller` relative to the `parent` size.
+    fn resolve(&self, value: &str, parent: f32) -> Option<f32> {
+        let medium = self.default_size;
+        let value = value.trim();
+        let size = match value {
+            "xx-small" => medium * 3.0 / 5.0,
+            "x-small" => medium * 3.0 / 4.0,
+            "small" => medium * 8.0 / 9.0,
+            "medium" => medium,
+            "large" => medium * 6.0 / 5.0,
+            "x-large" => medium * 3.0 / 2.0,
+            "xx-large" => medium * 2.0,
+            "xxx-large" => medium * 3.0,
+            "larger" => parent * 1.2,
+            "smaller" => parent / 1.2,
+            _ => match value.strip_suffix("em") {
+                Some(em) => em.trim().parse::<f32>().ok()? * parent,
+                None => css::parse_length(value, parent)?,
+            },
+        };
+
+        (size > 0.0).then_some(size)
+    }
+}
+
+/// Per-redraw inputs to `render` that come from the browser rather than the page.
+pub struct RenderOptions<'a> {
+    pub viewport: Viewport,
+    /// How far the page is scrolled down, in pixels.
+    pub scroll_offset: f32,
+    /// Scale applied to everything on the page, 1.0 being unzoomed.
+    pub zoom: f32,
+    pub fonts: FontSettings,
+    pub is_visited: &'a dyn Fn(&str) -> bool,
+    /// Whether the reader opened or closed the `<details>` with the given key. `None` if they
+    /// haven't toggled it, leaving it as its `open` attribute says.
+    pub details_open: &'a dyn Fn(&str) -> Option<bool>,
+    /// The text of the `<textarea>` with the given key, if the reader edited it. `None` leaves the
+    /// initial content.
+    pub field_value: &'a dyn Fn(&str) -> Option<String>,
+    /// Key of the `<textarea>` typing goes into, which is drawn with a caret.
+    pub focused_field: Option<&'a str>,
+    /// Whether the focused field's caret is in the on half of its blink.
+    pub caret_visible: bool,
+    /// The sheets the page's `<link rel="stylesheet">`s point to, in document order.
+    pub linked_styles: &'a [css::Styles],
+    /// The SVG sources of the page's `<img>`s.
+    pub images: &'a [Image],
+    /// The documents of the page's `<iframe>`s.
+    pub frames: &'a [Frame],
+    /// Draw each `<img>` as a box with its `alt` text, as for one that failed to load.
+    pub block_images: bool,
+    /// The reader's stylesheet, which takes priority over the page's.
+    pub user_styles: Option<&'a css::Styles>,
+    pub dark_mode: DarkMode,
+    /// Antialias text and shapes. Off gives pixel-crisp output, which doesn't depend on the
+    /// platform's antialiasing.
+    pub anti_alias: bool,
+    /// Time the render, for [`RendererState::stats`].
+    pub profile: bool,
+}
+
+/// An `<img>`'s `src` as written, and the image it points to.
+pub type Image = (String, Vec<u8>);
+
+/// An `<iframe>`'s `src` as written, and the document it points to.
+pub type Frame = (String, HtmlElement);
+
+/// Where the time of a profiled render went.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    /// Walking the tree: cascading styles, and placing boxes and text.
+    pub layout: Duration,
+    /// Drawing finished lines of text onto the canvas.
+    pub paint: Duration,
+}
+
+impl Default for RenderOptions<'_> {
+    fn default() -> Self {
+        RenderOptions {
+            viewport: Viewport::default(),
+            scroll_offset: 0.0,
+            zoom: 1.0,
+            fonts: FontSettings::default(),
+            is_visited: &|_| false,
+            details_open: &|_| None,
+            field_value: &|_| None,
+            focused_field: None,
+            caret_visible: true,
+            linked_styles: &[],
+            images: &[],
+            frames: &[],
+            block_images: false,
+            user_styles: None,
+            dark_mode: DarkMode::Off,
+            anti_alias: true,
+            profile: false,
+        }
+    }
+}
+
+/// Paints the page below the chrome. Recorded rects are in document coordinates, i.e. zoomed
+/// but before `scroll_offset` is applied.
+pub fn render(
+    canvas: &Canvas,
+    html: &HtmlElement,
+    width: u32,
+    height: u32,
+    options: &RenderOptions,
+    text_cache: &mut TextCache,
+) -> RendererState {
+    let viewport = options.viewport;
+    let is_visited = options.is_visited;
+    let details_open = options.details_open;
+    let forced_dark = options.dark_mode == DarkMode::Forced;
+    let anti_alias = options.anti_alias;
+    let zoom = options.zoom;
+    // the page is laid out unzoomed into a correspondingly smaller window, then scaled up
+    let layout_width = width as f32 / zoom;
+    let layout_height = viewport.top + (height as f32 - viewport.top) / zoom;
+    let right_edge = layout_width - viewport.right;
+    let head = html.head_metadata();
+    let started = options.profile.then(Instant::now);
+
+    let mut state = RendererState {
+        hyper_links: Vec::new(),
+        titles: vec![],
+        disclosures: Vec::new(),
+        text_fields: Vec::new(),
+        summary_boxes: vec![],
+        details: vec![],
+        element_path: vec![],
+        borders: vec![],
+        bordered_boxes: vec![],
+        text_runs: Vec::new(),
+        current_color: "#000000".to_string(),
+        cursor_position: (viewport.left, viewport.top),
+        left_margin: viewport.left,
+        right_margin: right_edge,
+        layout: HashMap::new(),
+        blocks: vec![],
+        quote_tops: vec![],
+        lists: vec![],
+        tables: vec![],
+        quotes: vec![],
+        pending_space: false,
+        last_word: None,
+        boxes: vec![],
+        open_boxes: vec![],
+        closed_boxes: vec![],
+        text_aligns: vec![],
+        text_styles: vec![],
+        line: vec![],
+        line_start: (viewport.left, TextAlign::Start, Direction::Ltr),
+        strut: 0.0,
+        typeface: typeface_for_lang(
+            html.attributes
+                .iter()
+                .find(|(key, _)| key == "lang")
+                .map(|(_, lang)| lang.as_str()),
+        ),
+        cascade: Cascade::new(
+            &head.styles,
+            options.linked_styles,
+            options.user_styles,
+            options.dark_mode,
+            layout_width,
+        ),
+        text_cache: std::mem::take(text_cache),
+        document_height: 0.0,
+        stats: started.map(|_| RenderStats::default()),
+    };
+    state.strut = line_height(
+        &Font::from_typeface(
+            state.typeface.clone(),
+            options.fonts.default_size.max(options.fonts.minimum_size),
+        ),
+        None,
+    );
+
+    canvas.save();
+    canvas.clip_rect(
+        Rect::new(0.0, viewport.top, width as f32, height as f32),
+        None,
+        None,
+    );
+    canvas.translate((0.0, viewport.top - options.scroll_offset));
+    canvas.scale((zoom, zoom));
+    canvas.translate((0.0, -viewport.top));
+
+    html.walk(
+        Rc::new(
+            move |trace: NodeTrace,
+                  name: String,
+                  index: usize,
+                  attributes: Vec<(String, String)>,
+                  children: Vec<HtmlElement>,
+                  text_node: Option<String>,
+                  state: &mut RendererState| {
+                let right_edge = state.right_margin;
+                // metadata is extracted up front, nothing in <head> is painted as content, nor
+                // a <title> misplaced outside it. A <template> holds content for scripts to use.
+                if name == "head" || name == "title" || name == "template" {
+                    return WalkControl::SkipChildren;
+                }
+                // <script> bodies are dropped by the parser and never run, so <noscript> needs no
+                // special casing: its fallback content is painted like any other inline content
+
+                if inline_style(&attributes).get("display") == Some("none") {
+                    return WalkControl::SkipChildren;
+                }
+
+                // a closed <details> shows its summary only
+                let parent = if text_node.is_some() {
+                    trace.0.last()
+                } else {
+                    trace.0.iter().rev().nth(1)
+                };
+                // the markup inside an inline <svg> is drawn as an image, not laid out as text
+                if text_node.is_some() && parent.is_some_and(|(name, _)| name == "svg") {
+                    return WalkControl::Continue;
+                }
+                // and the content of a <button>, <textarea> or <iframe> is drawn with its box.
+                // That of an <iframe>, <progress> or <meter> is only a fallback for browsers
+                // that can't draw them.
+                let ancestors = if text_node.is_some() {
+                    &trace.0[..]
+                } else {
+                    &trace.0[..trace.0.len() - 1]
+                };
+                if ancestors.iter().any(|(name, _)| {
+                    ["button", "textarea", "iframe", "progress", "meter"].contains(&name.as_str())
+                }) {
+                    return if text_node.is_some() {
+                        WalkControl::Continue
+                    } else {
+                        WalkControl::SkipChildren
+                    };
+                }
+                let in_details = parent.is_some_and(|(name, _)| name == "details");
+                if let Some((_, open, summary)) = state.details.last().filter(|_| in_details) {
+                    if !open && *summary != Some(index) {
+                        return WalkControl::SkipChildren;
+                    }
+                }
+
+                if let Some((_, style)) = attributes.iter().find(|(key, _)| key == "style") {
+                    let styles = css::parse_css(style.clone()).unwrap();
+
+                    if styles.get_first("display") == Some("flex") {
+                        let gap_str = styles.get_first("gap").unwrap();
+
+                        for i in 0..children.len() {
+                            if i == 0 {
+                                continue;
+                            }
+
+                            state.layout.insert(
+                                trace.names().join(":")
+                                    + ":"
+                                    + children[i].name.as_str()
+                                    + format!("[{}]", i).as_str()
+                                    + "."
+                                    + "gap-left",
+                                gap_str.to_string(),
+                            );
+                        }
+                    }
+
+                    eprintln!("{:?}", state.layout);
+                }
+
+                eprintln!("{:?} ({:?}:{:?})", trace, name, text_node);
+
+                if name != "textNode" {
+                    // inline style wins over stylesheets, which win over the presentational hint
+                    let text_align = inline_style(&attributes)
+                        .get("text-align")
+                        .and_then(TextAlign::parse)
+                        .or_else(|| {
+                            state
+                                .cascade
+                                .get(&trace.0, &[], "text-align")
+                                .and_then(TextAlign::parse)
+                        })
+                        .or_else(|| {
+                            attributes
+                                .iter()
+                                .find(|(key, _)| {
+                                    key == "align" && ALIGN_ELEMENTS.contains(&name.as_str())
+                                })
+                                .and_then(|(_, value)| TextAlign::parse(value))
+                        });
+                    // an element setting its own alignment or displayed as a block lays out on
+                    // lines of its own
+                    let display_block = specified_property(&trace.0, &state.cascade, "display")
+                        .is_some_and(|display| display == "block");
+                    if (text_align.is_some() || display_block)
+                        && state.cursor_position.0 > state.left_margin
+                    {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+                    state.text_aligns.push(text_align);
+                    let text_decoration = inline_style(&attributes)
+                        .get("text-decoration")
+                        .map(str::to_string)
+                        .or_else(|| {
+                            state
+                                .cascade
+                                .get(
+                                    &trace.0,
+                                    link_pseudo_classes(&name, &attributes, is_visited),
+                                    "text-decoration",
+                                )
+                                .map(str::to_string)
+                        });
+                    // forced dark mode keeps the page's light highlights from showing through
+                    let background = (!forced_dark
+                        && !BLOCK_LEVEL_ELEMENTS.contains(&name.as_str()))
+                    .then(|| specified_property(&trace.0, &state.cascade, "background-color"))
+                    .flatten()
+                    .and_then(|color| css::parse_color(&color));
+                    // superscripts and subscripts move by a share of the text size around them
+                    let parent_font_size = |cascade| {
+                        let parent = NodeTrace(trace.0[..trace.0.len() - 1].to_vec());
+                        options.fonts.size(&parent, cascade)
+                    };
+                    let vertical_align =
+                        specified_property(&trace.0, &state.cascade, "vertical-align")
+                            .map(|value| value.trim().to_ascii_lowercase());
+                    let baseline_shift = match vertical_align.as_deref() {
+                        Some("super") => parent_font_size(&state.cascade) / 3.0,
+                        Some("sub") => -parent_font_size(&state.cascade) / 5.0,
+                        _ => 0.0,
+                    };
+                    let vertical_align = match vertical_align.as_deref() {
+                        Some("baseline" | "sub" | "super") => Some(VerticalAlign::Baseline),
+                        Some("middle") => Some(VerticalAlign::Middle),
+                        Some("top") => Some(VerticalAlign::Top),
+                        Some("bottom") => Some(VerticalAlign::Bottom),
+                        _ => None,
+                    };
+                    let text_style = state.text_styles.last().copied().unwrap_or_default();
+                    state.text_styles.push(text_style.within(
+                        &name,
+                        text_decoration.as_deref(),
+                        background,
+                        baseline_shift,
+                        vertical_align,
+                    ));
+
+                    state.element_path.push(format!("{}[{}]", name, index));
+                    if name == "q" {
+                        let mut words = vec![];
+                        word_keys(&state.element_path.join(":"), &children, &mut words);
+                        state
+                            .quotes
+                            .push(words.first().cloned().zip(words.last().cloned()));
+                    }
+                    let (x, y) = state.cursor_position;
+                    state.open_boxes.push(state.boxes.len());
+                    state.boxes.push(LayoutBox {
+                        depth: trace.0.len() - 1,
+                        name: name.clone(),
+                        rect: Rect::new(x, y, x, y),
+                        text: None,
+                        color: None,
+                        style: None,
+                        family: None,
+                    });
+                }
+                let mut paint = PaintExt::new(anti_alias);
+
+                if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    let border = border(&trace, &state.cascade);
+                    if let Some((width, color)) = border {
+                        if state.cursor_position.0 > state.left_margin {
+                            state.break_line(canvas, right_edge, viewport);
+                        }
+
+                        // the content is laid out inside the top and left edges
+                        state
+                            .bordered_boxes
+                            .push((state.boxes.len() - 1, width, color));
+                        state.left_margin += width;
+                        state.cursor_position =
+                            (state.left_margin, state.cursor_position.1 + width);
+                    }
+                    state.borders.push(border.map(|(width, _)| width));
+                }
+
+                if name == "div" {
+                    let style = inline_style(&attributes);
+                    let top = state.cursor_position.1;
+                    let content_width = layout_width - viewport.left - viewport.right;
+
+                    let box_width = style
+                        .get("width")
+                        .and_then(|value| css::parse_length(value, content_width))
+                        .unwrap_or(content_width);
+                    let box_height = style.get("height").and_then(|value| {
+                        css::parse_length(value, layout_height - viewport.top - viewport.bottom)
+                    });
+
+                    // Without a fixed height the box size is only known after its children are
+                    // laid out, so the background is drawn for fixed-height boxes only.
+                    // forced dark mode keeps the page's light boxes from showing through
+                    if let (Some(box_height), Some(background), false) =
+                        (box_height, style.get("background-color"), forced_dark)
+                    {
+                        paint.set_color_hex(background);
+                        canvas.draw_rect(
+                            Rect::new(
+                                state.left_margin,
+                                top,
+                                state.left_margin + box_width,
+                                top + box_height,
+                            ),
+                            &paint.0,
+                        );
+                    }
+
+                    if let Some(box_height) = box_height {
+                        state.extend_document(top + box_height, viewport);
+                        if let Some(layout_box) = state.boxes.last_mut() {
+                            layout_box.rect = Rect::new(
+                                state.left_margin,
+                                top,
+                                state.left_margin + box_width,
+                                top + box_height,
+                            );
+                        }
+                    }
+                    state
+                        .blocks
+                        .push(box_height.map(|box_height| top + box_height));
+                }
+
+                if name == "blockquote" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    state.quote_tops.push(state.cursor_position.1);
+                    state.left_margin += BLOCKQUOTE_INDENT;
+                    state.cursor_position.0 = state.left_margin;
+                }
+
+                if name == "dd" {
+                    state.left_margin += DEFINITION_INDENT;
+                    state.cursor_position.0 = state.left_margin;
+                }
+
+                if name == "ol" || name == "ul" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|(k, _)| k == key)
+                            .map(|(_, value)| value.trim())
+                    };
+                    let list = if name == "ol" {
+                        let kind = attribute("type")
+                            .filter(|kind| ["1", "a", "A", "i", "I"].contains(kind))
+                            .and_then(|kind| kind.chars().next())
+                            .unwrap_or('1');
+                        let start = attribute("start")
+                            .and_then(|start| start.parse().ok())
+                            .unwrap_or(1);
+                        (Some(kind), start)
+                    } else {
+                        (None, 1)
+                    };
+                    state.lists.push(list);
+                    state.left_margin += LIST_INDENT;
+                    state.cursor_position.0 = state.left_margin;
+                }
+
+                if name == "li" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    // the number or bullet hangs in the list's indent, ending a space before the
+                    // item's first line
+                    let marker = state.lists.last_mut().map(|(kind, next)| match kind {
+                        Some(kind) => {
+                            *next += 1;
+                            format!("{}.", list_marker(*next - 1, *kind))
+                        }
+                        None => "\u{2022}".to_string(),
+                    });
+                    let font = font(
+                        state.typeface.clone(),
+                        options.fonts.size(&trace, &state.cascade),
+                        anti_alias,
+                    );
+                    if let Some((rect, blob)) = marker
+                        .as_ref()
+                        .and_then(|marker| state.text_cache.get(&font, marker))
+                    {
+                        let line_height = line_height(
+                            &font,
+                            inherited_property(&trace, &state.cascade, "line-height").as_deref(),
+                        );
+                        let (_, metrics) = font.metrics();
+                        let half_leading = (line_height + metrics.ascent - metrics.descent) / 2.0;
+                        let pos = (
+                            state.left_margin - SPACE_WIDTH - rect.width(),
+                            state.cursor_position.1 + half_leading - metrics.ascent,
+                        );
+                        let color = inherited_property(&trace, &state.cascade, "color")
+                            .and_then(|color| css::parse_color(&color))
+                            .or_else(|| css::parse_color(&state.current_color))
+                            .unwrap_or(0x000000);
+                        paint.set_color_u32(color);
+                        canvas.draw_text_blob(&blob, pos, &paint.0);
+                        state.boxes.push(LayoutBox {
+                            depth: trace.0.len(),
+                            name: "::marker".to_string(),
+                            rect: Rect::new(
+                                pos.0,
+                                pos.1 + metrics.ascent,
+                                pos.0 + rect.width(),
+                                pos.1 + metrics.descent,
+                            ),
+                            text: marker,
+                            color: Some(color),
+                            style: None,
+                            family: Some(font.typeface().family_name()),
+                        });
+                    }
+                }
+
+                // only SVG images are drawn for now. An image that is blocked, failed to load or
+                // can't be decoded leaves a box with its alt text in its place.
+                let svg = match name.as_str() {
+                    "img" if !options.block_images => attributes
+                        .iter()
+                        .find(|(key, _)| key == "src")
+                        .and_then(|(_, src)| options.images.iter().find(|(image, _)| image == src))
+                        .map(|(_, bytes)| bytes.clone()),
+                    "svg" => Some(
+                        HtmlElement {
+                            name: name.clone(),
+                            attributes: attributes.clone(),
+                            children: children.clone(),
+                            text_node: None,
+                        }
+                        .to_html()
+                        .into_bytes(),
+                    ),
+                    _ => None,
+                };
+                let dom = svg.and_then(|svg| {
+                    svg::Dom::from_bytes(&svg, FontMgr::new())
+                        .map_err(|err| eprintln!("Ignoring SVG: {}", err))
+                        .ok()
+                });
+                if dom.is_some() || name == "img" {
+                    let dimension = |key: &str, default: f32| {
+                        attributes
+                            .iter()
+                            .find(|(k, _)| k == key)
+                            .and_then(|(_, value)| value.trim_end_matches("px").parse::<f32>().ok())
+                            .filter(|size| size.is_finite() && *size > 0.0)
+                            .unwrap_or(default)
+                    };
+                    let font = font(
+                        state.typeface.clone(),
+                        options.fonts.size(&trace, &state.cascade),
+                        anti_alias,
+                    );
+                    let line_height = line_height(&font, None);
+                    let (_, metrics) = font.metrics();
+                    let alt = attributes
+                        .iter()
+                        .find(|(key, _)| key == "alt")
+                        .filter(|_| dom.is_none())
+                        .map(|(_, alt)| alt.trim().to_string());
+                    let text = alt
+                        .as_deref()
+                        .and_then(|alt| state.text_cache.get(&font, alt));
+                    // sized to fit the alt text, unless the <img> gives its own size
+                    let (width, height) = if dom.is_some() {
+                        (
+                            dimension("width", SVG_SIZE.0),
+                            dimension("height", SVG_SIZE.1),
+                        )
+                    } else {
+                        let text_width = text.as_ref().map_or(0.0, |(rect, _)| rect.width());
+                        (
+                            dimension("width", text_width + 2.0 * ALT_TEXT_PADDING),
+                            dimension("height", line_height + 2.0 * ALT_TEXT_PADDING),
+                        )
+                    };
+
+                    // set inline like a word, so after a space and wrapping before the right edge
+                    if state.pending_space && state.cursor_position.0 > state.left_margin {
+                        state.cursor_position.0 += SPACE_WIDTH;
+                    }
+                    if state.cursor_position.0 > state.left_margin
+                        && state.cursor_position.0 + width > right_edge
+                    {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+                    let (x, y) = state.cursor_position;
+                    let rect = Rect::from_xywh(x, y, width, height);
+
+                    let mut text_run = None;
+                    let draw: Box<dyn FnOnce(&Canvas)> = if let Some(mut dom) = dom {
+                        dom.set_container_size((width, height));
+                        Box::new(move |canvas: &Canvas| {
+                            canvas.save();
+                            canvas.translate((x, y));
+                            dom.render(canvas);
+                            canvas.restore();
+                        })
+                    } else {
+                        let half_leading = (line_height + metrics.ascent - metrics.descent) / 2.0;
+                        let origin = (
+                            x + ALT_TEXT_PADDING,
+                            y + ALT_TEXT_PADDING + half_leading - metrics.ascent,
+                        );
+                        let blob = alt.zip(text).map(|(alt, (text_rect, blob))| {
+                            let run = Rect::new(
+                                origin.0,
+                                origin.1 + metrics.ascent,
+                                origin.0 + text_rect.width(),
+                                origin.1 + metrics.descent,
+                            );
+                            state.text_runs.push((run, alt));
+                            text_run = Some(state.text_runs.len() - 1);
+                            blob
+                        });
+
+                        let mut edge_paint = PaintExt(paint.0.clone());
+                        edge_paint.set_color_hex("#999999");
+                        let mut text_paint = PaintExt(paint.0.clone());
+                        text_paint.set_color_hex("#666666");
+                        Box::new(move |canvas: &Canvas| {
+                            for edge in edges(rect) {
+                                canvas.draw_rect(edge, &edge_paint.0);
+                            }
+                            if let Some(blob) = blob {
+                                canvas.save();
+                                canvas.clip_rect(rect, None, None);
+                                canvas.draw_text_blob(&blob, origin, &text_paint.0);
+                                canvas.restore();
+                            }
+                        })
+                    };
+
+                    if let Some(layout_box) = state.boxes.last_mut() {
+                        layout_box.rect = rect;
+                    }
+                    if state.line.is_empty() {
+                        let direction = match inherited_property(
+                            &trace,
+                            &state.cascade,
+                            "direction",
+                        )
+                        .as_deref()
+                        {
+                            Some("rtl") => Direction::Rtl,
+                            _ => Direction::Ltr,
+                        };
+                        state.line_start = (state.left_margin, state.text_align(), direction);
+                    }
+                    // the bottom sits on the baseline, or the middle half the x-height above it
+                    let text_style = state.text_styles.last().copied().unwrap_or_default();
+                    let above_baseline = text_style.baseline_shift
+                        + match text_style.vertical_align {
+                            VerticalAlign::Middle => (height + metrics.x_height) / 2.0,
+                            _ => height,
+                        };
+                    state.line.push(LineRun {
+                        content: RunContent::Image(draw),
+                        origin: (x, y),
+                        paint: paint.0.clone(),
+                        layout_box: state.boxes.len() - 1,
+                        text_run,
+                        hyper_link: None,
+                        title: None,
+                        above_baseline,
+                        below_baseline: height - above_baseline,
+                        vertical_align: text_style.vertical_align,
+                        decorations: vec![],
+                        background: None,
+                    });
+                    state.cursor_position.0 = rect.right;
+                    state.pending_space = false;
+                }
+
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, value)| value.clone())
+                };
+                let input_type = attribute("type").map(|kind| kind.to_ascii_lowercase());
+                let label = match (name.as_str(), input_type.as_deref()) {
+                    ("button", _) => Some(
+                        children
+                            .iter()
+                            .map(|child| child.text_content())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                    ("input", Some("button")) => Some(attribute("value").unwrap_or_default()),
+                    ("input", Some("submit")) => {
+                        Some(attribute("value").unwrap_or_else(|| "Submit".to_string()))
+                    }
+                    ("input", Some("reset")) => {
+                        Some(attribute("value").unwrap_or_else(|| "Reset".to_string()))
+                    }
+                    _ => None,
+                };
+                let disabled = attribute("disabled").is_some();
+                if let Some(label) = label {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    let label = label.split_whitespace().collect::<Vec<_>>().join(" ");
+                    let font = font(
+                        state.typeface.clone(),
+                        options.fonts.size(&trace, &state.cascade),
+                        anti_alias,
+                    );
+                    let line_height = line_height(&font, None);
+                    let text = state.text_cache.get(&font, &label);
+                    let text_width = text.as_ref().map_or(0.0, |(rect, _)| rect.width());
+                    let rect = Rect::from_xywh(
+                        state.left_margin,
+                        state.cursor_position.1,
+                        text_width + 2.0 * BUTTON_PADDING,
+                        line_height + 2.0 * BUTTON_PADDING,
+                    );
+
+                    // greyed out when disabled
+                    let (fill, edge, label_color) = if disabled {
+                        ("#f5f5f5", "#c0c0c0", "#a0a0a0")
+                    } else {
+                        ("#efefef", "#767676", "#000000")
+                    };
+                    paint.set_color_hex(fill);
+                    canvas.draw_rect(rect, &paint.0);
+                    paint.set_color_hex(edge);
+                    for edge in edges(rect) {
+                        canvas.draw_rect(edge, &paint.0);
+                    }
+                    if let Some((text_rect, blob)) = text {
+                        let (_, metrics) = font.metrics();
+                        let half_leading = (line_height + metrics.ascent - metrics.descent) / 2.0;
+                        let origin = (
+                            rect.left + BUTTON_PADDING,
+                            rect.top + BUTTON_PADDING + half_leading - metrics.ascent,
+                        );
+                        paint.set_color_hex(label_color);
+                        canvas.draw_text_blob(&blob, origin, &paint.0);
+
+                        let run = Rect::new(
+                            origin.0,
+                            origin.1 + metrics.ascent,
+                            origin.0 + text_rect.width(),
+                            origin.1 + metrics.descent,
+                        );
+                        state.text_runs.push((run, label));
+                    }
+
+                    // a button follows the innermost anchor around it, or submits its form
+                    let submits = match name.as_str() {
+                        "button" => !matches!(input_type.as_deref(), Some("button" | "reset")),
+                        _ => input_type.as_deref() == Some("submit"),
+                    };
+                    let link = trace.0.iter().rev().find_map(|(name, attributes)| {
+                        let attribute = |key: &str| {
+                            attributes
+                                .iter()
+                                .find(|(k, _)| k == key)
+                                .map(|(_, value)| value.clone())
+                        };
+                        match name.as_str() {
+                            "a" => attribute("href").map(|href| Link {
+                                href,
+                                target: attribute("target"),
+                            }),
+                            "form" if submits => Some(Link {
+                                href: attribute("action").unwrap_or_default(),
+                                target: attribute("target"),
+                            }),
+                            _ => None,
+                        }
+                    });
+                    // a disabled button can't be clicked, so submits nothing
+                    if let Some(link) = link.filter(|link| !disabled && is_navigable(&link.href)) {
+                        state.hyper_links.push((rect, link));
+                    }
+
+                    if let Some(layout_box) = state.boxes.last_mut() {
+                        layout_box.rect = rect;
+                    }
+                    state.cursor_position.1 = rect.bottom;
+                    state.extend_document(rect.bottom, viewport);
+                }
+
+                // a bar filled as far as the value is from the minimum to the maximum, or left
+                // empty if they don't say
+                if name == "progress" || name == "meter" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    let number = |key: &str| {
+                        attribute(key)
+                            .and_then(|value| value.trim().parse::<f32>().ok())
+                            .filter(|number| number.is_finite())
+                    };
+                    let (min, max) = if name == "meter" {
+                        (number("min").unwrap_or(0.0), number("max").unwrap_or(1.0))
+                    } else {
+                        (0.0, number("max").filter(|max| *max > 0.0).unwrap_or(1.0))
+                    };
+                    let fraction = number("value")
+                        .filter(|_| max > min)
+                        .map_or(0.0, |value| ((value - min) / (max - min)).clamp(0.0, 1.0));
+
+                    let rect = Rect::from_xywh(
+                        state.left_margin,
+                        state.cursor_position.1,
+                        BAR_SIZE.0,
+                        BAR_SIZE.1,
+                    );
+                    paint.set_color_hex("#dddddd");
+                    canvas.draw_rect(rect, &paint.0);
+                    paint.set_color_hex(if name == "meter" {
+                        "#22aa22"
+                    } else {
+                        "#3366cc"
+                    });
+                    canvas.draw_rect(
+                        Rect::from_xywh(
+                            rect.left,
+                            rect.top,
+                            rect.width() * fraction,
+                            rect.height(),
+                        ),
+                        &paint.0,
+                    );
+
+                    if let Some(layout_box) = state.boxes.last_mut() {
+                        layout_box.rect = rect;
+                    }
+                    state.cursor_position.1 = rect.bottom;
+                    state.extend_document(rect.bottom, viewport);
+                }
+
+                // the embedded document is laid out in the box as a page of its own, with frames
+                // of its own left empty
+                if name == "iframe" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    let size = |key: &str, default: f32| {
+                        attribute(key)
+                            .and_then(|value| value.trim_end_matches("px").parse::<f32>().ok())
+                            .filter(|size| size.is_finite() && *size > 0.0)
+                            .unwrap_or(default)
+                    };
+                    let rect = Rect::from_xywh(
+                        state.left_margin,
+                        state.cursor_position.1,
+                        size("width", SVG_SIZE.0),
+                        size("height", SVG_SIZE.1),
+                    );
+                    paint.set_color_hex("#ffffff");
+                    canvas.draw_rect(rect, &paint.0);
+
+                    let src = attribute("src");
+                    if let Some((_, document)) = options
+                        .frames
+                        .iter()
+                        .find(|(frame, _)| Some(frame) == src.as_ref())
+                    {
+                        let frame_options = RenderOptions {
+                            viewport: Viewport {
+                                left: FRAME_MARGIN,
+                                top: 0.0,
+                                right: FRAME_MARGIN,
+                                bottom: 0.0,
+                            },
+                            scroll_offset: 0.0,
+                            zoom: 1.0,
+                            fonts: options.fonts,
+                            is_visited: options.is_visited,
+                            details_open: &|_| None,
+                            field_value: &|_| None,
+                            focused_field: None,
+                            caret_visible: false,
+                            linked_styles: &[],
+                            images: &[],
+                            frames: &[],
+                            block_images: options.block_images,
+                            user_styles: options.user_styles,
+                            dark_mode: options.dark_mode,
+                            anti_alias,
+                            profile: false,
+                        };
+                        canvas.save();
+                        canvas.clip_rect(rect, None, None);
+                        canvas.translate((rect.left, rect.top));
+                        render(
+                            canvas,
+                            document,
+                            rect.width() as u32,
+                            rect.height() as u32,
+                            &frame_options,
+                            &mut state.text_cache,
+                        );
+                        canvas.restore();
+                    }
+
+                    paint.set_color_hex("#767676");
+                    for edge in edges(rect) {
+                        canvas.draw_rect(edge, &paint.0);
+                    }
+                    if let Some(layout_box) = state.boxes.last_mut() {
+                        layout_box.rect = rect;
+                    }
+                    state.cursor_position.1 = rect.bottom;
+                    state.extend_document(rect.bottom, viewport);
+                }
+
+                // sized in characters and lines of its text, which wraps inside it
+                if name == "textarea" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    let count = |key: &str, default: usize| {
+                        attribute(key)
+                            .and_then(|value| value.trim().parse::<usize>().ok())
+                            .filter(|count| *count > 0)
+                            .unwrap_or(default)
+                    };
+                    let font = font(
+                        state.typeface.clone(),
+                        options.fonts.size(&trace, &state.cascade),
+                        anti_alias,
+                    );
+                    let line_height = line_height(&font, None);
+                    let (_, metrics) = font.metrics();
+                    let rect = Rect::from_xywh(
+                        state.left_margin,
+                        state.cursor_position.1,
+                        count("cols", 20) as f32 * metrics.avg_char_width + 2.0 * TEXTAREA_PADDING,
+                        count("rows", 2) as f32 * line_height + 2.0 * TEXTAREA_PADDING,
+                    );
+
+                    let key = state.element_path.join(":");
+                    // a newline straight after the start tag isn't part of the content
+                    let value = (options.field_value)(&key).unwrap_or_else(|| {
+                        let content = children
+                            .iter()
+                            .map(|child| child.text_content())
+                            .collect::<String>();
+                        content
+                            .strip_prefix('\n')
+                            .map_or(content.clone(), str::to_string)
+                    });
+
+                    let readonly = attribute("readonly").is_some();
+                    let (fill, edge, text_color) = if disabled {
+                        ("#efefef", "#c0c0c0", "#6d6d6d")
+                    } else {
+                        ("#ffffff", "#767676", "#000000")
+                    };
+                    paint.set_color_hex(fill);
+                    canvas.draw_rect(rect, &paint.0);
+                    paint.set_color_hex(edge);
+                    for edge in edges(rect) {
+                        canvas.draw_rect(edge, &paint.0);
+                    }
+
+                    let lines = wrap_lines(&value, rect.width() - 2.0 * TEXTAREA_PADDING, |text| {
+                        state.text_cache.measure(&font, text).0
+                    });
+                    let half_leading = (line_height + metrics.ascent - metrics.descent) / 2.0;
+                    let origin = |line: usize| {
+                        (
+                            rect.left + TEXTAREA_PADDING,
+                            rect.top + TEXTAREA_PADDING + line as f32 * line_height + half_leading
+                                - metrics.ascent,
+                        )
+                    };
+                    paint.set_color_hex(text_color);
+                    canvas.save();
+                    canvas.clip_rect(rect, None, None);
+                    for (index, line) in lines.iter().enumerate() {
+                        let Some((text_rect, blob)) = state.text_cache.get(&font, line) else {
+                            continue;
+                        };
+                        let origin = origin(index);
+                        canvas.draw_text_blob(&blob, origin, &paint.0);
+                        state.text_runs.push((
+                            Rect::new(
+                                origin.0,
+                                origin.1 + metrics.ascent,
+                                origin.0 + text_rect.width(),
+                                origin.1 + metrics.descent,
+                            ),
+                            line.clone(),
+                        ));
+                    }
+                    // typing goes on at the end of the text, if it can be edited at all
+                    if options.focused_field == Some(key.as_str())
+                        && options.caret_visible
+                        && !readonly
+                    {
+                        let last = lines.len() - 1;
+                        let (x, y) = origin(last);
+                        let x = x + state.text_cache.measure(&font, &lines[last]).0;
+                        canvas.draw_rect(
+                            Rect::new(x, y + metrics.ascent, x + 1.0, y + metrics.descent),
+                            &paint.0,
+                        );
+                    }
+                    canvas.restore();
+
+                    state.text_fields.push((
+                        rect,
+                        TextField {
+                            key,
+                            value,
+                            disabled,
+                            readonly,
+                        },
+                    ));
+                    if let Some(layout_box) = state.boxes.last_mut() {
+                        layout_box.rect = rect;
+                    }
+                    state.cursor_position.1 = rect.bottom;
+                    state.extend_document(rect.bottom, viewport);
+                }
+
+                if name == "table" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    let span = |cell: &HtmlElement, key: &str| {
+                        cell.attributes
+                            .iter()
+                            .find(|(k, _)| k == key)
+                            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                            .filter(|span| *span > 0)
+                            .map_or(1, |span| span.min(1000))
+                    };
+                    let rows = children
+                        .iter()
+                        .flat_map(|child| match child.name.as_str() {
+                            "thead" | "tbody" | "tfoot" => child.children.iter().collect(),
+                            _ => vec![child],
+                        })
+                        .filter(|row| row.name == "tr")
+                        .map(|row| {
+                            row.children
+                                .iter()
+                                .filter(|cell| cell.name == "td" || cell.name == "th")
+                                .map(|cell| (span(cell, "colspan"), span(cell, "rowspan")))
+                                .collect()
+                        })
+                        .collect::<Vec<_>>();
+                    let cells = table_grid(&rows);
+                    let columns = cells
+                        .iter()
+                        .map(|cell| cell.column + cell.columns)
+                        .max()
+                        .unwrap_or(1);
+                    let top = state.cursor_position.1;
+                    state.tables.push(TableLayout {
+                        left: state.left_margin,
+                        column_width: (right_edge - state.left_margin) / columns as f32,
+                        cells,
+                        next_cell: 0,
+                        row_tops: vec![top],
+                        row_bottoms: vec![top; rows.len()],
+                        cell_boxes: vec![],
+                        outer_margins: None,
+                    });
+                }
+
+                // a cell's lines go in the slots it spans, a padding in from their edges
+                let in_row = parent.is_some_and(|(name, _)| name == "tr");
+                if let (true, Some(table)) = (
+                    in_row && (name == "td" || name == "th"),
+                    state.tables.last_mut(),
+                ) {
+                    if let Some(cell) = table.cells.get(table.next_cell).copied() {
+                        table.next_cell += 1;
+                        table.outer_margins = Some((state.left_margin, state.right_margin));
+                        table
+                            .cell_boxes
+                            .push((*state.open_boxes.last().unwrap(), cell));
+
+                        let left = table.left + cell.column as f32 * table.column_width;
+                        state.left_margin = left + CELL_PADDING;
+                        state.right_margin =
+                            left + cell.columns as f32 * table.column_width - CELL_PADDING;
+                        state.cursor_position =
+                            (state.left_margin, table.row_tops[cell.row] + CELL_PADDING);
+                        state.pending_space = false;
+                    }
+                }
+
+                if name == "details" {
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    let key = state.element_path.join(":");
+                    let open = details_open(&key)
+                        .unwrap_or_else(|| attributes.iter().any(|(key, _)| key == "open"));
+                    let summary = children.iter().position(|child| child.name == "summary");
+                    state.details.push((key, open, summary));
+                }
+
+                let is_summary = in_details
+                    && name == "summary"
+                    && state
+                        .details
+                        .last()
+                        .is_some_and(|(_, _, summary)| *summary == Some(index));
+                if is_summary {
+                    let (key, open, _) = state.details.last().unwrap().clone();
+                    if state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+
+                    // a triangle pointing at the content when open, and to the side when closed,
+                    // centered on the summary's first line
+                    let font = Font::from_typeface(
+                        state.typeface.clone(),
+                        options.fonts.size(&trace, &state.cascade),
+                    );
+                    let line_height = line_height(
+                        &font,
+                        inherited_property(&trace, &state.cascade, "line-height").as_deref(),
+                    );
+                    let side = font.size() / 2.0;
+                    let (x, top) = state.cursor_position;
+                    let y = top + (line_height - side) / 2.0;
+                    let mut marker = Path::new();
+                    if open {
+                        marker
+                            .move_to((x, y))
+                            .line_to((x + side, y))
+                            .line_to((x + side / 2.0, y + side));
+                    } else {
+                        marker
+                            .move_to((x, y))
+                            .line_to((x + side, y + side / 2.0))
+                            .line_to((x, y + side));
+                    }
+                    marker.close();
+                    paint.set_color_u32(
+                        inherited_property(&trace, &state.cascade, "color")
+                            .and_then(|color| css::parse_color(&color))
+                            .or_else(|| css::parse_color(&state.current_color))
+                            .unwrap_or(0x000000),
+                    );
+                    canvas.draw_path(&marker, &paint.0);
+                    state.extend_document(top + line_height, viewport);
+
+                    // the summary is clickable from the marker on
+                    if let Some(layout_box) = state.boxes.last_mut() {
+                        layout_box.rect = Rect::new(x, top, x + side, top + line_height);
+                    }
+                    state
+                        .disclosures
+                        .push((Rect::default(), Disclosure { key, open }));
+                    state.summary_boxes.push(state.boxes.len() - 1);
+                    state.cursor_position.0 = x + side + SPACE_WIDTH;
+                }
+
+                if name == "body" {
+                    let background = |path: &[Element]| {
+                        if forced_dark {
+                            state
+                                .cascade
+                                .get_author(path, &[], "background-color")
+                                .map(str::to_string)
+                        } else {
+                            specified_property(path, &state.cascade, "background-color")
+                        }
+                        .and_then(|color| css::parse_color(&color))
+                    };
+                    // the root's background fills the page, or failing that the body's does
+                    let root = trace.0.iter().position(|(name, _)| name == "html");
+                    let background = root
+                        .and_then(|root| background(&trace.0[..=root]))
+                        .or_else(|| background(&trace.0));
+                    if let Some(color) = background {
+                        paint.set_color_u32(color);
+                        canvas.draw_rect(
+                            Rect::new(
+                                0.0,
+                                viewport.top,
+                                layout_width,
+                                layout_height + options.scroll_offset / zoom,
+                            ),
+                            &paint.0,
+                        );
+                    }
+                } else if trace.names().contains(&"body".to_string()) {
+                    // the innermost enclosing anchor with an href wins
+                    let anchor = trace.0.iter().rposition(|(name, attributes)| {
+                        name == "a" && attributes.iter().any(|(key, _)| key == "href")
+                    });
+                    let link = anchor.map(|index| {
+                        let (_, attributes) = &trace.0[index];
+                        let attribute = |name: &str| {
+                            attributes
+                                .iter()
+                                .find(|(key, _)| key == name)
+                                .map(|(_, value)| value.clone())
+                        };
+
+                        Link {
+                            href: attribute("href").unwrap_or_default(),
+                            target: attribute("target"),
+                        }
+                    });
+                    let white_space = inherited_property(&trace, &state.cascade, "white-space");
+                    let preserve_whitespace = white_space.as_deref() == Some("pre");
+                    let wrap = !matches!(white_space.as_deref(), Some("pre" | "nowrap"));
+
+                    if let Some(text) = text_node.as_deref().filter(|text| text.trim().is_empty()) {
+                        if !preserve_whitespace {
+                            state.pending_space = true;
+                            return WalkControl::Continue;
+                        }
+
+                        for c in text.chars() {
+                            match c {
+                                '\n' => state.break_line(canvas, right_edge, viewport),
+                                '\t' => {
+                                    let tab = SPACE_WIDTH * 8.0;
+                                    let column = state.cursor_position.0 - state.left_margin;
+                                    state.cursor_position.0 =
+                                        state.left_margin + ((column / tab).floor() + 1.0) * tab;
+                                }
+                                _ => state.cursor_position.0 += SPACE_WIDTH,
+                            }
+                        }
+                        return WalkControl::Continue;
+                    }
+
+                    let is_text_node = text_node.is_some();
+                    if let Some(text_node) = text_node {
+                        let mut paint = PaintExt::new(anti_alias);
+                        let text_style = state.text_styles.last().copied().unwrap_or_default();
+                        let typeface = inherited_property(&trace, &state.cascade, "font-family")
+                            .and_then(|families| typeface_for_families(&families))
+                            .unwrap_or_else(|| state.typeface.clone());
+                        let typeface =
+                            styled_typeface(&typeface, text_style.bold, text_style.italic);
+                        let font = font(
+                            typeface,
+                            options.fonts.size(&trace, &state.cascade),
+                            anti_alias,
+                        );
+                        let line_height = line_height(
+                            &font,
+                            inherited_property(&trace, &state.cascade, "line-height").as_deref(),
+                        );
+                        let (_, metrics) = font.metrics();
+                        // the line height is shared out evenly above and below the glyphs
+                        let half_leading = (line_height + metrics.ascent - metrics.descent) / 2.0;
+                        let above_baseline = half_leading - metrics.ascent;
+
+                        // a word too long for the rest of the line breaks at its last soft hyphen
+                        // that leaves it room for a hyphen, and the rest goes on the next line
+                        // the marks of the <q>s a word starts or ends go with it, so never wrap
+                        // away from it
+                        let key = (state.element_path.join(":"), index);
+                        let mut quoted = text_node.clone();
+                        for (depth, words) in state.quotes.iter().enumerate().rev() {
+                            let (open, close) = QUOTE_MARKS[depth % QUOTE_MARKS.len()];
+                            if let Some((first, last)) = words {
+                                if *first == key {
+                                    quoted.insert(0, open);
+                                }
+                                if *last == key {
+                                    quoted.push(close);
+                                }
+                            }
+                        }
+                        let text_node = &quoted;
+
+                        let mut word = Some(text_node.clone());
+                        while let Some(remaining) = word.take() {
+                            let color = if let Some(anchor) = anchor {
+                                let (name, attributes) = &trace.0[anchor];
+                                let pseudo_classes =
+                                    link_pseudo_classes(name, attributes, is_visited);
+                                state
+                                    .cascade
+                                    .get(&trace.0[..=anchor], pseudo_classes, "color")
+                                    .and_then(css::parse_color)
+                                    .unwrap_or(0x0055ff)
+                            } else {
+                                inherited_property(&trace, &state.cascade, "color")
+                                    .and_then(|color| css::parse_color(&color))
+                                    .or_else(|| css::parse_color(&state.current_color))
+                                    .unwrap_or(0x000000)
+                            };
+                            paint.set_color_u32(color);
+                            let follows_word =
+                                state
+                                    .last_word
+                                    .as_ref()
+                                    .is_some_and(|(last_trace, last_index)| {
+                                        *last_trace == trace && last_index + 1 == index
+                                    });
+                            let space_start = state.cursor_position.0;
+                            if (state.pending_space || follows_word)
+                                && state.cursor_position.0 > state.left_margin
+                            {
+                                state.cursor_position.0 += SPACE_WIDTH;
+                            }
+
+                            let room = right_edge - state.cursor_position.0;
+                            let mut measure =
+                                |text: &str| state.text_cache.measure(&font, text).1.width();
+                            let (text_node, rest) =
+                                match soft_hyphen_break(&remaining, room, &mut measure) {
+                                    Some((head, tail)) if wrap => (head, Some(tail)),
+                                    _ => (remaining.replace(SOFT_HYPHEN, ""), None),
+                                };
+                            // it may break once on a line of its own
+                            if wrap
+                                && rest.is_none()
+                                && remaining.contains(SOFT_HYPHEN)
+                                && state.cursor_position.0 > state.left_margin
+                                && measure(&text_node) > room
+                            {
+                                state.break_line(canvas, right_edge, viewport);
+                                word = Some(remaining);
+                                continue;
+                            }
+                            let Some((rect, text)) = state.text_cache.get(&font, &text_node) else {
+                                continue;
+                            };
+
+                            // wrap before a run that would cross the right edge, unless it already
+                            // starts the line
+                            if wrap
+                                && state.cursor_position.0 > state.left_margin
+                                && state.cursor_position.0 + rect.width() > right_edge
+                            {
+                                state.break_line(canvas, right_edge, viewport);
+                            }
+
+                            let pos = (
+                                state.cursor_position.0,
+                                state.cursor_position.1 + above_baseline,
+                            );
+
+                            // from the font's ascent to its descent, whatever the glyphs
+                            let run = Rect::new(
+                                pos.0,
+                                pos.1 + metrics.ascent,
+                                pos.0 + rect.width(),
+                                pos.1 + metrics.descent,
+                            );
+
+                            let hyper_link = link
+                                .clone()
+                                .filter(|link| is_navigable(&link.href))
+                                .map(|link| {
+                                    eprintln!("Hyperlink: {:?}", link);
+
+                                    state.hyper_links.push((run, link));
+                                    state.hyper_links.len() - 1
+                                });
+                            let title = trace.0.iter().rev().find_map(|(name, attributes)| {
+                                attributes
+                                    .iter()
+                                    .find(|(key, _)| key == "title")
+                                    .filter(|_| !BLOCK_LEVEL_ELEMENTS.contains(&name.as_str()))
+                                    .map(|(_, title)| title.clone())
+                            });
+                            let title = title.map(|title| {
+                                state.titles.push((run, title));
+                                state.titles.len() - 1
+                            });
+                            // just below the baseline, along the top of the ascent, and through
+                            // the middle of lowercase letters. A word split from the same text as
+                            // the one before it on the line carries the lines across the space.
+                            let thickness = (font.size() / 16.0).max(1.0);
+                            let start = if follows_word && !state.line.is_empty() {
+                                pos.0 - SPACE_WIDTH
+                            } else {
+                                pos.0
+                            };
+                            let line_at =
+                                |y: f32| Rect::new(start, y, pos.0 + rect.width(), y + thickness);
+                            let mut decorations = vec![];
+                            if text_style.underline {
+                                decorations.push(line_at(pos.1 + metrics.descent / 3.0));
+                            }
+                            if text_style.overline {
+                                decorations.push(line_at(pos.1 + metrics.ascent));
+                            }
+                            if text_style.line_through {
+                                decorations.push(line_at(
+                                    pos.1 - metrics.x_height / 2.0 - thickness / 2.0,
+                                ));
+                            }
+
+                            // and the highlight across the space from a run with the same one
+                            let highlighted_space = state.line.last().is_some_and(|last| {
+                                last.background.map(|(_, color)| color) == text_style.background
+                            });
+                            let background = text_style.background.map(|color| {
+                                let left = if highlighted_space {
+                                    space_start
+                                } else {
+                                    pos.0
+                                };
+                                (Rect::new(left, run.top, run.right, run.bottom), color)
+                            });
+
+                            state.text_runs.push((run, text_node.clone()));
+                            state.boxes.push(LayoutBox {
+                                depth: trace.0.len(),
+                                name: name.clone(),
+                                rect: run,
+                                text: Some(text_node.clone()),
+                                color: Some(color),
+                                style: Some(text_style),
+                                family: Some(font.typeface().family_name()),
+                            });
+                            if state.line.is_empty() {
+                                let direction =
+                                    match inherited_property(&trace, &state.cascade, "direction")
+                                        .as_deref()
+                                    {
+                                        Some("rtl") => Direction::Rtl,
+                                        Some("ltr") => Direction::Ltr,
+                                        // `auto` or unset: a single direction for the line,
+                                        // from the script of its first run
+                                        _ => Direction::of_text(&text_node).unwrap_or_default(),
+                                    };
+                                state.line_start =
+                                    (state.left_margin, state.text_align(), direction);
+                            }
+                            // a shifted run reaches as much further from the line's baseline on
+                            // one side as it falls short on the other. Centering the glyphs on
+                            // half the x-height is a shift too.
+                            let shift = text_style.baseline_shift
+                                + match text_style.vertical_align {
+                                    VerticalAlign::Middle => {
+                                        (metrics.x_height + metrics.ascent + metrics.descent) / 2.0
+                                    }
+                                    _ => 0.0,
+                                };
+                            state.line.push(LineRun {
+                                content: RunContent::Text(text),
+                                origin: pos,
+                                paint: paint.0.clone(),
+                                layout_box: state.boxes.len() - 1,
+                                text_run: Some(state.text_runs.len() - 1),
+                                hyper_link,
+                                title,
+                                above_baseline: above_baseline + shift,
+                                below_baseline: line_height - above_baseline - shift,
+                                vertical_align: text_style.vertical_align,
+                                decorations,
+                                background,
+                            });
+                            state.cursor_position.0 = pos.0 + rect.width();
+                            state.pending_space = false;
+                            state.last_word = Some((trace.clone(), index));
+
+                            if rest.is_some() {
+                                state.break_line(canvas, right_edge, viewport);
+                            }
+                            word = rest;
+                        }
+                    }
+
+                    if name == "br" {
+                        state.break_line(canvas, right_edge, viewport);
+                        state.pending_space = false;
+                    } else {
+                        let gap = match state.layout.get(
+                            &(trace.names().join(":")
+                                + format!("[{}]", index).as_str()
+                                + "."
+                                + "gap-left"),
+                        ) {
+                            Some(gap_left) if !is_text_node => {
+                                gap_left.trim_end_matches("px").parse::<f32>().unwrap()
+                            }
+                            _ => 0.0,
+                        };
+
+                        state.cursor_position =
+                            (state.cursor_position.0 + gap, state.cursor_position.1);
+                    }
+                }
+
+                WalkControl::Continue
+            },
+        ),
+        Rc::new(
+            move |trace: NodeTrace, name: String, state: &mut RendererState| {
+                let right_edge = state.right_margin;
+                let sets_text_align = if name != "textNode" {
+                    state.element_path.pop();
+                    let index = state.open_boxes.pop().unwrap();
+                    state.closed_boxes.push((index, state.boxes.len()));
+                    state.text_styles.pop();
+                    state.text_aligns.pop().unwrap().is_some()
+                } else {
+                    false
+                };
+
+                if name == "blockquote" {
+                    let top = state.quote_tops.pop().unwrap();
+                    let bottom = if state.cursor_position.0 > state.left_margin {
+                        state.cursor_position.1 + state.line_height() + 8.0
+                    } else {
+                        state.cursor_position.1 + 8.0
+                    };
+                    state.left_margin -= BLOCKQUOTE_INDENT;
+                    state.extend_document(bottom, viewport);
+
+                    let mut paint = PaintExt::new(anti_alias);
+                    paint.set_color_hex("#cccccc");
+                    canvas.draw_rect(
+                        Rect::new(
+                            state.left_margin + 8.0,
+                            top,
+                            state.left_margin + 12.0,
+                            bottom,
+                        ),
+                        &paint.0,
+                    );
+                }
+
+                if name == "details" {
+                    state.details.pop();
+                }
+
+                if name == "q" {
+                    state.quotes.pop();
+                }
+
+                if name == "td" || name == "th" {
+                    let placed = state
+                        .tables
+                        .last()
+                        .is_some_and(|table| table.outer_margins.is_some());
+                    if placed && state.cursor_position.0 > state.left_margin {
+                        state.break_line(canvas, right_edge, viewport);
+                    }
+                    if let Some(table) = state.tables.last_mut().filter(|_| placed) {
+                        if let Some((_, cell)) = table.cell_boxes.last() {
+                            let bottom = &mut table.row_bottoms[cell.row + cell.rows - 1];
+                            *bottom = bottom.max(state.cursor_position.1 + CELL_PADDING);
+                        }
+                        (state.left_margin, state.right_margin) =
+                            table.outer_margins.take().unwrap();
+                    }
+                }
+
+                // a row is as tall as the cells ending on it need
+                if let (true, Some(table)) = (name == "tr", state.tables.last_mut()) {
+                    let row = table.row_tops.len() - 1;
+                    let top = table.row_tops[row];
+                    let bottom = table
+                        .row_bottoms
+                        .get(row)
+                        .map_or(top, |bottom| bottom.max(top));
+                    for (index, cell) in &table.cell_boxes {
+                        if cell.row + cell.rows - 1 == row {
+                            let left = table.left + cell.column as f32 * table.column_width;
+                            state.boxes[*index].rect = Rect::new(
+                                left,
+                                table.row_tops[cell.row],
+                                left + cell.columns as f32 * table.column_width,
+                                bottom,
+                            );
+                        }
+                    }
+                    table.row_tops.push(bottom);
+                    state.cursor_position = (state.left_margin, bottom);
+                }
+
+                if name == "table" {
+                    if let Some(table) = state.tables.pop() {
+                        let bottom = table.row_tops[table.row_tops.len() - 1];
+                        state.cursor_position = (state.left_margin, bottom);
+                        state.extend_document(bottom, viewport);
+                    }
+                }
+
+                // <details>, <summary>, lists and `display: block` elements are on lines of their
+                // own, without the blank line an empty block leaves
+                let own_lines = sets_text_align
+                    || ["details", "summary", "ol", "ul", "li"].contains(&name.as_str())
+                    || (name != "textNode"
+                        && specified_property(&trace.0, &state.cascade, "display")
+                            .is_some_and(|display| display == "block"));
+                if BLOCK_ELEMENTS.contains(&name.as_str())
+                    || (own_lines && state.cursor_position.0 > state.left_margin)
+                {
+                    state.break_line(canvas, right_edge, viewport);
+                }
+
+                if name == "ol" || name == "ul" {
+                    state.lists.pop();
+                    state.left_margin -= LIST_INDENT;
+                    state.cursor_position.0 = state.left_margin;
+                }
+
+                if name == "dd" {
+                    state.left_margin -= DEFINITION_INDENT;
+                    state.cursor_position.0 = state.left_margin;
+                }
+
+                if name == "div" {
+                    if let Some(bottom) = state.blocks.pop().flatten() {
+                        state.cursor_position.1 = state.cursor_position.1.max(bottom);
+                    }
+                }
+
+                if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    if let Some(width) = state.borders.pop().flatten() {
+                        state.left_margin -= width;
+                        state.cursor_position =
+                            (state.left_margin, state.cursor_position.1 + width);
+                        state.extend_document(state.cursor_position.1, viewport);
+                    }
+                }
+
+                let mut key_to_remove = vec![];
+                for key in state.layout.keys() {
+                    if key.starts_with(&(trace.names().join(":") + ":")) {
+                        key_to_remove.push(key.clone());
+                    }
+                }
+
+                for key in key_to_remove {
+                    state.layout.remove(&key);
+                }
+            },
+        ),
+        &mut state,
+    );
+    state.finish_line(canvas, right_edge, viewport);
+
+    // an element covers everything laid out inside it; inner elements close, and so are grown,
+    // before outer ones
+    for (index, end) in std::mem::take(&mut state.closed_boxes) {
+        let mut rect = state.boxes[index].rect;
+        for inner in &state.boxes[index + 1..end] {
+            if !inner.rect.is_empty() {
+                rect = if rect.is_empty() {
+                    inner.rect
+                } else {
+                    Rect::new(
+                        rect.left.min(inner.rect.left),
+                        rect.top.min(inner.rect.top),
+                        rect.right.max(inner.rect.right),
+                        rect.bottom.max(inner.rect.bottom),
+                    )
+                };
+            }
+        }
+        state.boxes[index].rect = rect;
+
+        // the border goes around the content, and is part of the element's box
+        if let Some((_, width, color)) = state
+            .bordered_boxes
+            .iter()
+            .find(|(bordered, _, _)| *bordered == index)
+        {
+            let (width, outer) = (*width, rect.with_outset((*width, *width)));
+            let mut paint = PaintExt::new(anti_alias);
+            paint.set_color_u32(*color);
+            for edge in [
+                Rect::new(outer.left, outer.top, outer.right, outer.top + width),
+                Rect::new(outer.right - width, outer.top, outer.right, outer.bottom),
+                Rect::new(outer.left, outer.bottom - width, outer.right, outer.bottom),
+                Rect::new(outer.left, outer.top, outer.left + width, outer.bottom),
+            ] {
+                canvas.draw_rect(edge, &paint.0);
+            }
+            state.boxes[index].rect = outer;
+        }
+    }
+    for ((rect, _), index) in state.disclosures.iter_mut().zip(&state.summary_boxes) {
+        *rect = state.boxes[*index].rect;
+    }
+
+    canvas.restore();
+
+    *text_cache = std::mem::take(&mut state.text_cache);
+
+    let to_document = |rect: &mut Rect| {
+        *rect = Rect::new(
+            rect.left * zoom,
+            viewport.top + (rect.top - viewport.top) * zoom,
+            rect.right * zoom,
+            viewport.top + (rect.bottom - viewport.top) * zoom,
+        );
+    };
+    state
+        .hyper_links
+        .iter_mut()
+        .for_each(|(rect, _)| to_document(rect));
+    state
+        .disclosures
+        .iter_mut()
+        .for_each(|(rect, _)| to_document(rect));
+    state
+        .text_fields
+        .iter_mut()
+        .for_each(|(rect, _)| to_document(rect));
+    state
+        .titles
+        .iter_mut()
+        .for_each(|(rect, _)| to_document(rect));
+    state
+        .text_runs
+        .iter_mut()
+        .for_each(|(rect, _)| to_document(rect));
+    state
+        .boxes
+        .iter_mut()
+        .for_each(|layout_box| to_document(&mut layout_box.rect));
+    state.document_height *= zoom;
+
+    if let (Some(stats), Some(started)) = (state.stats.as_mut(), started) {
+        stats.layout = started.elapsed().saturating_sub(stats.paint);
+    }
+
+    state
+}
+
+/// Lays out `html` for a `width`×`height` window without showing it, painting onto a scratch
+/// surface.
+pub fn layout(
+    html: &HtmlElement,
+    width: u32,
+    height: u32,
+    options: &RenderOptions,
+) -> RendererState {
+    let mut surface =
+        skia_safe::surfaces::raster_n32_premul((width as i32, height as i32)).unwrap();
+
+    render(
+        surface.canvas(),
+        html,
+        width,
+        height,
+        options,
+        &mut TextCache::default(),
+    )
+}
+
+/// One line per box, indented by depth: the element name or quoted text, then its origin and
+/// size.
+pub fn format_layout(boxes: &[LayoutBox]) -> String {
+    boxes
+        .iter()
+        .map(|layout_box| {
+            let label = match &layout_box.text {
+                Some(text) => format!("{:?}", text),
+                None => layout_box.name.clone(),
+            };
+            let rect = layout_box.rect;
+
+            format!(
+                "{}{} ({}, {}) {}x{}\n",
+                "  ".repeat(layout_box.depth),
+                label,
+                rect.x(),
+                rect.y(),
+                rect.width(),
+                rect.height()
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_format_layout() {
+    let boxes = vec![
+        LayoutBox {
+            depth: 0,
+            name: "div".to_string(),
+            rect: Rect::new(25.0, 120.0, 775.0, 220.0),
+            text: None,
+            color: None,
+            style: None,
+            family: None,
+        },
+        LayoutBox {
+            depth: 1,
+            name: "textNode".to_string(),
+            rect: Rect::new(25.0, 124.0, 57.5, 160.0),
+            text: Some("say \"hi\"".to_string()),
+            color: Some(0x000000),
+            style: Some(TextStyle::default()),
+            family: Some("Noto Sans".to_string()),
+        },
+    ];
+
+    assert_eq!(
+        format_layout(&boxes),
+        "div (25, 120) 750x100\n  \"say \\\"hi\\\"\" (25, 124) 32.5x36\n"
+    );
+}
+
+/// Renders `html` onto a blank offscreen surface and encodes the result as PNG.
+pub fn render_to_png(
+    html: &HtmlElement,
+    width: u32,
+    height: u32,
+    options: &RenderOptions,
+) -> Option<skia_safe::Data> {
+    let mut surface = skia_safe::surfaces::raster_n32_premul((width as i32, height as i32))?;
+    surface.canvas().clear(0xFFFFFFFF);
+    render(
+        surface.canvas(),
+        html,
+        width,
+        height,
+        options,
+        &mut TextCache::default(),
+    );
+
+    surface
+        .image_snapshot()
+        .encode_to_data_with_quality(skia_safe::EncodedImageFormat::PNG, 100)
+}
+
+/// Renders `html` with the default options into a `width`×`height` buffer of `0x00RRGGBB`
+/// pixels, as the window would show it.
+pub fn render_page(html: &HtmlElement, width: u32, height: u32) -> Vec<u32> {
+    render_with_pixels(html, width, height, &RenderOptions::default()).1
+}
+
+#[test]
+fn test_render_page() {
+    let html = crate::html::parse_html(
+        r##"<html><body><div style="height: 100px; background-color: #808080;"></div></body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+    let buffer = render_page(&html, 200, 300);
+
+    assert_eq!(buffer.len(), 200 * 300);
+    assert_eq!(buffer[250 * 200 + 100], 0xffffff);
+    assert_eq!(buffer[150 * 200 + 100], 0x808080);
+}
+
+#[test]
+fn test_render_page_background() {
+    let page = |style: &str, body: &str| {
+        let html = crate::html::parse_html(format!(
+            "<html><head><style>{}</style></head><body{}><p>text</p></body></html>",
+            style, body
+        ))
+        .unwrap();
+        render_page(&html, 200, 300)[250 * 200 + 100]
+    };
+
+    assert_eq!(page("", ""), 0xffffff);
+    assert_eq!(page("", r##" bgcolor="#808080""##), 0x808080);
+    assert_eq!(
+        page("body { background: #808080 no-repeat; }", ""),
+        0x808080
+    );
+    // the stylesheet wins over the attribute, and the root over the body
+    assert_eq!(
+        page(
+            "body { background-color: #404040; }",
+            r##" bgcolor="#808080""##
+        ),
+        0x404040
+    );
+    assert_eq!(
+        page(
+            "html { background: #202020; } body { background-color: #404040; }",
+            ""
+        ),
+        0x202020
+    );
+}
+
+#[test]
+fn test_render_buttons() {
+    let html = crate::html::parse_html(
+        r#"<html><body><form action="search.html"><button>Go <b>now</b></button><button type="button">Inert</button><input type="submit"></form><a href="next.html"><button type="button">Next</button></a></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let state = layout(&html, 400, 600, &RenderOptions::default());
+    let buttons = state
+        .boxes
+        .iter()
+        .filter(|layout_box| ["button", "input"].contains(&layout_box.name.as_str()))
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+
+    // a box around each label, each on a line of its own
+    let labels = state
+        .text_runs
+        .iter()
+        .map(|(_, text)| text.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["Go now", "Inert", "Submit", "Next"]);
+    for (button, (label, _)) in buttons.iter().zip(&state.text_runs) {
+        assert_eq!(
+            (button.left + BUTTON_PADDING, button.top + BUTTON_PADDING),
+            (label.left, label.top)
+        );
+        assert_eq!(button.width(), label.width() + 2.0 * BUTTON_PADDING);
+    }
+    assert_eq!(buttons[1].top, buttons[0].bottom);
+
+    // submit buttons and the one in the anchor are clicked through to where they lead
+    let links = state
+        .hyper_links
+        .iter()
+        .map(|(rect, link)| (*rect, link.href.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        links,
+        vec![
+            (buttons[0], "search.html"),
+            (buttons[2], "search.html"),
+            (buttons[3], "next.html"),
+        ]
+    );
+
+    let buffer = render_page(&html, 400, 600);
+    let pixel = |x: f32, y: f32| buffer[y as usize * 400 + x as usize];
+    assert_eq!(pixel(buttons[0].left + 2.0, buttons[0].top + 2.0), 0xefefef);
+    assert_eq!(pixel(buttons[0].left, buttons[0].top + 2.0), 0x767676);
+}
+
+#[test]
+fn test_wrap_lines() {
+    let measure = |text: &str| text.chars().count() as f32;
+    let cases = vec![
+        ("", vec![""]),
+        ("short", vec!["short"]),
+        ("one two three", vec!["one two", "three"]),
+        ("first\n\nthird line", vec!["first", "", "third", "line"]),
+        ("unbreakable word", vec!["unbreakable", "word"]),
+        ("  indented", vec!["  indented"]),
+        ("trailing ", vec!["trailing "]),
+    ];
+
+    for (text, want) in cases {
+        assert_eq!(wrap_lines(text, 7.0, measure), want, "{:?}", text);
+    }
+}
+
+/// Renders `html` onto a white page with `options`, returning the layout along with the pixels.
+fn render_with_pixels(
+    html: &HtmlElement,
+    width: u32,
+    height: u32,
+    options: &RenderOptions,
+) -> (RendererState, Vec<u32>) {
+    let mut surface =
+        skia_safe::surfaces::raster_n32_premul((width as i32, height as i32)).unwrap();
+    surface.canvas().clear(0xFFFFFFFF);
+    let state = render(
+        surface.canvas(),
+        html,
+        width,
+        height,
+        options,
+        &mut TextCache::default(),
+    );
+
+    let pixmap = surface.peek_pixels().unwrap();
+    let mut buffer = vec![0; (width * height) as usize];
+    copy_pixels(
+        pixmap.bytes().unwrap(),
+        pixmap.row_bytes(),
+        width as usize,
+        &mut buffer,
+    );
+
+    (state, buffer)
+}
+
+#[test]
+fn test_render_bars() {
+    let html = crate::html::parse_html(
+        r#"<html><body><progress value="30" max="100">30%</progress><meter value="0.75"></meter><meter min="10" max="20" value="15"></meter><progress value="soon"></progress><progress value="3" max="2"></progress></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let (state, buffer) = render_with_pixels(&html, 400, 600, &RenderOptions::default());
+    let track = 0xdddddd;
+    // the width of the filled part, from the left of the bar to the first pixel of the track
+    let filled = |bar: &Rect| {
+        let y = bar.center_y() as usize;
+        (bar.left as usize..bar.right as usize)
+            .find(|x| buffer[y * 400 + x] == track)
+            .map_or(bar.width(), |x| x as f32 - bar.left)
+    };
+    let bars = state
+        .boxes
+        .iter()
+        .filter(|layout_box| ["progress", "meter"].contains(&layout_box.name.as_str()))
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+
+    // one per line, the fallback text unpainted, and filled by the ratio of the value
+    assert!(state.text_runs.is_empty());
+    assert_eq!(
+        bars[0],
+        Rect::from_xywh(25.0, 120.0, BAR_SIZE.0, BAR_SIZE.1)
+    );
+    assert_eq!(bars[1].top, bars[0].bottom);
+    let fills = bars.iter().map(filled).collect::<Vec<_>>();
+    assert_eq!(
+        fills,
+        vec![
+            BAR_SIZE.0 * 0.3,
+            BAR_SIZE.0 * 0.75,
+            BAR_SIZE.0 * 0.5,
+            0.0,
+            BAR_SIZE.0
+        ]
+    );
+}
+
+#[test]
+fn test_render_iframe() {
+    let html = crate::html::parse_html(
+        r#"<html><body>above <iframe src="inner.html" width="200" height="100">No frames</iframe><iframe src="missing.html" /></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let inner = crate::html::parse_html(
+        r##"<html><body bgcolor="#808080">inside <iframe src="inner.html" /></body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+    let frames = vec![("inner.html".to_string(), inner)];
+    let (state, buffer) = render_with_pixels(
+        &html,
+        400,
+        600,
+        &RenderOptions {
+            frames: &frames,
+            ..RenderOptions::default()
+        },
+    );
+    let pixel = |x: f32, y: f32| buffer[y as usize * 400 + x as usize];
+
+    // the boxes are sized by their attributes, or as other replaced elements, on lines of their
+    // own; the fallback content isn't shown
+    let frames = state
+        .boxes
+        .iter()
+        .filter(|layout_box| layout_box.name == "iframe")
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+    let (above, _) = state.text_runs[0];
+    assert_eq!(frames[0], Rect::from_xywh(25.0, above.bottom, 200.0, 100.0));
+    assert_eq!(
+        frames[1],
+        Rect::from_xywh(25.0, frames[0].bottom, SVG_SIZE.0, SVG_SIZE.1)
+    );
+    assert_eq!(state.text_runs.len(), 1);
+
+    // the embedded page's background fills its box within the border, and no further, while
+    // the frame it embeds in turn is left blank
+    let center = (frames[0].center_x(), frames[0].center_y());
+    assert_eq!(pixel(center.0, frames[0].top + 2.0), 0x808080);
+    assert_eq!(pixel(frames[0].left, center.1), 0x767676);
+    assert_eq!(pixel(frames[0].right + 2.0, center.1), 0xffffff);
+    assert_eq!(
+        pixel(frames[0].left + 2.0, frames[0].bottom + 2.0),
+        0xffffff
+    );
+    assert_eq!(
+        pixel(frames[0].left + 20.0, frames[0].bottom - 10.0),
+        0xffffff
+    );
+}
+
+#[test]
+fn test_render_textarea() {
+    let html = crate::html::parse_html(
+        "<html><body><textarea rows=\"3\" cols=\"10\">\nsome words to wrap</textarea><textarea></textarea></body></html>"
+            .to_string(),
+    )
+    .unwrap();
+    let state = layout(&html, 400, 600, &RenderOptions::default());
+    let (rect, field) = &state.text_fields[0];
+
+    // cols characters wide and rows lines high, inside a padding
+    let char_width = font(crate::helper::default_typeface(), 32.0, true)
+        .metrics()
+        .1
+        .avg_char_width;
+    assert_eq!(rect.width(), 10.0 * char_width + 2.0 * TEXTAREA_PADDING);
+    assert_eq!(
+        rect.height(),
+        3.0 * normal_line_height() + 2.0 * TEXTAREA_PADDING
+    );
+    let (default, _) = &state.text_fields[1];
+    assert_eq!(
+        (default.width(), default.height()),
+        (
+            20.0 * char_width + 2.0 * TEXTAREA_PADDING,
+            2.0 * normal_line_height() + 2.0 * TEXTAREA_PADDING
+        )
+    );
+    assert_eq!(default.top, rect.bottom);
+
+    // the content less the leading newline, wrapped to the inside of the box
+    assert_eq!(field.value, "some words to wrap");
+    let lines = state
+        .text_runs
+        .iter()
+        .map(|(run, text)| (run.left, text.as_str()))
+        .collect::<Vec<_>>();
+    let left = rect.left + TEXTAREA_PADDING;
+    assert_eq!(lines, vec![(left, "some words"), (left, "to wrap")]);
+
+    // the reader's edits replace the content
+    let edited = layout(
+        &html,
+        400,
+        600,
+        &RenderOptions {
+            field_value: &|key| (key == field.key).then(|| "typed".to_string()),
+            ..RenderOptions::default()
+        },
+    );
+    assert_eq!(edited.text_fields[0].1.value, "typed");
+    assert_eq!(edited.text_runs[0].1, "typed");
+}
+
+#[test]
+fn test_render_disabled_controls() {
+    let html = crate::html::parse_html(
+        r#"<html><body><form action="search.html"><input type="submit" disabled="" /><button>Go</button><textarea disabled="">off</textarea><textarea readonly="">fixed</textarea></form></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let (state, buffer) = render_with_pixels(&html, 400, 600, &RenderOptions::default());
+    let pixel = |rect: &Rect| buffer[(rect.top as usize + 2) * 400 + rect.left as usize + 2];
+    let buttons = state
+        .boxes
+        .iter()
+        .filter(|layout_box| ["button", "input"].contains(&layout_box.name.as_str()))
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+
+    // the disabled button is greyed and submits nothing
+    assert_eq!(pixel(&buttons[0]), 0xf5f5f5);
+    assert_eq!(pixel(&buttons[1]), 0xefefef);
+    let links = state
+        .hyper_links
+        .iter()
+        .map(|(rect, _)| *rect)
+        .collect::<Vec<_>>();
+    assert_eq!(links, vec![buttons[1]]);
+
+    let fields = state
+        .text_fields
+        .iter()
+        .map(|(rect, field)| (pixel(rect), field.disabled, field.readonly))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        vec![(0xefefef, true, false), (0xffffff, false, true)]
+    );
+}
+
+#[test]
+fn test_render_caret_blink() {
+    let html = crate::html::parse_html(
+        r#"<html><body><textarea>text</textarea></body></html>"#.to_string(),
+    )
+    .unwrap();
+    let (state, unfocused) = render_with_pixels(&html, 400, 300, &RenderOptions::default());
+    let key = state.text_fields[0].1.key.clone();
+    let render = |caret_visible| {
+        let options = RenderOptions {
+            focused_field: Some(&key),
+            caret_visible,
+            ..RenderOptions::default()
+        };
+        render_with_pixels(&html, 400, 300, &options).1
+    };
+
+    // the caret comes and goes with its blink, leaving the field as it was unfocused
+    assert_ne!(render(true), unfocused);
+    assert_eq!(render(false), unfocused);
+}
+
+#[test]
+fn test_render_blocked_images() {
+    let html = crate::html::parse_html(
+        r#"<html><body>above <img src="logo.svg" alt="Logo"><img src="photo.svg" width="100" height="60"></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let svg = br##"<svg xmlns="http://www.w3.org/2000/svg"><rect fill="#808080"/></svg>"##;
+    let images = vec![("logo.svg".to_string(), svg.to_vec())];
+    let state = layout(
+        &html,
+        400,
+        600,
+        &RenderOptions {
+            images: &images,
+            block_images: true,
+            ..RenderOptions::default()
+        },
+    );
+    let imgs = state
+        .boxes
+        .iter()
+        .filter(|layout_box| layout_box.name == "img")
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+
+    // a box around the alt text set inline after the text, even for an image that was fetched
+    let (above, _) = state.text_runs[0];
+    let (alt, text) = &state.text_runs[1];
+    assert_eq!(text, "Logo");
+    assert_eq!(imgs[0].left, above.right + SPACE_WIDTH);
+    assert_eq!(
+        (imgs[0].width(), imgs[0].height()),
+        (
+            alt.width() + 2.0 * ALT_TEXT_PADDING,
+            normal_line_height() + 2.0 * ALT_TEXT_PADDING
+        )
+    );
+    assert_eq!(
+        (alt.left, alt.top),
+        (
+            imgs[0].left + ALT_TEXT_PADDING,
+            imgs[0].top + ALT_TEXT_PADDING
+        )
+    );
+    // or the size the <img> gives, with no text if it has no alt, each bottom on the baseline
+    assert_eq!(state.text_runs.len(), 2);
+    assert_eq!(
+        imgs[1],
+        Rect::from_xywh(imgs[0].right, imgs[0].bottom - 60.0, 100.0, 60.0)
+    );
+}
+
+#[test]
+fn test_render_broken_images() {
+    let html = crate::html::parse_html(
+        r#"<html><body><img src="missing.svg" alt="Missing"><img src="bad.svg" alt="Undecodable"></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let images = vec![("bad.svg".to_string(), b"not an image".to_vec())];
+    let state = layout(
+        &html,
+        400,
+        600,
+        &RenderOptions {
+            images: &images,
+            ..RenderOptions::default()
+        },
+    );
+    let imgs = state
+        .boxes
+        .iter()
+        .filter(|layout_box| layout_box.name == "img")
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+
+    // each in a box of its own, side by side
+    let runs = state
+        .text_runs
+        .iter()
+        .map(|(rect, text)| (text.as_str(), rect.top - ALT_TEXT_PADDING))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        runs,
+        vec![("Missing", imgs[0].top), ("Undecodable", imgs[1].top)]
+    );
+    assert_eq!(
+        (imgs[1].left, imgs[1].bottom),
+        (imgs[0].right, imgs[0].bottom)
+    );
+}
+
+#[test]
+fn test_render_svg() {
+    let svg = |color: &str| {
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="60"><rect width="100" height="60" fill="{}"/></svg>"#,
+            color
+        )
+    };
+    // an inline <svg>, then a fetched one in an <img> sized by its attributes
+    let html = crate::html::parse_html(format!(
+        r#"<html><body>above {}<img src="logo.svg" width="50" height="40" /></body></html>"#,
+        svg("#00ff00")
+    ))
+    .unwrap();
+    let images = vec![("logo.svg".to_string(), svg("#808080").into_bytes())];
+    let options = RenderOptions {
+        images: &images,
+        ..RenderOptions::default()
+    };
+
+    let state = layout(&html, 400, 600, &options);
+    let rect = |name: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.name == name)
+            .unwrap()
+            .rect
+    };
+    let (above, _) = state.text_runs[0];
+    let (inline, img) = (rect("svg"), rect("img"));
+    // on the line after the text, bottoms on its baseline
+    assert_eq!(
+        (inline.left, inline.width(), inline.height()),
+        (above.right + SPACE_WIDTH, 100.0, 60.0)
+    );
+    assert_eq!(
+        (img.left, img.bottom, img.width(), img.height()),
+        (inline.right, inline.bottom, 50.0, 40.0)
+    );
+
+    let mut surface = skia_safe::surfaces::raster_n32_premul((400, 600)).unwrap();
+    surface.canvas().clear(0xFFFFFFFF);
+    render(
+        surface.canvas(),
+        &html,
+        400,
+        600,
+        &options,
+        &mut TextCache::default(),
+    );
+    let pixmap = surface.peek_pixels().unwrap();
+    let mut pixels = vec![0; 400 * 600];
+    copy_pixels(
+        pixmap.bytes().unwrap(),
+        pixmap.row_bytes(),
+        400,
+        &mut pixels,
+    );
+    let pixel = |rect: Rect| pixels[rect.center_y() as usize * 400 + rect.center_x() as usize];
+    assert_eq!(pixel(inline), 0x00ff00);
+    assert_eq!(pixel(img), 0x808080);
+}
+
+#[test]
+fn test_render_vertical_align() {
+    let html = crate::html::parse_html(
+        r#"<html><body><div>a <img alt="" width="20" height="100" style="vertical-align: middle" /> b</div><div>c <img alt="" width="20" height="10" style="vertical-align: top" /><img alt="" width="20" height="80" /></div><div>d <img alt="" width="20" height="10" style="vertical-align: bottom" /><img alt="" width="20" height="80" /></div></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let state = layout(&html, 400, 600, &RenderOptions::default());
+    let imgs = state
+        .boxes
+        .iter()
+        .filter(|layout_box| layout_box.name == "img")
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+    let text = |word: &str| {
+        state
+            .text_runs
+            .iter()
+            .find(|(_, text)| text == word)
+            .unwrap()
+            .0
+    };
+    let (_, metrics) = font(crate::helper::default_typeface(), 32.0, true).metrics();
+    let baseline = |word: &str| text(word).bottom - metrics.descent;
+
+    // centered on half the x-height above the baseline of the text either side
+    assert_eq!(baseline("a"), baseline("b"));
+    assert_eq!(imgs[0].center_y(), baseline("a") - metrics.x_height / 2.0);
+    assert_eq!(imgs[0].left, text("a").right + SPACE_WIDTH);
+
+    // against the top of a line the taller image sets, or the bottom the text does
+    assert_eq!(imgs[1].top, imgs[2].top);
+    assert_eq!(imgs[2].bottom, baseline("c"));
+    assert_eq!(imgs[3].bottom, text("d").bottom);
+    assert_eq!(imgs[4].bottom, baseline("d"));
+}
+
+/// Copies 4-byte-per-pixel rows, which may be padded past `width` pixels, into a packed window
+/// buffer.
+pub fn copy_pixels(pixels: &[u8], row_bytes: usize, width: usize, buffer: &mut [u32]) {
+    for (y, row) in buffer.chunks_mut(width).enumerate() {
+        let source = &pixels[y * row_bytes..];
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = source[x * 4 + 2] as u32
+                | (source[x * 4 + 1] as u32) << 8
+                | (source[x * 4] as u32) << 16;
+        }
+    }
+}
+
+#[test]
+fn test_copy_pixels() {
+    // two rows of two pixels, each row padded to three
+    let pixels = [
+        1, 2, 3, 255, 4, 5, 6, 255, 0, 0, 0, 0, //
+        7, 8, 9, 255, 10, 11, 12, 255, 0, 0, 0, 0,
+    ];
+    let mut buffer = [0; 4];
+    copy_pixels(&pixels, 12, 2, &mut buffer);
+
+    assert_eq!(buffer, [0x010203, 0x040506, 0x070809, 0x0a0b0c]);
+}
+
+/// Height of a line of text in `font`: the font's own line spacing, unless a CSS `line-height`
+/// (`normal`, a multiple of the font size, a length or a percentage) says otherwise.
+fn line_height(font: &Font, css_line_height: Option<&str>) -> f32 {
+    let (_, metrics) = font.metrics();
+    let normal = -metrics.ascent + metrics.descent + metrics.leading;
+
+    match css_line_height.map(str::trim) {
+        None | Some("normal") => normal,
+        Some(value) => match value.parse::<f32>() {
+            Ok(multiple) => multiple * font.size(),
+            Err(_) => css::parse_length(value, font.size()).unwrap_or(normal),
+        },
+    }
+}
+
+/// The value the last element of `path` sets for `property`: from its inline style, else the
+/// page's and the reader's stylesheets, else a presentational attribute, else the user-agent
+/// defaults.
+fn specified_property(path: &[Element], cascade: &Cascade, property: &str) -> Option<String> {
+    let (name, attributes) = path.last()?;
+
+    inline_style(attributes)
+        .get(property)
+        .map(str::to_string)
+        .or_else(|| cascade.get_author(path, &[], property).map(str::to_string))
+        .or_else(|| presentational_hint(name, attributes, property))
+        .or_else(|| {
+            cascade
+                .get_user_agent(path, &[], property)
+                .map(str::to_string)
+        })
+}
+
+/// A font whose glyph edges are antialiased, or aliased for pixel-crisp text.
+pub fn font(typeface: Typeface, size: f32, anti_alias: bool) -> Font {
+    let mut font = Font::from_typeface(typeface, size);
+    if !anti_alias {
+        font.set_edging(Edging::Alias);
+    }
+
+    font
+}
+
+/// Width and color of the border of the last element in `trace`, if it has one. Every style but
+/// `none` and `hidden` is drawn solid.
+fn border(trace: &NodeTrace, cascade: &Cascade) -> Option<(f32, u32)> {
+    let property = |property| specified_property(&trace.0, cascade, property);
+
+    match property("border-style").as_deref() {
+        None | Some("none" | "hidden") => return None,
+        Some(_) => (),
+    }
+    let width = match property("border-width").as_deref() {
+        Some("thin") => 1.0,
+        Some("medium") | None => 3.0,
+        Some("thick") => 5.0,
+        Some(width) => css::parse_length(width, 0.0)?,
+    };
+    // `currentColor` by default
+    let color = property("border-color")
+        .or_else(|| inherited_property(trace, cascade, "color"))
+        .and_then(|color| css::parse_color(&color))
+        .unwrap_or(0x000000);
+
+    (width > 0.0).then_some((width, color))
+}
+
+/// Looks up an inherited `property` on the innermost element in `trace` that sets it.
+fn inherited_property(trace: &NodeTrace, cascade: &Cascade, property: &str) -> Option<String> {
+    (1..=trace.0.len())
+        .rev()
+        .find_map(|end| specified_property(&trace.0[..end], cascade, property))
+}
+
+/// Legacy attributes such as `<font color>` and `<body text>`, as the CSS value they stand for.
+fn presentational_hint(
+    name: &str,
+    attributes: &[(String, String)],
+    property: &str,
+) -> Option<String> {
+    let attribute = match (name, property) {
+        ("font", "color") => "color",
+        ("font", "font-size") => "size",
+        ("font", "font-family") => "face",
+        ("body", "color") => "text",
+        ("body", "background-color") => "bgcolor",
+        (_, "direction") => "dir",
+        _ => return None,
+    };
+    let (_, value) = attributes.iter().find(|(key, _)| key == attribute)?;
+
+    if property != "font-size" {
+        return Some(value.clone());
+    }
+
+    // `size` is 1 to 7, 3 being the default, or relative to 3 if signed
+    let value = value.trim();
+    let size = match value.strip_prefix(['+', '-']) {
+        Some(delta) => {
+            let delta = delta.parse::<i32>().ok()?;
+            if value.starts_with('-') {
+                3 - delta
+            } else {
+                3 + delta
+            }
+        }
+        None => value.parse::<i32>().ok()?,
+    };
+    let keyword = [
+        "x-small",
+        "small",
+        "medium",
+        "large",
+        "x-large",
+        "xx-large",
+        "xxx-large",
+    ][size.clamp(1, 7) as usize - 1];
+
+    Some(keyword.to_string())
+}
+
+/// The pseudo-classes an element is in: an `<a>` with an `href` is `:link`, or `:visited` once
+/// the reader has been to where it leads.
+fn link_pseudo_classes(
+    name: &str,
+    attributes: &[(String, String)],
+    is_visited: &dyn Fn(&str) -> bool,
+) -> &'static [&'static str] {
+    match attributes.iter().find(|(key, _)| key == "href") {
+        Some((_, href)) if name == "a" => {
+            if is_navigable(href) && is_visited(href) {
+                &["visited"]
+            } else {
+                &["link"]
+            }
+        }
+        _ => &[],
+    }
+}
+
+/// Empty and fragment-only hrefs (`""`, `"#"`, `"#top"`) stay on the page, so they get no
+/// clickable rect.
+fn is_navigable(href: &str) -> bool {
+    !href.is_empty() && !href.starts_with('#')
+}
+
+/// Where to break `word` at a soft hyphen so the part before it, ending in a hyphen, is at most
+/// `room` wide: that part and the rest, breaking as late in the word as possible. `None` if the
+/// word fits whole, or no break leaves a short enough first part.
+fn soft_hyphen_break(
+    word: &str,
+    room: f32,
+    mut measure: impl FnMut(&str) -> f32,
+) -> Option<(String, String)> {
+    if !word.contains(SOFT_HYPHEN) || measure(&word.replace(SOFT_HYPHEN, "")) <= room {
+        return None;
+    }
+
+    word.match_indices(SOFT_HYPHEN)
+        .rev()
+        .filter(|(at, _)| *at > 0)
+        .find_map(|(at, _)| {
+            let head = format!("{}-", word[..at].replace(SOFT_HYPHEN, ""));
+            (measure(&head) <= room)
+                .then(|| (head, word[at + SOFT_HYPHEN.len_utf8()..].to_string()))
+        })
+}
+
+#[test]
+fn test_soft_hyphen_break() {
+    let width = |text: &str| text.chars().count() as f32;
+    let word = "in\u{ad}com\u{ad}pre\u{ad}hen\u{ad}si\u{ad}ble";
+
+    assert_eq!(soft_hyphen_break(word, 20.0, width), None);
+    assert_eq!(soft_hyphen_break("incomprehensible", 5.0, width), None);
+    assert_eq!(
+        soft_hyphen_break(word, 12.0, width),
+        Some(("incomprehen-".to_string(), "si\u{ad}ble".to_string()))
+    );
+    assert_eq!(
+        soft_hyphen_break(word, 6.0, width),
+        Some((
+            "incom-".to_string(),
+            "pre\u{ad}hen\u{ad}si\u{ad}ble".to_string()
+        ))
+    );
+    // not even the first part fits
+    assert_eq!(soft_hyphen_break(word, 2.0, width), None);
+}
+
+/// Breaks `text` into the lines it takes at `width`: at each newline, and between words where the
+/// next one wouldn't fit. A word too long for a line of its own overflows it. There's always at
+/// least one line, if an empty one.
+fn wrap_lines(text: &str, width: f32, mut measure: impl FnMut(&str) -> f32) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut words = paragraph.split(' ');
+        let mut line = words.next().unwrap_or_default().to_string();
+        for word in words {
+            let longer = format!("{} {}", line, word);
+            if !word.is_empty() && !line.trim().is_empty() && measure(&longer) > width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = longer;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// The four 1px edges along the inside of `rect`.
+fn edges(rect: Rect) -> [Rect; 4] {
+    [
+        Rect::new(rect.left, rect.top, rect.right, rect.top + 1.0),
+        Rect::new(rect.right - 1.0, rect.top, rect.right, rect.bottom),
+        Rect::new(rect.left, rect.bottom - 1.0, rect.right, rect.bottom),
+        Rect::new(rect.left, rect.top, rect.left + 1.0, rect.bottom),
+    ]
+}
+
+/// Collects the words under the element at `path` with `children`, in document order.
+fn word_keys(path: &str, children: &[HtmlElement], words: &mut Vec<WordKey>) {
+    for (index, child) in children.iter().enumerate() {
+        if child.name != "textNode" {
+            let path = format!("{}:{}[{}]", path, child.name, index);
+            word_keys(&path, &child.children, words);
+        } else if child
+            .text_node
+            .as_deref()
+            .is_some_and(|text| !text.trim().is_empty())
+        {
+            words.push((path.to_string(), index));
+        }
+    }
+}
+
+/// Places the cells of each row, given as their `colspan` and `rowspan`, in a table's grid. A
+/// cell takes the first column of its row not already covered by a cell spanning down from the
+/// rows above, and spans no further down than the last row.
+fn table_grid(rows: &[Vec<(usize, usize)>]) -> Vec<GridCell> {
+    let mut occupied = HashSet::new();
+    let mut cells = vec![];
+
+    for (row, spans) in rows.iter().enumerate() {
+        let mut column = 0;
+        for &(colspan, rowspan) in spans {
+            while occupied.contains(&(column, row)) {
+                column += 1;
+            }
+
+            let cell = GridCell {
+                column,
+                row,
+                columns: colspan,
+                rows: rowspan.min(rows.len() - row),
+            };
+            for slot_row in row..row + cell.rows {
+                for slot_column in column..column + cell.columns {
+                    occupied.insert((slot_column, slot_row));
+                }
+            }
+            cells.push(cell);
+            column += colspan;
+        }
+    }
+
+    cells
+}
+
+#[test]
+fn test_table_grid() {
+    let cell = |column, row, columns, rows| GridCell {
+        column,
+        row,
+        columns,
+        rows,
+    };
+
+    assert_eq!(
+        table_grid(&[vec![(2, 1)], vec![(1, 1), (1, 1)]]),
+        vec![cell(0, 0, 2, 1), cell(0, 1, 1, 1), cell(1, 1, 1, 1)]
+    );
+    // cells skip the slots taken by rowspans from above, and spans stop at the last row
+    assert_eq!(
+        table_grid(&[
+            vec![(1, 2), (1, 1), (1, 5)],
+            vec![(1, 1)],
+            vec![(1, 1), (2, 1)]
+        ]),
+        vec![
+            cell(0, 0, 1, 2),
+            cell(1, 0, 1, 1),
+            cell(2, 0, 1, 3),
+            cell(1, 1, 1, 1),
+            cell(0, 2, 1, 1),
+            cell(1, 2, 2, 1),
+        ]
+    );
+}
+
+/// The marker of item `index` of an `<ol>` numbered in the style of its `type`: `1` for
+/// decimal, `a`/`A` for letters (`z` is followed by `aa`) and `i`/`I` for roman numerals.
+/// Numbers a style can't write, like 0 in letters or 4000 in roman numerals, are decimal.
+fn list_marker(index: i64, kind: char) -> String {
+    match kind {
+        'a' | 'A' if index > 0 => {
+            // bijective base 26: there is no zero digit
+            let mut letters = vec![];
+            let mut n = index;
+            while n > 0 {
+                n -= 1;
+                letters.push((b'a' + (n % 26) as u8) as char);
+                n /= 26;
+            }
+            let marker: String = letters.into_iter().rev().collect();
+            if kind == 'A' {
+                marker.to_uppercase()
+            } else {
+                marker
+            }
+        }
+        'i' | 'I' if (1..4000).contains(&index) => {
+            const NUMERALS: [(i64, &str); 13] = [
+                (1000, "m"),
+                (900, "cm"),
+                (500, "d"),
+                (400, "cd"),
+                (100, "c"),
+                (90, "xc"),
+                (50, "l"),
+                (40, "xl"),
+                (10, "x"),
+                (9, "ix"),
+                (5, "v"),
+                (4, "iv"),
+                (1, "i"),
+            ];
+            let mut marker = String::new();
+            let mut n = index;
+            for (value, numeral) in NUMERALS {
+                while n >= value {
+                    marker.push_str(numeral);
+                    n -= value;
+                }
+            }
+            if kind == 'I' {
+                marker.to_uppercase()
+            } else {
+                marker
+            }
+        }
+        _ => index.to_string(),
+    }
+}
+
+/// The declarations of the element's `style` attribute, as one style without a selector.
+fn inline_style(attributes: &[(String, String)]) -> css::Style {
+    let rules = attributes
+        .iter()
+        .find(|(key, _)| key == "style")
+        .and_then(|(_, style)| css::parse_css(style.clone()).ok())
+        .map(|styles| {
+            styles
+                .styles
+                .into_iter()
+                .flat_map(|style| style.rules)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    css::Style {
+        selector: None,
+        rules,
+    }
+}
+
+#[cfg(test)]
+fn render_headless(source: &str) -> RendererState {
+    render_headless_with(source, Viewport::default())
+}
+
+#[cfg(test)]
+fn render_headless_with(source: &str, viewport: Viewport) -> RendererState {
+    let html = crate::html::parse_html(source.to_string()).unwrap();
+
+    layout(
+        &html,
+        800,
+        600,
+        &RenderOptions {
+            viewport,
+            ..RenderOptions::default()
+        },
+    )
+}
+
+/// Line height of body text in the default font.
+#[cfg(test)]
+fn normal_line_height() -> f32 {
+    line_height(
+        &Font::from_typeface(
+            typeface_for_lang(None),
+            FontSettings::default().default_size,
+        ),
+        None,
+    )
+}
+
+#[test]
+fn test_render_fixed_height_div() {
+    let cases = vec![
+        (
+            r#"<html><body><div></div></body></html>"#,
+            normal_line_height(),
+        ),
+        (
+            r#"<html><body><div style="height: 100px;"></div></body></html>"#,
+            100.0,
+        ),
+        (
+            r#"<html><body><div style="height: 50%; width: 200px;"></div></body></html>"#,
+            240.0,
+        ),
+    ];
+
+    for (source, want) in cases {
+        let state = render_headless(source);
+        assert_eq!(state.cursor_position, (25.0, 120.0 + want), "{}", source);
+    }
+}
+
+#[test]
+fn test_render_display_none() {
+    let visible = render_headless(r#"<html><body><div>shown</div></body></html>"#);
+    let hidden = render_headless(
+        r#"<html><body><div>shown</div><div style="display: none;">hidden <a href="hidden.html">link</a></div></body></html>"#,
+    );
+
+    assert!(hidden.hyper_links.is_empty());
+    assert_eq!(hidden.cursor_position, visible.cursor_position);
+}
+
+#[test]
+fn test_render_skips_head() {
+    let plain = render_headless(r#"<html><body>content</body></html>"#);
+    let with_head = render_headless(
+        r#"<html><head><title>title text</title><body><a href="head.html">head link</a></body></head><body>content</body></html>"#,
+    );
+
+    assert!(with_head.hyper_links.is_empty());
+    assert_eq!(with_head.cursor_position, plain.cursor_position);
+}
+
+#[test]
+fn test_render_skips_template() {
+    let plain = render_headless(r#"<html><body>content</body></html>"#);
+    let with_template = render_headless(
+        r#"<html><body><template><a href="row.html">row</a><p>more</p></template>content</body></html>"#,
+    );
+
+    assert!(with_template.hyper_links.is_empty());
+    assert!(!with_template
+        .boxes
+        .iter()
+        .any(|layout_box| layout_box.name == "a" || layout_box.name == "p"));
+    assert_eq!(with_template.text_runs.len(), 1);
+    assert_eq!(with_template.cursor_position, plain.cursor_position);
+}
+
+#[test]
+fn test_render_noscript() {
+    let state = render_headless(
+        r#"<html><body><script>document.write("hi")</script><noscript>JS disabled</noscript></body></html>"#,
+    );
+
+    let texts = state
+        .text_runs
+        .iter()
+        .map(|(_, text)| text.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["JS", "disabled"]);
+}
+
+#[test]
+fn test_render_fragment() {
+    let state = render_headless("<div>first</div><div>second</div>");
+
+    let texts = state
+        .text_runs
+        .iter()
+        .map(|(_, text)| text.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["first", "second"]);
+}
+
+#[test]
+fn test_render_spacing() {
+    let state = render_headless(
+        r#"<html><body>a <a href="x.html">link</a>. next <b>bold</b><i>italic</i></body></html>"#,
+    );
+
+    let runs = state
+        .text_runs
+        .iter()
+        .map(|(rect, _)| (rect.x(), rect.right()))
+        .collect::<Vec<_>>();
+
+    let gaps = runs
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].1)
+        .collect::<Vec<_>>();
+    // a _ link . _ next _ bold italic
+    assert_eq!(gaps, vec![8.0, 0.0, 8.0, 8.0, 0.0]);
+}
+
+#[test]
+fn test_render_layout_boxes() {
+    let state = render_headless(
+        r#"<html><body><div style="height: 100px;">one two</div><p><b>three</b></p></body></html>"#,
+    );
+
+    let boxes = state
+        .boxes
+        .iter()
+        .map(|layout_box| {
+            (
+                layout_box.depth,
+                layout_box.text.as_deref().unwrap_or(&layout_box.name),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        boxes,
+        vec![
+            (0, "html"),
+            (1, "body"),
+            (2, "div"),
+            (3, "one"),
+            (3, "two"),
+            (2, "p"),
+            (3, "b"),
+            (4, "three"),
+        ]
+    );
+
+    let rect = |name: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref().unwrap_or(&layout_box.name) == name)
+            .unwrap()
+            .rect
+    };
+    assert_eq!(rect("div").height(), 100.0);
+    assert_eq!(rect("b"), rect("three"));
+    assert_eq!(rect("body").top, rect("div").top);
+    assert_eq!(rect("body").bottom, rect("three").bottom);
+}
+
+#[test]
+fn test_render_border() {
+    let html = crate::html::parse_html(
+        r##"<html><body>before<div style="border: 4px solid #ff0000;">boxed</div>after</body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+    let mut surface = skia_safe::surfaces::raster_n32_premul((800, 600)).unwrap();
+    surface.canvas().clear(0xFFFFFFFF);
+    let state = render(
+        surface.canvas(),
+        &html,
+        800,
+        600,
+        &RenderOptions::default(),
+        &mut TextCache::default(),
+    );
+    let rect = |name: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref().unwrap_or(&layout_box.name) == name)
+            .unwrap()
+            .rect
+    };
+
+    // the border takes up room around the content, and is part of the element's box
+    let (boxed, div) = (rect("boxed"), rect("div"));
+    assert_eq!(
+        (boxed.left, boxed.top),
+        (25.0 + 4.0, rect("before").bottom + 4.0)
+    );
+    assert_eq!(div, boxed.with_outset((4.0, 4.0)));
+    assert_eq!(rect("after").top, div.bottom);
+    assert_eq!(rect("body").bottom, rect("after").bottom);
+
+    let pixels = surface.peek_pixels().unwrap();
+    let pixel = |x: f32, y: f32| {
+        let i = ((y as usize) * 800 + x as usize) * 4;
+        let bytes = pixels.bytes().unwrap();
+        (bytes[i + 2], bytes[i + 1], bytes[i])
+    };
+    let red = (0xff, 0x00, 0x00);
+    assert_eq!(pixel(div.left + 1.0, boxed.center_y()), red);
+    assert_eq!(pixel(div.right - 1.0, boxed.center_y()), red);
+    assert_eq!(pixel(boxed.center_x(), div.top + 1.0), red);
+    assert_eq!(pixel(boxed.center_x(), div.bottom - 1.0), red);
+    assert_eq!(pixel(div.right + 1.0, boxed.center_y()), (0xff, 0xff, 0xff));
+}
+
+#[test]
+fn test_render_blockquote() {
+    let state = render_headless(
+        r#"<html><body>before <blockquote>quoted <blockquote>nested</blockquote></blockquote>after</body></html>"#,
+    );
+
+    let runs = state
+        .text_runs
+        .iter()
+        .map(|(rect, text)| (text.as_str(), rect.x()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        runs,
+        vec![
+            ("before", 25.0),
+            ("quoted", 25.0 + BLOCKQUOTE_INDENT),
+            ("nested", 25.0 + BLOCKQUOTE_INDENT * 2.0),
+            ("after", 25.0),
+        ]
+    );
+    assert_eq!(state.left_margin, 25.0);
+}
+
+#[test]
+fn test_render_details() {
+    let source = r#"<html><body><details><summary>Closed</summary>hidden <a href="hidden.html">link</a></details><details open=""><summary>Open</summary>shown</details>after</body></html>"#;
+    let html = crate::html::parse_html(source.to_string()).unwrap();
+    let render_with = |details_open: &dyn Fn(&str) -> Option<bool>| {
+        layout(
+            &html,
+            800,
+            600,
+            &RenderOptions {
+                details_open,
+                ..RenderOptions::default()
+            },
+        )
+    };
+    let texts = |state: &RendererState| {
+        state
+            .text_runs
+            .iter()
+            .map(|(_, text)| text.clone())
+            .collect::<Vec<_>>()
+    };
+
+    let state = render_with(&|_| None);
+    assert_eq!(texts(&state), vec!["Closed", "Open", "shown", "after"]);
+    assert!(state.hyper_links.is_empty());
+    assert_eq!(
+        state
+            .disclosures
+            .iter()
+            .map(|(_, disclosure)| disclosure.clone())
+            .collect::<Vec<_>>(),
+        vec![
+            Disclosure {
+                key: "html[0]:body[0]:details[0]".to_string(),
+                open: false,
+            },
+            Disclosure {
+                key: "html[0]:body[0]:details[1]".to_string(),
+                open: true,
+            },
+        ]
+    );
+
+    // each on a line of its own, the summary text after its marker
+    let rows = state
+        .text_runs
+        .iter()
+        .map(|(rect, _)| rect.top)
+        .collect::<Vec<_>>();
+    assert!(rows.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", rows);
+    let (summary, _) = &state.text_runs[0];
+    let (target, _) = &state.disclosures[0];
+    assert!(target.left < summary.left);
+    assert_eq!(target.right, summary.right);
+
+    // the reader's toggling overrides the attribute
+    let state = render_with(&|key| Some(key.ends_with("details[0]")));
+    assert_eq!(
+        texts(&state),
+        vec!["Closed", "hidden", "link", "Open", "after"]
+    );
+    assert_eq!(state.hyper_links.len(), 1);
+}
+
+#[test]
+fn test_render_text_align() {
+    // content spans x = 25..775
+    let cases = vec![
+        (r#"<center>a b</center>"#, TextAlign::Center),
+        (r#"<div align="center">a b</div>"#, TextAlign::Center),
+        (r#"<p align="right">a b</p>"#, TextAlign::Right),
+        (
+            r#"<div align="bogus" style="text-align: left;">a b</div>"#,
+            TextAlign::Left,
+        ),
+        (
+            r#"<div style="text-align: left;" align="right">a b</div>"#,
+            TextAlign::Left,
+        ),
+        (
+            r#"<div align="center"><div align="right">a b</div></div>"#,
+            TextAlign::Right,
+        ),
+    ];
+
+    for (content, want) in cases {
+        let state = render_headless(&format!(
+            "<html><body>before {}after</body></html>",
+            content
+        ));
+        let run = |text: &str| {
+            state
+                .text_runs
+                .iter()
+                .find(|(_, run)| run == text)
+                .map(|(rect, _)| *rect)
+                .unwrap()
+        };
+        let (a, b) = (run("a"), run("b"));
+
+        assert_eq!(b.left - a.right, SPACE_WIDTH, "{}", content);
+        assert!(a.top > run("before").top, "{}", content);
+        assert!(run("after").top > a.top, "{}", content);
+        assert_eq!(run("after").left, 25.0, "{}", content);
+        match want {
+            TextAlign::Left => assert_eq!(a.left, 25.0, "{}", content),
+            TextAlign::Center => assert_eq!(a.left - 25.0, 775.0 - b.right, "{}", content),
+            TextAlign::Right => assert_eq!(b.right, 775.0, "{}", content),
+            TextAlign::Start | TextAlign::End => unreachable!(),
+        }
+    }
+
+    let state = render_headless(r#"<html><body><center><b>a</b></center></body></html>"#);
+    let bold = state.boxes.iter().find(|layout_box| layout_box.name == "b");
+    assert_eq!(bold.unwrap().rect, state.text_runs[0].0);
+}
+
+#[test]
+fn test_render_text_style() {
+    let state = render_headless(
+        r#"<html><body>plain <b>bold <i>both</i></b> <i>italic</i> <u>under</u> <s>struck</s> <strike>strike</strike></body></html>"#,
+    );
+    let style = |text: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some(text))
+            .unwrap()
+            .style
+            .unwrap()
+    };
+    let bold = TextStyle {
+        bold: true,
+        ..TextStyle::default()
+    };
+    let italic = TextStyle {
+        italic: true,
+        ..TextStyle::default()
+    };
+
+    assert_eq!(style("plain"), TextStyle::default());
+    assert_eq!(style("bold"), bold);
+    assert_eq!(
+        style("both"),
+        TextStyle {
+            italic: true,
+            ..bold
+        }
+    );
+    assert_eq!(style("italic"), italic);
+    assert_eq!(
+        style("under"),
+        TextStyle {
+            underline: true,
+            ..TextStyle::default()
+        }
+    );
+    let struck = TextStyle {
+        line_through: true,
+        ..TextStyle::default()
+    };
+    assert_eq!(style("struck"), struck);
+    assert_eq!(style("strike"), struck);
+}
+
+#[test]
+fn test_render_text_decoration() {
+    // rows painted in the middle column of the word, which only its decorations reach
+    let painted_rows = |source: &str| {
+        let html = crate::html::parse_html(source.to_string()).unwrap();
+        let state = layout(&html, 400, 300, &RenderOptions::default());
+        let rect = state.text_runs[0].0;
+        let x = rect.center_x() as usize;
+        let pixels = render_page(&html, 400, 300);
+
+        (rect.top as usize..rect.bottom as usize)
+            .filter(|y| pixels[y * 400 + x] & 0xFFFFFF != 0xFFFFFF)
+            .collect::<Vec<_>>()
+    };
+
+    let under = painted_rows("<html><body><u>under</u></body></html>");
+    let struck = painted_rows("<html><body><s>struck</s></body></html>");
+    assert!(painted_rows("<html><body>plain</body></html>").is_empty());
+    assert!(!under.is_empty());
+    assert!(!struck.is_empty());
+    assert!(struck.last() < under.first(), "{:?} {:?}", struck, under);
+    assert_eq!(
+        painted_rows("<html><body><strike>struck</strike></body></html>"),
+        struck
+    );
+}
+
+#[test]
+fn test_render_text_decoration_property() {
+    let style = |source: &str| {
+        render_headless(source)
+            .boxes
+            .iter()
+            .find_map(|layout_box| layout_box.style)
+            .unwrap()
+    };
+    let lines = |source: &str| {
+        let style = style(source);
+        (style.underline, style.overline, style.line_through)
+    };
+
+    let link = r#"<a href="next.html">link</a>"#;
+    assert_eq!(
+        lines(&format!("<html><body>{}</body></html>", link)),
+        (true, false, false)
+    );
+    assert_eq!(
+        lines(&format!(
+            "<html><head><style>a {{ text-decoration: none; }}</style></head><body>{}</body></html>",
+            link
+        )),
+        (false, false, false)
+    );
+    let cases = vec![
+        (
+            r#"<p style="text-decoration: underline overline">text</p>"#,
+            (true, true, false),
+        ),
+        (
+            r#"<span style="text-decoration: line-through #ff0000">text</span>"#,
+            (false, false, true),
+        ),
+        (
+            r#"<u style="text-decoration: none">text</u>"#,
+            (false, false, false),
+        ),
+        (
+            r#"<u style="text-decoration: #ff0000">text</u>"#,
+            (true, false, false),
+        ),
+        // an outer element's line goes through its descendants regardless
+        (
+            r#"<u><span style="text-decoration: none">text</span></u>"#,
+            (true, false, false),
+        ),
+        (
+            r#"<s><span style="text-decoration: overline">text</span></s>"#,
+            (false, true, true),
+        ),
+    ];
+    for (body, want) in cases {
+        assert_eq!(
+            lines(&format!("<html><body>{}</body></html>", body)),
+            want,
+            "{}",
+            body
+        );
+    }
+
+    // rows painted in the middle of the first word, or of the space after it, which only
+    // decorations reach
+    let painted_rows = |source: &str, after_word: bool| {
+        let html = crate::html::parse_html(source.to_string()).unwrap();
+        let state = layout(&html, 400, 300, &RenderOptions::default());
+        let rect = state.text_runs[0].0;
+        let runs = &state.text_runs;
+        let x = if after_word {
+            (runs[0].0.right + runs[1].0.left) / 2.0
+        } else {
+            runs[0].0.center_x()
+        } as usize;
+        let pixels = render_page(&html, 400, 300);
+
+        (rect.top as usize..rect.bottom as usize)
+            .filter(|y| pixels[y * 400 + x] & 0xFFFFFF != 0xFFFFFF)
+            .collect::<Vec<_>>()
+    };
+
+    let over = painted_rows(
+        r#"<html><body><span style="text-decoration: overline">over</span></body></html>"#,
+        false,
+    );
+    let struck = painted_rows("<html><body><s>struck</s></body></html>", false);
+    assert!(!over.is_empty());
+    assert!(over.last() < struck.first(), "{:?} {:?}", over, struck);
+    assert!(painted_rows(
+        &format!(
+            "<html><head><style>a {{ text-decoration: none; }}</style></head><body>{}</body></html>",
+            link
+        ),
+        false
+    )
+    .is_empty());
+
+    // the space between two words of the same text is lined too, but not the one before it
+    let under = painted_rows("<html><body><u>under</u></body></html>", false);
+    assert_eq!(
+        painted_rows("<html><body><u>two words</u></body></html>", true),
+        under
+    );
+    assert!(painted_rows("<html><body><u>two</u> <u>words</u></body></html>", true).is_empty());
+}
+
+#[test]
+fn test_render_multibyte() {
+    let state = render_headless(
+        r#"<html><body>日本語のテキスト 🎉 <a href="次.html">リンク</a></body></html>"#,
+    );
+
+    let runs = state
+        .text_runs
+        .iter()
+        .map(|(_, text)| text.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(runs, vec!["日本語のテキスト", "🎉", "リンク"]);
+
+    // measured as whole runs, spaced and on one line
+    for pair in state.text_runs.windows(2) {
+        let ((left, _), (right, _)) = (&pair[0], &pair[1]);
+        assert!(left.width() > 0.0 && right.width() > 0.0);
+        assert_eq!(right.left - left.right, SPACE_WIDTH);
+        assert_eq!(right.top, left.top);
+    }
+    assert_eq!(state.hyper_links[0].1.href, "次.html");
+    assert_eq!(state.hyper_links[0].0, state.text_runs[2].0);
+}
+
+#[test]
+fn test_render_direction() {
+    // content spans x = 25..775
+    let runs = |content: &str| {
+        render_headless(&format!("<html><body>{}</body></html>", content))
+            .text_runs
+            .into_iter()
+            .map(|(rect, _)| rect)
+            .collect::<Vec<_>>()
+    };
+
+    let rtl = runs(r#"<div dir="rtl">one two<br />three</div>"#);
+    assert_eq!(rtl[0].right, 775.0);
+    assert_eq!(rtl[0].left - rtl[1].right, SPACE_WIDTH);
+    assert_eq!(rtl[2].right, 775.0);
+
+    let ltr = runs(r#"<div dir="rtl"><p dir="ltr">one two</p></div>"#);
+    assert_eq!(ltr[0].left, 25.0);
+
+    // with no `dir`, the script decides
+    let hebrew = runs("שלום עולם");
+    assert_eq!(hebrew[0].right, 775.0);
+    assert!(hebrew[1].right < hebrew[0].left);
+
+    let styled = runs(r#"<div style="direction: rtl;">one</div>"#);
+    assert_eq!(styled[0].right, 775.0);
+
+    // `end` is the left side in a right-to-left block, while left and right stay put
+    let end = runs(r#"<div dir="rtl" style="text-align: end;">one two</div>"#);
+    assert_eq!(end[1].left, 25.0);
+    let left = runs(r#"<div dir="rtl" align="left">one two</div>"#);
+    assert_eq!(left[1].left, 25.0);
+    assert_eq!(left[0].left - left[1].right, SPACE_WIDTH);
+}
+
+#[test]
+fn test_render_white_space() {
+    // with no room right of the left margin, every run that may wrap does
+    let narrow = Viewport {
+        right: 800.0 - 25.0 - 1.0,
+        ..Viewport::default()
+    };
+    let runs = |content: &str| {
+        render_headless_with(&format!("<html><body>{}</body></html>", content), narrow)
+            .text_runs
+            .into_iter()
+            .map(|(rect, _)| rect)
+            .collect::<Vec<_>>()
+    };
+
+    let normal = runs(r#"<div style="white-space: normal;">a   b</div>"#);
+    assert!(normal[1].top > normal[0].top);
+
+    let nowrap = runs(r#"<div style="white-space: nowrap;">a   b</div>"#);
+    assert_eq!(nowrap[1].top, nowrap[0].top);
+    assert_eq!(nowrap[1].left - nowrap[0].right, SPACE_WIDTH);
+
+    for content in [
+        r#"<div style="white-space: pre;">a   b
+  c</div>"#,
+        "<pre>\na   b\n  c</pre>",
+    ] {
+        let pre = runs(content);
+        assert_eq!(pre[1].top, pre[0].top, "{}", content);
+        assert_eq!(pre[1].left - pre[0].right, SPACE_WIDTH * 3.0, "{}", content);
+        assert!(pre[2].top > pre[0].top, "{}", content);
+        assert_eq!(pre[2].left, 25.0 + SPACE_WIDTH * 2.0, "{}", content);
+    }
+
+    let tabbed = runs("<pre>a\tb</pre>");
+    assert_eq!(tabbed[1].left, 25.0 + SPACE_WIDTH * 8.0);
+}
+
+#[test]
+fn test_render_viewport() {
+    let source = r#"<html><body>aaaa bbbb cccc</body></html>"#;
+    let origins = |state: RendererState| {
+        state
+            .text_runs
+            .iter()
+            .map(|(rect, _)| (rect.x(), rect.y()))
+            .collect::<Vec<_>>()
+    };
+
+    let inset = render_headless_with(
+        source,
+        Viewport {
+            left: 50.0,
+            top: 200.0,
+            right: 0.0,
+            bottom: 0.0,
+        },
+    );
+    let wide = origins(render_headless(source));
+    assert_eq!(origins(inset)[0], (50.0, wide[0].1 + 80.0));
+    assert!(wide.iter().all(|(_, y)| *y == wide[0].1));
+
+    // with no room left of the right padding every run wraps, but still gets drawn
+    let narrow = render_headless_with(
+        source,
+        Viewport {
+            right: 800.0 - 25.0 - 1.0,
+            ..Viewport::default()
+        },
+    );
+    let narrow = origins(narrow);
+    assert_eq!(
+        narrow,
+        vec![
+            (25.0, wide[0].1),
+            (25.0, wide[0].1 + normal_line_height()),
+            (25.0, wide[0].1 + normal_line_height() * 2.0)
+        ]
+    );
+}
+
+#[test]
+fn test_render_anchor_hrefs() {
+    let state = render_headless(
+        r##"<html><body><a name="top">named</a> <a href="">empty</a> <a href="#">hash</a> <a href="outer.html">outer <b>bold</b> <a href="inner.html">inner</a></a></body></html>"##,
+    );
+
+    let links = state
+        .hyper_links
+        .iter()
+        .map(|(_, link)| link.href.as_str())
+        .collect::<Vec<_>>();
+
+    assert_eq!(links, vec!["outer.html", "outer.html", "inner.html"]);
+    assert_eq!(state.text_runs.len(), 6);
+}
+
+#[test]
+fn test_render_code() {
+    let state = render_headless(
+        r#"<html><body><p>Call <code>fetch()</code> <b><code>first</code></b> then</p></body></html>"#,
+    );
+    let runs = state
+        .boxes
+        .iter()
+        .filter_map(|layout_box| {
+            Some((
+                layout_box.text.as_deref()?,
+                layout_box.family.as_deref()?,
+                layout_box.rect.top,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    // inline, on the same line as the text around it, in the monospace face
+    let (_, family, top) = runs[0];
+    assert_ne!(family, "Noto Sans Mono");
+    assert_eq!(
+        runs,
+        vec![
+            ("Call", family, top),
+            ("fetch()", "Noto Sans Mono", top),
+            ("first", "Noto Sans Mono", top),
+            ("then", family, top),
+        ]
+    );
+}
+
+#[test]
+fn test_render_mark() {
+    let html = crate::html::parse_html(
+        r##"<html><body>plain <mark>yellow</mark> <mark style="background-color: #808080">two <b>words</b></mark> plain</body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+    let state = layout(&html, 400, 300, &RenderOptions::default());
+    let runs = state
+        .boxes
+        .iter()
+        .filter(|layout_box| layout_box.text.is_some())
+        .map(|layout_box| (layout_box.rect, layout_box.style.unwrap().background))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        runs.iter()
+            .map(|(_, background)| *background)
+            .collect::<Vec<_>>(),
+        vec![None, Some(0xffff00), Some(0x808080), Some(0x808080), None]
+    );
+
+    // behind the marked words and the space between them, but not the text around them
+    let buffer = render_page(&html, 400, 300);
+    let pixel = |x: f32, y: f32| buffer[y as usize * 400 + x as usize];
+    let (two, words, after) = (runs[2].0, runs[3].0, runs[4].0);
+    assert_eq!(pixel(two.left + 1.0, two.top + 1.0), 0x808080);
+    assert_eq!(
+        pixel(words.left - SPACE_WIDTH / 2.0, words.top + 1.0),
+        0x808080
+    );
+    assert_eq!(
+        pixel(words.right + SPACE_WIDTH / 2.0, words.top + 1.0),
+        0xffffff
+    );
+    assert_eq!(pixel(after.left + 1.0, after.top + 1.0), 0xffffff);
+}
+
+#[test]
+fn test_render_sup_sub() {
+    let state = render_headless("<html><body>x<sup>2</sup> y<sub>i</sub></body></html>");
+    let run = |text: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some(text))
+            .unwrap()
+            .rect
+    };
+    let (x, two, y, i) = (run("x"), run("2"), run("y"), run("i"));
+
+    // raised or lowered against the text around it, and smaller
+    assert!(two.bottom < x.bottom, "{:?} {:?}", two, x);
+    assert!(two.height() < x.height());
+    assert!(i.bottom > y.bottom, "{:?} {:?}", i, y);
+    assert!(i.height() < y.height());
+    assert_eq!(two.left, x.right);
+}
+
+#[test]
+fn test_render_definition_list() {
+    let state = render_headless(
+        "<html><body><dl><dt>term</dt><dd>meaning</dd><dt>next</dt><dd>more <dl><dd>nested</dd></dl></dd></dl>after</body></html>",
+    );
+    let run = |text: &str| {
+        state
+            .text_runs
+            .iter()
+            .find(|(_, run)| run == text)
+            .unwrap()
+            .0
+    };
+
+    // each term and definition on a line of its own, the definitions indented
+    assert_eq!(run("term").left, 25.0);
+    assert_eq!(run("meaning").left, 25.0 + DEFINITION_INDENT);
+    assert_eq!(run("meaning").top, run("term").bottom);
+    assert_eq!(run("next").left, 25.0);
+    assert_eq!(run("next").top, run("meaning").bottom);
+    assert_eq!(run("nested").left, 25.0 + DEFINITION_INDENT * 2.0);
+    assert_eq!(run("after").left, 25.0);
+}
+
+#[test]
+fn test_render_quotes() {
+    let state = render_headless(
+        "<html><body><p>say <q>hi</q> and <q>a <q>b c</q> d</q> <q><b>x</b></q> <q> </q></p></body></html>",
+    );
+    let words = state
+        .boxes
+        .iter()
+        .filter_map(|layout_box| layout_box.text.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        words,
+        vec!["say", "\"hi\"", "and", "\"a", "'b", "c'", "d\"", "\"x\""]
+    );
+}
+
+#[test]
+fn test_render_word_breaks() {
+    let lines = |body: &str| {
+        let state = render_headless(&format!("<html><body>{}</body></html>", body));
+        let mut lines: Vec<(f32, Vec<String>)> = vec![];
+        for (rect, text) in state.text_runs {
+            match lines.last_mut() {
+                Some((top, texts)) if *top == rect.top => texts.push(text),
+                _ => lines.push((rect.top, vec![text])),
+            }
+        }
+        lines
+            .into_iter()
+            .map(|(_, texts)| texts)
+            .collect::<Vec<_>>()
+    };
+    let a = "a".repeat(30);
+    let b = "b".repeat(30);
+
+    // too long for a line, a word only wraps where it may
+    assert_eq!(
+        lines(&format!("{}{}", a, b)),
+        vec![vec![format!("{}{}", a, b)]]
+    );
+    assert_eq!(
+        lines(&format!("{}<wbr />{}", a, b)),
+        vec![vec![a.clone()], vec![b.clone()]]
+    );
+    assert_eq!(
+        lines(&format!("{}&shy;{}", a, b)),
+        vec![vec![format!("{}-", a)], vec![b.clone()]]
+    );
+    // the break opportunities are invisible when the word fits
+    assert_eq!(
+        lines("co&shy;operate with<wbr />out"),
+        vec![vec!["cooperate", "with", "out"]]
+    );
+    // where no break leaves room after "start", the word moves to a line of its own to break
+    let long = "a".repeat(42);
+    assert_eq!(
+        lines(&format!("start {}&shy;{}", long, long)),
+        vec![
+            vec!["start".to_string()],
+            vec![format!("{}-", long)],
+            vec![long]
+        ]
+    );
+}
+
+#[test]
+fn test_render_titles() {
+    let state = render_headless(
+        r#"<html><body><p title="ignored">A <abbr title="HyperText Markup Language">HTML</abbr> page <span title="outer">with <acronym title="inner">CSS</acronym> and text</span></p></body></html>"#,
+    );
+    let titles = state
+        .titles
+        .iter()
+        .map(|(rect, title)| {
+            let (_, text) = state.text_runs.iter().find(|(run, _)| run == rect).unwrap();
+            (text.as_str(), title.as_str())
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        titles,
+        vec![
+            ("HTML", "HyperText Markup Language"),
+            ("with", "outer"),
+            ("CSS", "inner"),
+            ("and", "outer"),
+            ("text", "outer"),
+        ]
+    );
+}
+
+#[test]
+fn test_list_marker() {
+    let markers = |kind| {
+        (1..=5)
+            .map(|index| list_marker(index, kind))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(markers('1'), vec!["1", "2", "3", "4", "5"]);
+    assert_eq!(markers('a'), vec!["a", "b", "c", "d", "e"]);
+    assert_eq!(markers('I'), vec!["I", "II", "III", "IV", "V"]);
+
+    assert_eq!(list_marker(26, 'a'), "z");
+    assert_eq!(list_marker(27, 'A'), "AA");
+    assert_eq!(list_marker(703, 'a'), "aaa");
+
+    assert_eq!(list_marker(9, 'i'), "ix");
+    assert_eq!(list_marker(14, 'i'), "xiv");
+    assert_eq!(list_marker(40, 'I'), "XL");
+    assert_eq!(list_marker(1994, 'I'), "MCMXCIV");
+    assert_eq!(list_marker(2024, 'i'), "mmxxiv");
+    assert_eq!(list_marker(3999, 'I'), "MMMCMXCIX");
+
+    // outside what letters and roman numerals can write
+    assert_eq!(list_marker(0, 'a'), "0");
+    assert_eq!(list_marker(-2, 'i'), "-2");
+    assert_eq!(list_marker(4000, 'I'), "4000");
+}
+
+#[test]
+fn test_render_list() {
+    let state = render_headless(
+        r#"<html><body><ol start="5" type="A"><li>five</li><li>six <ol type="i" start="3"><li>three</li></ol></li></ol><ul><li>dot</li></ul><ol type="x"><li>one</li></ol></body></html>"#,
+    );
+    let markers = state
+        .boxes
+        .iter()
+        .filter(|layout_box| layout_box.name == "::marker")
+        .collect::<Vec<_>>();
+    assert_eq!(
+        markers
+            .iter()
+            .map(|marker| marker.text.as_deref().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["E.", "F.", "iii.", "\u{2022}", "1."]
+    );
+
+    // each marker hangs a space before its item's text, which is indented per open list
+    let left = |text: &str| {
+        let (run, _) = state.text_runs.iter().find(|(_, t)| t == text).unwrap();
+        run.left
+    };
+    for (marker, text) in markers.iter().zip(["five", "six", "three", "dot", "one"]) {
+        assert_eq!(marker.rect.right + SPACE_WIDTH, left(text));
+    }
+    assert_eq!(left("three") - left("six"), LIST_INDENT);
+    assert_eq!(left("five"), left("dot"));
+}
+
+#[test]
+fn test_render_semantic_blocks() {
+    let state = render_headless(
+        r#"<html><body>before <nav><a href="a.html">home</a> <a href="b.html">about</a></nav><article><header>title</header>text <em>inline</em></article>after <span style="display: block;">own</span> end</body></html>"#,
+    );
+    let lines = state
+        .text_runs
+        .iter()
+        .map(|(rect, text)| (text.as_str(), rect.left, rect.top))
+        .collect::<Vec<_>>();
+    let (_, left, top) = lines[0];
+    let line = |n: f32| top + n * normal_line_height();
+
+    // each block starts a line at the margin, and what follows it starts another
+    assert_eq!(
+        lines,
+        vec![
+            ("before", left, line(0.0)),
+            ("home", left, line(1.0)),
+            ("about", left + 4.0 * 16.0 + SPACE_WIDTH, line(1.0)),
+            ("title", left, line(2.0)),
+            ("text", left, line(3.0)),
+            ("inline", left + 4.0 * 16.0 + SPACE_WIDTH, line(3.0)),
+            ("after", left, line(4.0)),
+            ("own", left, line(5.0)),
+            ("end", left, line(6.0)),
+        ]
+    );
+}
+
+#[test]
+fn test_render_table() {
+    let state = render_headless(
+        r#"<html><body><table><tr><td colspan="2">wide</td></tr><tr><td>left</td><td>right cell that wraps onto a second line</td></tr><tr><td rowspan="2">tall</td><td>a</td></tr><tr><td>b</td></tr></table>after</body></html>"#,
+    );
+    let cells = state
+        .boxes
+        .iter()
+        .filter(|layout_box| layout_box.name == "td")
+        .map(|layout_box| layout_box.rect)
+        .collect::<Vec<_>>();
+    let run = |text: &str| {
+        let (rect, _) = state.text_runs.iter().find(|(_, t)| t == text).unwrap();
+        *rect
+    };
+    let line = normal_line_height();
+    let row = line + 2.0 * CELL_PADDING;
+    let (top, middle, right) = (cells[0].top, 25.0 + 750.0 / 2.0, 775.0);
+
+    // the wide cell spans both columns, the ones below take one each, and a row is as tall as
+    // its tallest cell
+    assert_eq!(cells[0], Rect::new(25.0, top, right, top + row));
+    assert_eq!(
+        cells[1],
+        Rect::new(25.0, top + row, middle, top + 2.0 * row + line)
+    );
+    assert_eq!(
+        cells[2],
+        Rect::new(middle, top + row, right, top + 2.0 * row + line)
+    );
+    let top = cells[1].bottom;
+    assert_eq!(cells[3], Rect::new(25.0, top, middle, top + 2.0 * row));
+    assert_eq!(cells[4], Rect::new(middle, top, right, top + row));
+    assert_eq!(
+        cells[5],
+        Rect::new(middle, top + row, right, top + 2.0 * row)
+    );
+
+    // content is padded in from the cell's edges, and wraps at its right edge
+    assert_eq!(
+        (run("right").left, run("right").top),
+        (middle + CELL_PADDING, top - row - line + CELL_PADDING)
+    );
+    assert!(state
+        .text_runs
+        .iter()
+        .all(|(rect, _)| rect.right <= right - CELL_PADDING));
+    let wrapped = state
+        .text_runs
+        .iter()
+        .filter(|(rect, _)| rect.top == run("right").top + line)
+        .map(|(rect, _)| rect.left)
+        .fold(f32::MAX, f32::min);
+    assert_eq!(wrapped, middle + CELL_PADDING);
+    assert_eq!(
+        (run("after").left, run("after").top),
+        (25.0, cells[5].bottom)
+    );
+}
+
+#[test]
+fn test_render_link_target() {
+    let state = render_headless(
+        r#"<html><body><a href="same.html">same</a> <a href="new.html" target="_blank">new <a href="inner.html">inner</a></a></body></html>"#,
+    );
+
+    let links = state
+        .hyper_links
+        .iter()
+        .map(|(_, link)| (link.href.as_str(), link.target.as_deref()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        links,
+        vec![
+            ("same.html", None),
+            ("new.html", Some("_blank")),
+            ("inner.html", None),
+        ]
+    );
+}
+
+#[test]
+fn test_render_document_height() {
+    let empty = render_headless(r#"<html><body></body></html>"#);
+    assert_eq!(empty.document_height, 0.0);
+
+    let tall =
+        render_headless(r#"<html><body><div style="height: 1000px;"></div>after</body></html>"#);
+    assert!(tall.document_height > 1000.0);
+
+    // each extra line adds one line height, whatever the font metrics
+    let one = render_headless(r#"<html><body>a</body></html>"#);
+    let three = render_headless(r#"<html><body>a<br />b<br />c</body></html>"#);
+    assert_eq!(
+        three.document_height - one.document_height,
+        normal_line_height() * 2.0
+    );
+}
+
+#[test]
+fn test_render_stats() {
+    let html = crate::html::parse_html(r#"<html><body><p>some text</p></body></html>"#.to_string())
+        .unwrap();
+    assert_eq!(
+        layout(&html, 800, 600, &RenderOptions::default()).stats,
+        None
+    );
+
+    let profiled = layout(
+        &html,
+        800,
+        600,
+        &RenderOptions {
+            profile: true,
+            ..RenderOptions::default()
+        },
+    );
+    assert!(profiled.stats.is_some());
+}
+
+#[test]
+fn test_render_line_height() {
+    let tops = |source: &str| {
+        render_headless(source)
+            .text_runs
+            .iter()
+            .map(|(rect, _)| rect.top)
+            .collect::<Vec<_>>()
+    };
+    let normal = normal_line_height();
+
+    let cases = vec![
+        ("a<br />b", normal),
+        (r#"<div style="line-height: 2;">a<br />b</div>"#, 64.0),
+        (r#"<div style="line-height: 50px;">a<br />b</div>"#, 50.0),
+        (r#"<div style="line-height: 150%;">a<br />b</div>"#, 48.0),
+        (
+            r#"<div style="line-height: normal;">a<br />b</div>"#,
+            normal,
+        ),
+    ];
+    for (content, want) in cases {
+        let tops = tops(&format!("<html><body>{}</body></html>", content));
+        assert_eq!(tops[1] - tops[0], want, "{}", content);
+    }
+
+    // a taller run on the line moves the shared baseline down and the next line with it
+    let tops = tops(r#"<html><body>a <b style="line-height: 80px;">b</b><br />c</body></html>"#);
+    assert_eq!(tops[0], tops[1]);
+    assert_eq!(tops[2] - tops[0], 80.0 - (80.0 - normal) / 2.0);
+}
+
+#[test]
+fn test_render_zoom() {
+    let source = r#"<html><body>aaaa <a href="b.html">bbbb</a><br />cccc</body></html>"#;
+    let render_zoomed = |zoom: f32| {
+        let html = crate::html::parse_html(source.to_string()).unwrap();
+        let mut surface = skia_safe::surfaces::raster_n32_premul((800, 600)).unwrap();
+
+        render(
+            surface.canvas(),
+            &html,
+            800,
+            600,
+            &RenderOptions {
+                zoom,
+                ..RenderOptions::default()
+            },
+            &mut TextCache::default(),
+        )
+    };
+
+    let normal = render_zoomed(1.0);
+    let zoomed = render_zoomed(2.0);
+
+    let scaled = |rect: &Rect| (rect.x() * 2.0, 120.0 + (rect.y() - 120.0) * 2.0);
+    for ((normal, _), (zoomed, _)) in normal.text_runs.iter().zip(&zoomed.text_runs) {
+        assert_eq!((zoomed.x(), zoomed.y()), scaled(normal));
+    }
+    assert_eq!(
+        zoomed.hyper_links[0].0.x(),
+        normal.hyper_links[0].0.x() * 2.0
+    );
+    assert_eq!(zoomed.document_height, normal.document_height * 2.0);
+}
+
+#[test]
+fn test_render_font() {
+    let state = render_headless(
+        r##"<html><body text="#333333">plain <font color="#ff0000">red <font size="7">big</font></font> <font color="green" size="+1">larger</font> after</body></html>"##,
+    );
+    let run = |text: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some(text))
+            .unwrap()
+    };
+
+    assert_eq!(run("plain").color, Some(0x333333));
+    assert_eq!(run("red").color, Some(0xff0000));
+    assert_eq!(run("big").color, Some(0xff0000));
+    assert_eq!(run("larger").color, Some(0x008000));
+    assert_eq!(run("after").color, Some(0x333333));
+
+    let height = |text: &str| run(text).rect.height();
+    assert_eq!(height("red"), height("plain"));
+    assert_eq!(height("big"), height("plain") * 3.0);
+    assert!((height("larger") - height("plain") * 1.2).abs() < 0.01);
+}
+
+#[test]
+fn test_render_dark_mode() {
+    let render_with = |source: &str, dark_mode: DarkMode| {
+        let html = crate::html::parse_html(source.to_string()).unwrap();
+        let mut surface = skia_safe::surfaces::raster_n32_premul((200, 300)).unwrap();
+        surface.canvas().clear(0xFFFFFFFF);
+        let state = render(
+            surface.canvas(),
+            &html,
+            200,
+            300,
+            &RenderOptions {
+                dark_mode,
+                ..RenderOptions::default()
+            },
+            &mut TextCache::default(),
+        );
+
+        // a gray level, well below the text
+        let pixels = surface.peek_pixels().unwrap();
+        let background = pixels.bytes().unwrap()[(280 * 200 + 100) * 4];
+        let colors = state
+            .boxes
+            .iter()
+            .filter_map(|layout_box| layout_box.color)
+            .collect::<Vec<_>>();
+        (background, colors)
+    };
+
+    let plain = r#"<html><body>text <a href="next.html">link</a></body></html>"#;
+    assert_eq!(
+        render_with(plain, DarkMode::Off),
+        (0xff, vec![0x000000, 0x0055ff])
+    );
+    assert_eq!(
+        render_with(plain, DarkMode::On),
+        (0x1e, vec![0xe8e8e8, 0x8ab4f8])
+    );
+
+    // colors the page sets win, unless dark mode is forced
+    let colored = r##"<html><body bgcolor="#cccccc" text="#333333">text</body></html>"##;
+    assert_eq!(render_with(colored, DarkMode::On), (0xcc, vec![0x333333]));
+    assert_eq!(
+        render_with(colored, DarkMode::Forced),
+        (0x1e, vec![0xe8e8e8])
+    );
+}
+
+#[test]
+fn test_render_class_selector() {
+    let state = render_headless(
+        r##"<html><head><style>.hl { color: red; } p.note { color: #0000ff; } a.quiet:link { color: #333333; }</style></head><body><p class="hl">red</p><p>plain</p><p class="note hl">both</p><div class="note">div</div><a class="quiet" href="next.html">quiet</a></body></html>"##,
+    );
+    let color = |text: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some(text))
+            .unwrap()
+            .color
+    };
+
+    assert_eq!(color("red"), Some(0xff0000));
+    assert_eq!(color("plain"), Some(0x000000));
+    assert_eq!(color("both"), Some(0x0000ff));
+    assert_eq!(color("div"), Some(0x000000));
+    assert_eq!(color("quiet"), Some(0x333333));
+}
+
+#[test]
+fn test_render_specificity() {
+    let state = render_headless(
+        r##"<html><head><style>#top { color: #ff0000; } .note { color: #00ff00; } p { color: #0000ff; }</style></head><body><p id="top" class="note">id</p><p class="note">class</p><p>tag</p><p id="top" class="note" style="color: #333333">inline</p></body></html>"##,
+    );
+    let color = |text: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some(text))
+            .unwrap()
+            .color
+    };
+
+    assert_eq!(color("id"), Some(0xff0000));
+    assert_eq!(color("class"), Some(0x00ff00));
+    assert_eq!(color("tag"), Some(0x0000ff));
+    assert_eq!(color("inline"), Some(0x333333));
+}
+
+#[test]
+fn test_render_descendant_selector() {
+    let state = render_headless(
+        r##"<html><head><style>div a { color: #ff0000; } .menu p { color: #00ff00; }</style></head><body><div class="menu"><p>item <a href="in.html">inside</a></p></div><p>text <a href="out.html">outside</a></p></body></html>"##,
+    );
+    let color = |text: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some(text))
+            .unwrap()
+            .color
+    };
+
+    assert_eq!(color("inside"), Some(0xff0000));
+    assert_eq!(color("outside"), Some(0x0055ff));
+    assert_eq!(color("item"), Some(0x00ff00));
+    assert_eq!(color("text"), Some(0x000000));
+}
+
+#[test]
+fn test_render_user_styles() {
+    let html = crate::html::parse_html(
+        r##"<html><head><style>p { font-size: 16px; }</style></head><body text="#333333">plain <p>para</p></body></html>"##
+            .to_string(),
+    )
+    .unwrap();
+    let user_styles =
+        css::parse_css("body { color: #eeeeee; } p { font-size: 48px; }".to_string()).unwrap();
+    let run = |state: &RendererState, text: &str| {
+        state
+            .boxes
+            .iter()
+            .find(|layout_box| layout_box.text.as_deref() == Some(text))
+            .unwrap()
+            .clone()
+    };
+
+    let page = layout(&html, 800, 600, &RenderOptions::default());
+    assert_eq!(run(&page, "plain").color, Some(0x333333));
+
+    let state = layout(
+        &html,
+        800,
+        600,
+        &RenderOptions {
+            user_styles: Some(&user_styles),
+            ..RenderOptions::default()
+        },
+    );
+    assert_eq!(run(&state, "plain").color, Some(0xeeeeee));
+    assert_eq!(run(&state, "para").color, Some(0xeeeeee));
+    assert_eq!(
+        run(&state, "para").rect.height(),
+        run(&page, "para").rect.height() * 3.0
+    );
+}
+
+#[test]
+fn test_resolve_font_size() {
+    let fonts = FontSettings {
+        default_size: 20.0,
+        minimum_size: 0.0,
+    };
+    let cases = vec![
+        ("medium", Some(20.0)),
+        ("xx-large", Some(40.0)),
+        ("20px", Some(20.0)),
+        ("1.5em", Some(15.0)),
+        ("50%", Some(5.0)),
+        ("larger", Some(12.0)),
+        ("0", None),
+        ("huge", None),
+    ];
+
+    for (value, want) in cases {
+        assert_eq!(fonts.resolve(value, 10.0), want, "{}", value);
+    }
+}
+
+#[test]
+fn test_render_font_settings() {
+    let source = r#"<html><body>plain <font size="1">tiny</font></body></html>"#;
+    let heights = |fonts: FontSettings| {
+        let html = crate::html::parse_html(source.to_string()).unwrap();
+        let state = layout(
+            &html,
+            800,
+            600,
+            &RenderOptions {
+                fonts,
+                ..RenderOptions::default()
+            },
+        );
+
+        state
+            .text_runs
+            .iter()
+            .map(|(rect, _)| rect.height())
+            .collect::<Vec<_>>()
+    };
+
+    let default = heights(FontSettings::default());
+    assert_eq!(default[1], default[0] * 3.0 / 4.0);
+
+    let larger = heights(FontSettings {
+        default_size: 64.0,
+        ..FontSettings::default()
+    });
+    assert_eq!(larger, vec![default[0] * 2.0, default[1] * 2.0]);
+
+    // the minimum lifts the tiny text to it but leaves larger text alone
+    let clamped = heights(FontSettings {
+        minimum_size: 32.0,
+        ..FontSettings::default()
+    });
+    assert_eq!(clamped, vec![default[0], default[0]]);
+}
+
+#[test]
+fn test_text_cache_reused_across_redraws() {
+    let html = crate::html::parse_html(
+        r#"<html><body>one two one <a href="x.html">two</a></body></html>"#.to_string(),
+    )
+    .unwrap();
+    let mut surface = skia_safe::surfaces::raster_n32_premul((800, 600)).unwrap();
+    let mut text_cache = TextCache::default();
+
+    let mut redraw = |text_cache: &mut TextCache| {
+        render(
+            surface.canvas(),
+            &html,
+            800,
+            600,
+            &RenderOptions::default(),
+            text_cache,
+        )
+        .text_runs
+    };
+
+    let first = redraw(&mut text_cache);
+    assert_eq!(text_cache.misses, 2);
+
+    let second = redraw(&mut text_cache);
+    assert_eq!(text_cache.misses, 2);
+    assert_eq!(first, second);
+
+    text_cache.clear();
+    redraw(&mut text_cache);
+    assert_eq!(text_cache.misses, 4);
+}
+
+#[test]
+fn test_text_cache_measurements() {
+    // a paragraph-heavy page, with words too long for a line that are measured for where to
+    // break them before they're drawn
+    let paragraph = "<p>the quick brown fox jumps over the lazy dog un\u{ad}break\u{ad}able</p>";
+    let html = crate::html::parse_html(format!(
+        "<html><body>{}<textarea>the lazy dog</textarea></body></html>",
+        paragraph.repeat(50)
+    ))
+    .unwrap();
+    let mut surface = skia_safe::surfaces::raster_n32_premul((400, 6000)).unwrap();
+    let mut text_cache = TextCache::default();
+    let mut redraw = |text_cache: &mut TextCache| {
+        render(
+            surface.canvas(),
+            &html,
+            400,
+            6000,
+            &RenderOptions::default(),
+            text_cache,
+        )
+        .text_runs
+        .len()
+    };
+
+    // each distinct run is measured once, however many times it appears
+    let runs = redraw(&mut text_cache);
+    assert!(runs > 500);
+    let measurements = text_cache.measurements;
+    assert!(measurements < 20, "{} measurements", measurements);
+    assert_eq!(text_cache.misses, text_cache.entries.len());
+    assert!(text_cache.measures.len() >= text_cache.entries.len());
+
+    // and not again on the next redraw
+    redraw(&mut text_cache);
+    assert_eq!(text_cache.measurements, measurements);
+}
@@ -0,0 +1,207 @@
+//! End-to-end snapshots: fixture pages are served over HTTP, fetched, parsed and painted, and the
+//! result is compared against golden PNGs.
+//!
+//! Fixtures live in `tests/fixtures` and goldens in `tests/goldens`. A missing golden fails the
+//! test; run with `UPDATE_GOLDENS=1` to record all of them from the current output.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use skia_safe::{image::CachingHint, Data, Image, ImageInfo};
+
+use crate::render::{self, RenderOptions};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+
+/// A channel may differ by this much before the pixel counts as changed, to absorb font
+/// rasterization differences between platforms.
+const CHANNEL_TOLERANCE: u8 = 16;
+/// Fraction of pixels allowed to change before a snapshot fails.
+const PIXEL_TOLERANCE: f64 = 0.005;
+
+/// Snapshots are painted without antialiasing, so edges don't blend differently from run to run.
+fn crisp() -> RenderOptions<'static> {
+    RenderOptions {
+        anti_alias: false,
+        ..RenderOptions::default()
+    }
+}
+
+fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+/// Serves the files in `root` on an ephemeral localhost port and returns the `host:port`.
+fn serve(root: PathBuf) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+
+            let mut request_line = String::new();
+            let mut reader = BufReader::new(&stream);
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            // drain the headers
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                line.clear();
+            }
+
+            let path = request_line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or("/")
+                .trim_start_matches('/');
+            let (status, body) = match std::fs::read(root.join(path)) {
+                Ok(body) => ("200 OK", body),
+                Err(_) => ("404 Not Found", b"not found".to_vec()),
+            };
+
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    host
+}
+
+fn decode(png: &[u8]) -> (i32, i32, Vec<u8>) {
+    let image = Image::from_encoded(Data::new_copy(png)).unwrap();
+    let info = ImageInfo::new_n32_premul((image.width(), image.height()), None);
+    let mut pixels = vec![0u8; (image.width() * image.height() * 4) as usize];
+    assert!(image.read_pixels(
+        &info,
+        &mut pixels,
+        image.width() as usize * 4,
+        (0, 0),
+        CachingHint::Allow
+    ));
+
+    (image.width(), image.height(), pixels)
+}
+
+/// Fraction of pixels that differ by more than `CHANNEL_TOLERANCE` in any channel.
+fn changed_pixels(actual: &[u8], golden: &[u8]) -> f64 {
+    let changed = actual
+        .chunks(4)
+        .zip(golden.chunks(4))
+        .filter(|(a, g)| {
+            a.iter()
+                .zip(g.iter())
+                .any(|(a, g)| a.abs_diff(*g) > CHANNEL_TOLERANCE)
+        })
+        .count();
+
+    changed as f64 / (actual.len() / 4).max(1) as f64
+}
+
+#[tokio::test]
+async fn test_fixture_snapshots() {
+    let fixtures = manifest_path("tests/fixtures");
+    let goldens = manifest_path("tests/goldens");
+    let update = std::env::var_os("UPDATE_GOLDENS").is_some();
+    if update {
+        std::fs::create_dir_all(&goldens).unwrap();
+    }
+
+    let host = serve(fixtures.clone());
+
+    let mut names = std::fs::read_dir(&fixtures)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".html"))
+        .collect::<Vec<_>>();
+    names.sort();
+    assert!(!names.is_empty(), "no fixtures in {:?}", fixtures);
+
+    let mut failures = vec![];
+    for name in names {
+        let (status, body) = crate::fetch(crate::url::page_url(&host, &name), crate::MAX_PAGE_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(status, 200, "{}", name);
+
+        let html = crate::html::parse_html(body).unwrap();
+        let png = render::render_to_png(&html, WIDTH, HEIGHT, &crisp()).unwrap();
+
+        let golden_path = goldens.join(name.replace(".html", ".png"));
+        if update {
+            std::fs::write(&golden_path, png.as_bytes()).unwrap();
+            continue;
+        }
+        if !golden_path.exists() {
+            failures.push(format!("{}: no golden at {:?}", name, golden_path));
+            continue;
+        }
+
+        let actual = decode(png.as_bytes());
+        let golden = decode(&std::fs::read(&golden_path).unwrap());
+        let changed = if (actual.0, actual.1) == (golden.0, golden.1) {
+            changed_pixels(&actual.2, &golden.2)
+        } else {
+            1.0
+        };
+
+        if changed > PIXEL_TOLERANCE {
+            let actual_path = std::env::temp_dir().join(name.replace(".html", ".actual.png"));
+            std::fs::write(&actual_path, png.as_bytes()).unwrap();
+            failures.push(format!(
+                "{}: {:.2}% of pixels changed, output written to {:?}",
+                name,
+                changed * 100.0,
+                actual_path
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "snapshots differ (rerun with UPDATE_GOLDENS=1 to accept):\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_crisp_render_is_stable() {
+    for name in ["blocks.html", "text_and_links.html"] {
+        let source = std::fs::read_to_string(manifest_path("tests/fixtures").join(name)).unwrap();
+        let html = crate::html::parse_html(source).unwrap();
+        let render = || render::render_to_png(&html, WIDTH, HEIGHT, &crisp()).unwrap();
+
+        let (first, second) = (render(), render());
+        assert_eq!(
+            changed_pixels(&decode(first.as_bytes()).2, &decode(second.as_bytes()).2),
+            0.0,
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_changed_pixels() {
+    let golden = [0, 0, 0, 255, 255, 255, 255, 255];
+
+    assert_eq!(changed_pixels(&golden, &golden), 0.0);
+    assert_eq!(
+        changed_pixels(&[10, 0, 0, 255, 255, 255, 255, 255], &golden),
+        0.0
+    );
+    assert_eq!(
+        changed_pixels(&[0, 0, 0, 255, 0, 255, 255, 255], &golden),
+        0.5
+    );
+}
@@ -0,0 +1,348 @@
+use reqwest::Url;
+
+/// How an absolute URL can start. Anything else is a path on the local server.
+const ABSOLUTE_PREFIXES: &[&str] = &["http://", "https://", "//", "data:", "file:"];
+
+/// Whether `url` is absolute (`http://`, `https://`, protocol-relative `//host`, `data:` or
+/// `file:`) rather than a path relative to the local server.
+pub fn is_absolute_url(url: &str) -> bool {
+    ABSOLUTE_PREFIXES.iter().any(|prefix| {
+        url.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    })
+}
+
+/// Schemes of links that lead to no page to load: `javascript:` never runs in this browser, and
+/// `mailto:` and `tel:` are for other applications.
+const NON_PAGE_SCHEMES: &[&str] = &["javascript", "mailto", "tel"];
+
+/// The scheme of `href`, lowercased, if it is one of the links that lead to no page.
+pub fn non_page_scheme(href: &str) -> Option<&'static str> {
+    let (scheme, _) = href.trim_start().split_once(':')?;
+    NON_PAGE_SCHEMES
+        .iter()
+        .copied()
+        .find(|known| known.eq_ignore_ascii_case(scheme))
+}
+
+/// Parses an absolute URL, or `None` for a relative or malformed one. A protocol-relative URL
+/// gets `http:`, the local server's scheme.
+pub fn parse_url(url: &str) -> Option<Url> {
+    if !is_absolute_url(url) {
+        return None;
+    }
+
+    match url.strip_prefix("//") {
+        Some(rest) => Url::parse(&format!("http://{}", rest)).ok(),
+        None => Url::parse(url).ok(),
+    }
+}
+
+/// The URL `path` is loaded from: itself if absolute, else on `host`.
+pub fn page_url(host: &str, path: &str) -> String {
+    if is_absolute_url(path) {
+        parse_url(path).map_or_else(|| path.to_string(), String::from)
+    } else {
+        format!("http://{}/{}", host, path)
+    }
+}
+
+/// Resolves `href` against the document at `current`, a path on `host` or an absolute URL, by
+/// `Url::join`. `None` if either is malformed.
+pub fn resolve_url(host: &str, current: &str, href: &str) -> Option<Url> {
+    Url::parse(&page_url(host, current)).ok()?.join(href).ok()
+}
+
+/// `url` as the browser keeps it: a path relative to the root of the local server if it is on
+/// `host`, else the whole URL.
+pub fn local_path(host: &str, url: &Url) -> String {
+    let authority = match (url.host_str(), url.port()) {
+        (Some(name), Some(port)) => format!("{}:{}", name, port),
+        (name, None) => name.unwrap_or_default().to_string(),
+        (None, Some(_)) => String::new(),
+    };
+    if url.scheme() != "http" || authority != host {
+        return url.to_string();
+    }
+
+    let mut path = url.path().trim_start_matches('/').to_string();
+    if let Some(query) = url.query() {
+        path = format!("{}?{}", path, query);
+    }
+    if let Some(fragment) = url.fragment() {
+        path = format!("{}#{}", path, fragment);
+    }
+    path
+}
+
+/// Resolves a link, image or stylesheet `href` on the document at `current`: to a path on the
+/// local server if it stays on `host`, else to an absolute URL. A malformed `href` is kept as it
+/// is.
+pub fn resolve_path(host: &str, current: &str, href: &str) -> String {
+    resolve_url(host, current, href).map_or_else(|| href.to_string(), |url| local_path(host, &url))
+}
+
+/// What relative URLs on the document at `current` resolve against: its `<base href>`, itself
+/// resolved against the document, if it has one.
+pub fn base_path(host: &str, current: &str, base: Option<&str>) -> String {
+    match base {
+        Some(base) => resolve_path(host, current, base),
+        None => current.to_string(),
+    }
+}
+
+#[test]
+fn test_is_absolute_url() {
+    let cases = vec![
+        ("http://example.com/", true),
+        ("https://example.com/a.html", true),
+        ("HTTPS://example.com/", true),
+        ("//cdn.example.com/x.js", true),
+        ("data:text/html,<p>hi</p>", true),
+        ("file:///tmp/page.html", true),
+        ("page.html", false),
+        ("/abs/page.html", false),
+        ("localhost:8000/page.html", false),
+        ("#top", false),
+        ("mailto:someone@example.com", false),
+        ("http:", false),
+        ("", false),
+    ];
+
+    for (url, want) in cases {
+        assert_eq!(is_absolute_url(url), want, "{}", url);
+    }
+}
+
+#[test]
+fn test_non_page_scheme() {
+    let cases = vec![
+        ("javascript:void(0)", Some("javascript")),
+        (" JavaScript:alert(1)", Some("javascript")),
+        ("mailto:someone@example.com", Some("mailto")),
+        ("tel:+1-555-0100", Some("tel")),
+        ("https://example.com/", None),
+        ("page.html?next=javascript:x", None),
+        ("javascript", None),
+    ];
+
+    for (href, want) in cases {
+        assert_eq!(non_page_scheme(href), want, "{}", href);
+    }
+}
+
+#[test]
+fn test_parse_url() {
+    let parts = |url: &str| {
+        parse_url(url).map(|url| {
+            (
+                url.scheme().to_string(),
+                url.host_str().map(str::to_string),
+                url.path().to_string(),
+            )
+        })
+    };
+    let part = |scheme: &str, host: Option<&str>, path: &str| {
+        Some((
+            scheme.to_string(),
+            host.map(str::to_string),
+            path.to_string(),
+        ))
+    };
+
+    assert_eq!(
+        parts("http://localhost:8000/index.html"),
+        part("http", Some("localhost"), "/index.html")
+    );
+    assert_eq!(
+        parts("https://example.com"),
+        part("https", Some("example.com"), "/")
+    );
+    assert_eq!(
+        parts("//cdn.example.com/x.js"),
+        part("http", Some("cdn.example.com"), "/x.js")
+    );
+    assert_eq!(
+        parts("data:text/plain,hello"),
+        part("data", None, "text/plain,hello")
+    );
+    assert_eq!(
+        parts("file:///tmp/page.html"),
+        part("file", None, "/tmp/page.html")
+    );
+    assert_eq!(parts("page.html"), None);
+    assert_eq!(parts("/abs/page.html"), None);
+    assert_eq!(parts("https://"), None);
+
+    assert_eq!(
+        page_url("localhost:8000", "sub/page.html"),
+        "http://localhost:8000/sub/page.html"
+    );
+    assert_eq!(
+        page_url("localhost:8000", "https://example.com"),
+        "https://example.com/"
+    );
+    assert_eq!(
+        page_url("localhost:8000", "//example.com/a.html"),
+        "http://example.com/a.html"
+    );
+}
+
+#[test]
+fn test_base_path() {
+    let html = crate::html::parse_html(
+        r#"<html><head><base href="/sub/"></head><body><a href="a.html">a</a></body></html>"#
+            .to_string(),
+    )
+    .unwrap();
+    let base = base_path(
+        "localhost:8000",
+        "index.html",
+        html.head_metadata().base.as_deref(),
+    );
+    assert_eq!(
+        resolve_path("localhost:8000", &base, "a.html"),
+        "sub/a.html"
+    );
+
+    let cases = vec![
+        ("dir/page.html", None, "dir/page.html"),
+        ("dir/page.html", Some("other/"), "dir/other/"),
+        ("dir/page.html", Some("http://localhost:8000/abs/"), "abs/"),
+        ("dir/page.html", Some("http://localhost:8000"), ""),
+        (
+            "dir/page.html",
+            Some("https://example.com/abs/"),
+            "https://example.com/abs/",
+        ),
+        (
+            "https://example.com/dir/page.html",
+            Some("other/"),
+            "https://example.com/dir/other/",
+        ),
+        (
+            "https://example.com/dir/page.html",
+            Some("//cdn.example.com/"),
+            "https://cdn.example.com/",
+        ),
+        (
+            "dir/page.html",
+            Some("https://localhost:8000/abs/"),
+            "https://localhost:8000/abs/",
+        ),
+    ];
+
+    for (current, base, want) in cases {
+        assert_eq!(
+            base_path("localhost:8000", current, base),
+            want,
+            "{} {:?}",
+            current,
+            base
+        );
+    }
+}
+
+#[test]
+fn test_resolve_path() {
+    // every kind of reference against a document at the server root, one in a directory, and
+    // one on another host
+    let hrefs = [
+        "next.html",
+        "img/logo.png",
+        "../up.html",
+        "/assets/style.css",
+        "//cdn.example.com/x.js",
+        "https://other.org/a.html",
+        "?page=2",
+        "next.html#top",
+        "data:text/plain,hello",
+    ];
+    let cases = vec![
+        (
+            "index.html",
+            [
+                "next.html",
+                "img/logo.png",
+                "up.html",
+                "assets/style.css",
+                "http://cdn.example.com/x.js",
+                "https://other.org/a.html",
+                "index.html?page=2",
+                "next.html#top",
+                "data:text/plain,hello",
+            ],
+        ),
+        (
+            "sub/dir/page.html",
+            [
+                "sub/dir/next.html",
+                "sub/dir/img/logo.png",
+                "sub/up.html",
+                "assets/style.css",
+                "http://cdn.example.com/x.js",
+                "https://other.org/a.html",
+                "sub/dir/page.html?page=2",
+                "sub/dir/next.html#top",
+                "data:text/plain,hello",
+            ],
+        ),
+        (
+            "https://example.com/docs/page.html",
+            [
+                "https://example.com/docs/next.html",
+                "https://example.com/docs/img/logo.png",
+                "https://example.com/up.html",
+                "https://example.com/assets/style.css",
+                "https://cdn.example.com/x.js",
+                "https://other.org/a.html",
+                "https://example.com/docs/page.html?page=2",
+                "https://example.com/docs/next.html#top",
+                "data:text/plain,hello",
+            ],
+        ),
+    ];
+
+    for (current, wants) in cases {
+        for (href, want) in hrefs.iter().zip(wants) {
+            assert_eq!(
+                resolve_path("localhost:8000", current, href),
+                want,
+                "{} {}",
+                current,
+                href
+            );
+        }
+    }
+
+    let cases = vec![
+        ("", "link1.html", "link1.html"),
+        ("sub/page.html", "/next.html", "next.html"),
+        (
+            "https://example.com",
+            "a.html",
+            "https://example.com/a.html",
+        ),
+        (
+            "http://localhost:8000/sub/page.html",
+            "next.html",
+            "sub/next.html",
+        ),
+        (
+            "http://example.com/page.html",
+            "https://example.com/secure.html",
+            "https://example.com/secure.html",
+        ),
+        ("index.html", "http://[bad", "http://[bad"),
+    ];
+
+    for (current, href, want) in cases {
+        assert_eq!(
+            resolve_path("localhost:8000", current, href),
+            want,
+            "{} {}",
+            current,
+            href
+        );
+    }
+}